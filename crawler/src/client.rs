@@ -1,29 +1,141 @@
 //! Flaresolverr Client
 
 use crate::{
-    error::Error,
-    proxy::Proxy,
+    error::{Error, ErrorType},
+    layer::is_challenge,
+    proxy::{IpHistory, LeakGuard, Outcome, Proxy},
     request::{Request, RequestInternal, Session},
     response::{Response, Solution},
 };
+use reqwest::Url;
+use std::{
+    sync::{
+        Arc, Mutex, OnceLock,
+        atomic::{AtomicBool, AtomicU64, Ordering},
+    },
+    time::Duration,
+};
+use tokio::sync::{Mutex as AsyncMutex, Semaphore};
+
+/// Restarts allowed while the new exit IP is on the site's blocklist
+const MAX_RESTARTS: usize = 3;
+
+/// User agent for direct downloads
+const USER_AGENT: &str =
+    "Mozilla/5.0 (Windows NT 10.0; Win64; x64; rv:128.0) Gecko/20100101 Firefox/128.0";
+
+/// Time allowed for a direct download
+const DOWNLOAD_TIMEOUT: Duration = Duration::from_secs(60);
 
+/// Flaresolverr session owned by the pool
 #[derive(Clone, Debug)]
-pub struct Client {
+struct PooledSession {
+    id: String,
+    generation: u64,
+}
+
+/// Session checked out of the pool. Dropping it returns the session to the pool, so sessions of
+/// cancelled requests are still ended by [`Client::destroy_session`].
+#[derive(Debug)]
+struct SessionGuard {
+    client: Client,
+    session: Option<PooledSession>,
+}
+
+impl SessionGuard {
+    fn id(&self) -> &str {
+        self.session
+            .as_ref()
+            .map_or("", |session| session.id.as_str())
+    }
+
+    /// Ends the session instead of returning it to the pool
+    async fn discard(mut self) {
+        if let Some(session) = self.session.take() {
+            self.client.end_session(&session.id).await;
+        }
+    }
+}
+
+impl Drop for SessionGuard {
+    fn drop(&mut self) {
+        if let Some(session) = self.session.take() {
+            self.client.checkin(session);
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Inner {
     client: reqwest::Client,
+
+    /// Client for direct downloads (through the proxy, without the solver)
+    direct: OnceLock<reqwest::Client>,
     solver: String,
     proxy: Option<Proxy>,
-    session: Option<String>,
+    concurrency: usize,
+
+    /// Refuses the proxy if it exposes the direct IP
+    guard: Option<Arc<LeakGuard>>,
+
+    /// Set once the proxy was checked. Cleared after every restart.
+    verified: AtomicBool,
+
+    /// Exit IP outcomes per site
+    history: Option<Arc<IpHistory>>,
+
+    /// Current exit IP of the proxy (when tracking history)
+    exit_ip: Mutex<Option<String>>,
+
+    /// Site of the last request
+    last_site: Mutex<Option<String>>,
+
+    /// Limits the number of requests in flight
+    permits: Semaphore,
+
+    /// Idle sessions
+    sessions: Mutex<Vec<PooledSession>>,
+
+    /// Bumped every time the client recovers. Sessions from older generations are discarded.
+    generation: AtomicU64,
+
+    /// Serializes recovery so concurrent failures only restart the proxy once
+    recovery: AsyncMutex<()>,
+}
+
+/// Cheaply cloneable client. Clones share the same session pool and concurrency limit.
+#[derive(Clone, Debug)]
+pub struct Client {
+    inner: Arc<Inner>,
 }
 
 impl Client {
     /// Creates a new Client
-    pub(crate) fn new_internal(solver: String, proxy: Option<Proxy>) -> Client {
-        let client = reqwest::Client::new();
+    pub(crate) fn new_internal(
+        solver: String,
+        proxy: Option<Proxy>,
+        concurrency: usize,
+        guard: Option<Arc<LeakGuard>>,
+        history: Option<Arc<IpHistory>>,
+    ) -> Client {
+        let concurrency = concurrency.max(1);
         Client {
-            client,
-            solver,
-            proxy,
-            session: None,
+            inner: Arc::new(Inner {
+                client: reqwest::Client::new(),
+                direct: OnceLock::new(),
+                solver,
+                proxy,
+                concurrency,
+                guard,
+                verified: AtomicBool::new(false),
+                history,
+                exit_ip: Mutex::new(None),
+                last_site: Mutex::new(None),
+                permits: Semaphore::new(concurrency),
+                sessions: Mutex::new(Vec::new()),
+                generation: AtomicU64::new(0),
+                recovery: AsyncMutex::new(()),
+            }),
         }
     }
 
@@ -33,33 +145,266 @@ impl Client {
 
     /// Creates a new Client
     pub fn new(solver: &str) -> Client {
-        Client::new_internal(solver.into(), None)
+        Client::new_internal(solver.into(), None, 1, None, None)
     }
 
     /// Creates a new Client with a proxy configuration
     pub fn with_proxy(solver: &str, proxy: Proxy) -> Client {
-        Client::new_internal(solver.into(), Some(proxy))
+        Client::new_internal(solver.into(), Some(proxy), 1, None, None)
     }
 
     /// Get solver URL
     pub fn solver(&self) -> &str {
-        self.solver.as_ref()
+        self.inner.solver.as_ref()
     }
 
     /// Get proxy
     pub fn proxy(&self) -> Option<&Proxy> {
-        self.proxy.as_ref()
+        self.inner.proxy.as_ref()
+    }
+
+    /// Get the maximum number of concurrent requests
+    pub fn concurrency(&self) -> usize {
+        self.inner.concurrency
+    }
+
+    /// Get the number of idle sessions
+    pub fn sessions(&self) -> usize {
+        self.inner
+            .sessions
+            .lock()
+            .expect("session pool poisoned")
+            .len()
+    }
+
+    /// Starts a flaresolverr session and adds it to the pool
+    pub async fn create_session(&self) -> Result<(), Error> {
+        let session = self.new_session().await?;
+        self.checkin(session);
+        Ok(())
+    }
+
+    /// Ends every idle flaresolverr session
+    pub async fn destroy_session(&self) -> Result<(), Error> {
+        let sessions =
+            std::mem::take(&mut *self.inner.sessions.lock().expect("session pool poisoned"));
+        for session in sessions {
+            self.end_session(&session.id).await;
+        }
+        Ok(())
+    }
+
+    /// Processes the flaresolverr request
+    pub async fn request(&self, request: &Request) -> Result<Solution, Error> {
+        let _permit = self.inner.permits.acquire().await.map_err(Error::solver)?;
+        let session = self.checkout().await?;
+        let res = self.request_with_session(request, session.id()).await;
+        if res.is_err() {
+            session.discard().await;
+        }
+        self.record_outcome(request, &res);
+        res
+    }
+
+    /// Convenience function for the typical HTTP GET
+    pub async fn get(&self, url: &str) -> Result<Solution, Error> {
+        self.request(&Request::get(url).build()).await
+    }
+
+    /// Convenience function for the typical HTTP POST
+    pub async fn post(&self, url: &str, post_data: &[(&str, &str)]) -> Result<Solution, Error> {
+        self.request(&Request::post(url).post_data(post_data).build())
+            .await
+    }
+
+    /// Downloads binary content (images and the like) directly instead of through the solver.
+    /// Goes through the proxy when one is configured and counts against the concurrency limit.
+    pub async fn download(&self, url: &str, accept: &str) -> Result<Vec<u8>, Error> {
+        let _permit = self.inner.permits.acquire().await.map_err(Error::solver)?;
+        self.verify_proxy().await?;
+        let res = self
+            .direct()?
+            .get(url)
+            .header(reqwest::header::ACCEPT, accept)
+            .send()
+            .await?;
+        match res.status().as_u16() {
+            200 => Ok(res.bytes().await?.to_vec()),
+            status => Err(Error::solution(format!(
+                "{} returned HTTP status {}",
+                url, status
+            ))),
+        }
+    }
+
+    /// Attempt to recover by resetting the sessions (and reconnecting the VPN)
+    ///
+    /// When several requests fail at once, only the first caller restarts the proxy. The others
+    /// wait for it and return.
+    pub async fn recover(&self, seconds: u64) -> Result<(), Error> {
+        let generation = self.inner.generation.load(Ordering::SeqCst);
+        let _guard = self.inner.recovery.lock().await;
+        if self.inner.generation.load(Ordering::SeqCst) != generation {
+            log::debug!("already recovered");
+            return Ok(());
+        }
+        self.destroy_session().await?;
+        if let Some(proxy) = &self.inner.proxy {
+            self.inner.verified.store(false, Ordering::SeqCst);
+            proxy.restart(seconds).await?;
+            self.avoid_blocked_ip(proxy, seconds).await?;
+        }
+        self.inner.generation.fetch_add(1, Ordering::SeqCst);
+        self.create_session().await
+    }
+
+    /// Takes an idle session from the pool or creates a new one
+    async fn checkout(&self) -> Result<SessionGuard, Error> {
+        let session = self
+            .inner
+            .sessions
+            .lock()
+            .expect("session pool poisoned")
+            .pop();
+        let session = match session {
+            Some(session) => session,
+            None => self.new_session().await?,
+        };
+        Ok(SessionGuard {
+            client: self.clone(),
+            session: Some(session),
+        })
+    }
+
+    /// Returns a session to the pool, or ends it in the background if the client recovered in
+    /// the meantime
+    fn checkin(&self, session: PooledSession) {
+        if session.generation == self.inner.generation.load(Ordering::SeqCst) {
+            self.inner
+                .sessions
+                .lock()
+                .expect("session pool poisoned")
+                .push(session);
+            return;
+        }
+        match tokio::runtime::Handle::try_current() {
+            Ok(handle) => {
+                let client = self.clone();
+                handle.spawn(async move { client.end_session(&session.id).await });
+            }
+            Err(_) => log::warn!("unable to end session {}", session.id),
+        }
+    }
+
+    /// Client for direct downloads, built on first use
+    fn direct(&self) -> Result<&reqwest::Client, Error> {
+        if let Some(direct) = self.inner.direct.get() {
+            return Ok(direct);
+        }
+        let mut builder = reqwest::Client::builder()
+            .user_agent(USER_AGENT)
+            .timeout(DOWNLOAD_TIMEOUT);
+        if let Some(proxy) = &self.inner.proxy {
+            let mut direct = reqwest::Proxy::all(&proxy.url)?;
+            if let Some(username) = &proxy.username {
+                direct = direct.basic_auth(username, proxy.password.as_deref().unwrap_or(""));
+            }
+            builder = builder.proxy(direct);
+        }
+        let direct = builder.build()?;
+        Ok(self.inner.direct.get_or_init(|| direct))
     }
 
-    /// Get session
-    pub fn session(&self) -> Option<&str> {
-        self.session.as_deref()
+    /// Checks the proxy with the leak guard and notes its exit IP before it is used
+    async fn verify_proxy(&self) -> Result<(), Error> {
+        if let Some(proxy) = &self.inner.proxy
+            && !self.inner.verified.load(Ordering::SeqCst)
+        {
+            if let Some(guard) = &self.inner.guard {
+                guard.check(proxy).await?;
+            }
+            if self.inner.history.is_some() {
+                *self.inner.exit_ip.lock().expect("exit ip poisoned") = proxy.ip().await;
+            }
+            self.inner.verified.store(true, Ordering::SeqCst);
+        }
+        Ok(())
+    }
+
+    /// Restarts the proxy again while its new exit IP is blocked on the last site
+    async fn avoid_blocked_ip(&self, proxy: &Proxy, seconds: u64) -> Result<(), Error> {
+        let site = self.inner.last_site.lock().expect("site poisoned").clone();
+        let (history, site) = match (&self.inner.history, site) {
+            (Some(history), Some(site)) => (history, site),
+            _ => return Ok(()),
+        };
+        for _ in 0..MAX_RESTARTS {
+            match proxy.ip().await {
+                Some(ip) if history.is_blocked(&site, &ip) => {
+                    log::warn!("exit IP {} is blocked on {}, restarting again", ip, site);
+                    proxy.restart(seconds).await?;
+                }
+                _ => return Ok(()),
+            }
+        }
+        log::warn!("unable to find an exit IP that is not blocked on {}", site);
+        Ok(())
+    }
+
+    /// Notes how the site treated the current exit IP
+    fn record_outcome(&self, request: &Request, res: &Result<Solution, Error>) {
+        let history = match &self.inner.history {
+            Some(history) => history,
+            None => return,
+        };
+        let site = match Url::parse(&request.url) {
+            Ok(url) => match url.host_str() {
+                Some(host) => host.trim_start_matches("www.").to_string(),
+                None => return,
+            },
+            Err(_) => return,
+        };
+        *self.inner.last_site.lock().expect("site poisoned") = Some(site.clone());
+        let ip = match self.inner.exit_ip.lock().expect("exit ip poisoned").clone() {
+            Some(ip) => ip,
+            None => return,
+        };
+        let outcome = match res {
+            Ok(solution) => match solution.status {
+                403 if is_banned(&solution.response) => Some(Outcome::Banned),
+                403 => Some(Outcome::Forbidden),
+                429 => Some(Outcome::Banned),
+                _ if is_challenge(&solution.response) => Some(Outcome::Challenged),
+                200 => Some(Outcome::Ok),
+                _ => None,
+            },
+            Err(e) => match e.error_type {
+                ErrorType::Solution => Some(Outcome::Challenged),
+                _ => None,
+            },
+        };
+        if let Some(outcome) = outcome {
+            history.record(&site, &ip, outcome);
+            let history = history.clone();
+            tokio::task::spawn_blocking(move || {
+                if let Err(e) = history.save() {
+                    log::warn!("unable to save exit IP history: {}", e);
+                }
+            });
+        }
     }
 
-    /// Starts a flaresolverr session
-    pub async fn create_session(&mut self) -> Result<(), Error> {
-        let json = Session::create(self.proxy.as_ref());
-        let res = self.client.post(&self.solver).json(&json).send().await?;
+    async fn new_session(&self) -> Result<PooledSession, Error> {
+        self.verify_proxy().await?;
+        let generation = self.inner.generation.load(Ordering::SeqCst);
+        let json = Session::create(self.inner.proxy.as_ref());
+        let res = self
+            .inner
+            .client
+            .post(&self.inner.solver)
+            .json(&json)
+            .send()
+            .await?;
         log::debug!("solver response: {:?}", &res);
 
         // Parse JSON
@@ -68,8 +413,10 @@ impl Client {
             match res.session {
                 Some(session) => {
                     log::debug!("created session: {}", &session);
-                    self.session = Some(session);
-                    Ok(())
+                    Ok(PooledSession {
+                        id: session,
+                        generation,
+                    })
                 }
                 None => Err(Error::solution("no session in response")),
             }
@@ -79,25 +426,35 @@ impl Client {
         }
     }
 
-    /// Ends the flaresolverr session
-    pub async fn destroy_session(&mut self) -> Result<(), Error> {
-        if let Some(session) = &self.session {
-            let json = Session::destroy(session);
-            let _ = self.client.post(&self.solver).json(&json).send().await;
-            log::debug!("destroyed session: {}", &session);
-            self.session = None;
-        }
-        Ok(())
+    async fn end_session(&self, session: &str) {
+        let json = Session::destroy(session);
+        let _ = self
+            .inner
+            .client
+            .post(&self.inner.solver)
+            .json(&json)
+            .send()
+            .await;
+        log::debug!("destroyed session: {}", session);
     }
 
-    /// Processes the flaresolverr request
-    pub async fn request(&mut self, request: &Request) -> Result<Solution, Error> {
+    async fn request_with_session(
+        &self,
+        request: &Request,
+        session: &str,
+    ) -> Result<Solution, Error> {
         // Send HTTP Post
         let req = RequestInternal {
-            request: &request,
-            session: self.session.as_deref(),
+            request,
+            session: Some(session),
         };
-        let res = self.client.post(&self.solver).json(&req).send().await?;
+        let res = self
+            .inner
+            .client
+            .post(&self.inner.solver)
+            .json(&req)
+            .send()
+            .await?;
         log::debug!("solver response: {:?}", &res);
 
         // Parse JSON
@@ -112,32 +469,15 @@ impl Client {
             Err(Error::parse_solution_error(&res.message))
         }
     }
-
-    /// Convenience function for the typical HTTP GET
-    pub async fn get(&mut self, url: &str) -> Result<Solution, Error> {
-        self.request(&Request::get(url).build()).await
-    }
-
-    /// Convenience function for the typical HTTP POST
-    pub async fn post(&mut self, url: &str, post_data: &[(&str, &str)]) -> Result<Solution, Error> {
-        self.request(&Request::post(url).post_data(post_data).build())
-            .await
-    }
-
-    /// Attempt to recover by resetting the session (and reconnecting the VPN)
-    pub async fn recover(&mut self, seconds: u64) -> Result<(), Error> {
-        self.destroy_session().await?;
-        if let Some(proxy) = &self.proxy {
-            proxy.restart(seconds).await?;
-        }
-        self.create_session().await
-    }
 }
 
 #[derive(Clone, Debug)]
 pub struct ClientBuilder {
     solver: String,
     proxy: Option<Proxy>,
+    concurrency: usize,
+    guard: Option<Arc<LeakGuard>>,
+    history: Option<Arc<IpHistory>>,
 }
 
 impl ClientBuilder {
@@ -145,6 +485,9 @@ impl ClientBuilder {
         ClientBuilder {
             solver: solver.into(),
             proxy: None,
+            concurrency: 1,
+            guard: None,
+            history: None,
         }
     }
 
@@ -153,7 +496,35 @@ impl ClientBuilder {
         self
     }
 
+    pub fn concurrency(mut self, concurrency: usize) -> Self {
+        self.concurrency = concurrency;
+        self
+    }
+
+    /// Refuses the proxy unless the guard accepts it
+    pub fn leak_guard(mut self, guard: Arc<LeakGuard>) -> Self {
+        self.guard = Some(guard);
+        self
+    }
+
+    /// Tracks exit IP outcomes and avoids blocked exit IPs after restarts
+    pub fn ip_history(mut self, history: Arc<IpHistory>) -> Self {
+        self.history = Some(history);
+        self
+    }
+
     pub fn build(self) -> Client {
-        Client::new_internal(self.solver, self.proxy)
+        Client::new_internal(
+            self.solver,
+            self.proxy,
+            self.concurrency,
+            self.guard,
+            self.history,
+        )
     }
 }
+
+/// Returns true if the page is an explicit block (Cloudflare 1020)
+fn is_banned(html: &str) -> bool {
+    html.contains("Sorry, you have been blocked") || html.contains("error code: 1020")
+}
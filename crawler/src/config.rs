@@ -3,7 +3,7 @@
 use crate::{
     client::Client,
     error::Error,
-    proxy::{Api, Proxy},
+    proxy::{Api, IpHistory, LeakGuard, Proxy},
 };
 
 #[cfg(feature = "cache")]
@@ -11,7 +11,11 @@ use crate::cache::Cache;
 
 use config::{File, FileFormat};
 use serde::Deserialize;
-use std::collections::{hash_map::Keys, HashMap};
+use std::{
+    collections::{hash_map::Keys, HashMap},
+    sync::{Arc, OnceLock},
+    time::Duration,
+};
 
 #[derive(Clone, Debug, Deserialize)]
 pub struct Config {
@@ -25,9 +29,40 @@ pub struct Config {
     /// Proxy name [default: disabled]
     proxy: Option<String>,
 
+    /// Concurrent requests per client [default: 1]
+    #[serde(default = "concurrency_default")]
+    concurrency: usize,
+
+    /// Minimum milliseconds between requests [default: 0]
+    #[serde(default)]
+    rate_limit: u64,
+
+    /// Seconds to wait for a proxy to recover between attempts [default: 60]
+    #[serde(default = "recover_timeout_default")]
+    recover_timeout: u64,
+
+    /// Refuse proxies exposing the direct IP or without an `api` to check it [default: true]
+    #[serde(default = "leak_guard_default")]
+    leak_guard: bool,
+
+    /// Direct public IP lookup endpoint [default: https://api.ipify.org]
+    #[serde(default = "ip_lookup_default")]
+    ip_lookup: String,
+
+    /// Exit IP history file [default: disabled]
+    ip_history: Option<String>,
+
     /// Map of proxies
     #[serde(default)]
     proxies: HashMap<String, ProxyConfig>,
+
+    /// Guard shared by every client built from this configuration
+    #[serde(skip)]
+    guard: OnceLock<Arc<LeakGuard>>,
+
+    /// History shared by every client built from this configuration
+    #[serde(skip)]
+    history: OnceLock<Arc<IpHistory>>,
 }
 
 impl Default for Config {
@@ -36,7 +71,15 @@ impl Default for Config {
             solver: solver_default(),
             cache: None,
             proxy: None,
+            concurrency: concurrency_default(),
+            rate_limit: 0,
+            recover_timeout: recover_timeout_default(),
+            leak_guard: leak_guard_default(),
+            ip_lookup: ip_lookup_default(),
+            ip_history: None,
             proxies: HashMap::default(),
+            guard: OnceLock::new(),
+            history: OnceLock::new(),
         }
     }
 }
@@ -53,6 +96,10 @@ impl Config {
     /// solver = "http://localhost:8191/v1"
     /// cache = "/path/to/cache_dir"
     /// proxy = "proxy2"
+    /// concurrency = 2
+    /// rate_limit = 500
+    /// ip_lookup = "https://api.ipify.org"
+    /// ip_history = "/path/to/ip-history.json"
     ///
     /// [proxies]
     /// proxy1 = { url = "http://localhost:9000" }
@@ -88,6 +135,36 @@ impl Config {
         self.proxy.as_deref()
     }
 
+    /// Returns the number of concurrent requests per client
+    pub fn concurrency(&self) -> usize {
+        self.concurrency
+    }
+
+    /// Returns the minimum time between requests
+    pub fn rate_limit(&self) -> Duration {
+        Duration::from_millis(self.rate_limit)
+    }
+
+    /// Returns the seconds to wait for a proxy to recover
+    pub fn recover_timeout(&self) -> u64 {
+        self.recover_timeout
+    }
+
+    /// Returns true if proxies are checked for IP leaks
+    pub fn leak_guard(&self) -> bool {
+        self.leak_guard
+    }
+
+    /// Returns a reference to the direct public IP lookup endpoint
+    pub fn ip_lookup(&self) -> &str {
+        self.ip_lookup.as_str()
+    }
+
+    /// Returns a reference to the exit IP history file
+    pub fn ip_history(&self) -> Option<&str> {
+        self.ip_history.as_deref()
+    }
+
     /// Returns an iterator to the proxy names
     pub fn proxies(&self) -> Keys<'_, String, ProxyConfig> {
         self.proxies.keys()
@@ -102,6 +179,36 @@ impl Config {
         }
     }
 
+    /// Loads the exit IP history shared by every client
+    pub fn to_ip_history(&self) -> Result<Option<Arc<IpHistory>>, Error> {
+        let path = match self.ip_history.as_deref() {
+            Some(path) => path,
+            None => return Ok(None),
+        };
+        if let Some(history) = self.history.get() {
+            return Ok(Some(history.clone()));
+        }
+        let history = Arc::new(IpHistory::load(path)?);
+        Ok(Some(self.history.get_or_init(|| history).clone()))
+    }
+
+    /// Returns a copy using the provided proxy. Clients built from the copy share the leak guard
+    /// and the IP history of this configuration.
+    pub fn with_proxy(&self, proxy: &str) -> Result<Config, Error> {
+        if !self.proxies.contains_key(proxy) {
+            return Err(Error::config(format!("invalid proxy `{}`", proxy)));
+        }
+        if self.leak_guard {
+            self.guard
+                .get_or_init(|| Arc::new(LeakGuard::new(&self.ip_lookup)));
+        }
+        self.to_ip_history()?;
+        Ok(Config {
+            proxy: Some(proxy.into()),
+            ..self.clone()
+        })
+    }
+
     /// Builds a `Client`
     pub fn to_client(&self) -> Result<Client, Error> {
         self.build_client(self.proxy.as_deref())
@@ -114,11 +221,29 @@ impl Config {
 
     /// Builds a `Client` with the provided proxy
     fn build_client(&self, proxy: Option<&str>) -> Result<Client, Error> {
-        let mut client = Client::builder(&self.solver);
+        let mut client = Client::builder(&self.solver).concurrency(self.concurrency);
         if let Some(proxy) = proxy {
-            match self.proxies.get(proxy) {
-                Some(pconf) => client = client.proxy(pconf.to_proxy()?),
+            let pconf = match self.proxies.get(proxy) {
+                Some(pconf) => pconf,
                 None => return Err(Error::config(format!("invalid proxy `{}`", proxy))),
+            };
+            client = client.proxy(pconf.to_proxy()?);
+
+            if self.leak_guard {
+                // The guard needs the proxy API to find the proxy's public IP
+                if pconf.api.is_none() {
+                    return Err(Error::config(format!(
+                        "proxy `{}` has no `api` to check it for IP leaks, set `leak_guard = false` to use it unchecked",
+                        proxy
+                    )));
+                }
+                let guard = self
+                    .guard
+                    .get_or_init(|| Arc::new(LeakGuard::new(&self.ip_lookup)));
+                client = client.leak_guard(guard.clone());
+            }
+            if let Some(history) = self.to_ip_history()? {
+                client = client.ip_history(history);
             }
         }
         Ok(client.build())
@@ -178,6 +303,48 @@ impl ConfigBuilder {
         self
     }
 
+    pub fn concurrency(mut self, concurrency: Option<usize>) -> Self {
+        if let Some(concurrency) = concurrency {
+            self.inner.concurrency = concurrency;
+        }
+        self
+    }
+
+    pub fn rate_limit(mut self, rate_limit: Option<u64>) -> Self {
+        if let Some(rate_limit) = rate_limit {
+            self.inner.rate_limit = rate_limit;
+        }
+        self
+    }
+
+    pub fn recover_timeout(mut self, recover_timeout: Option<u64>) -> Self {
+        if let Some(recover_timeout) = recover_timeout {
+            self.inner.recover_timeout = recover_timeout;
+        }
+        self
+    }
+
+    pub fn disable_leak_guard(mut self, disable_leak_guard: bool) -> Self {
+        if disable_leak_guard {
+            self.inner.leak_guard = false;
+        }
+        self
+    }
+
+    pub fn ip_lookup(mut self, ip_lookup: Option<String>) -> Self {
+        if let Some(ip_lookup) = ip_lookup {
+            self.inner.ip_lookup = ip_lookup;
+        }
+        self
+    }
+
+    pub fn ip_history(mut self, ip_history: Option<String>) -> Self {
+        if let Some(ip_history) = ip_history {
+            self.inner.ip_history = Some(ip_history);
+        }
+        self
+    }
+
     pub fn build(self) -> Config {
         self.inner
     }
@@ -188,6 +355,24 @@ fn solver_default() -> String {
     "http://localhost:8191/v1".into()
 }
 
+/// Default concurrency
+fn concurrency_default() -> usize {
+    1
+}
+
+fn recover_timeout_default() -> u64 {
+    60
+}
+
+fn leak_guard_default() -> bool {
+    true
+}
+
+/// Default direct public IP lookup endpoint
+fn ip_lookup_default() -> String {
+    "https://api.ipify.org".into()
+}
+
 #[derive(Clone, Debug, Deserialize)]
 pub struct ProxyConfig {
     /// Proxy URL
@@ -13,6 +13,9 @@ pub enum ErrorType {
     /// JSON parsing errors
     Json,
 
+    /// Proxy refused by the leak guard
+    Leak,
+
     /// Proxy errors
     Proxy,
 
@@ -29,6 +32,7 @@ impl fmt::Display for ErrorType {
             ErrorType::Config => f.write_str("config"),
             ErrorType::Io => f.write_str("io"),
             ErrorType::Json => f.write_str("json"),
+            ErrorType::Leak => f.write_str("leak"),
             ErrorType::Proxy => f.write_str("proxy"),
             ErrorType::Solution => f.write_str("solution"),
             ErrorType::Solver => f.write_str("solver"),
@@ -113,6 +117,14 @@ impl Error {
         }
     }
 
+    pub fn leak(msg: impl fmt::Display) -> Error {
+        Error {
+            error_type: ErrorType::Leak,
+            fatal: true,
+            message: format!("{}", msg),
+        }
+    }
+
     pub fn proxy(msg: impl fmt::Display) -> Error {
         Error {
             error_type: ErrorType::Proxy,
@@ -0,0 +1,74 @@
+//! Cache layer
+
+use crate::{
+    cache::Cache,
+    error::Error,
+    layer::{Fetch, Layer},
+    request::Request,
+    response::Solution,
+};
+use async_trait::async_trait;
+use serde_json::Value;
+
+/// Serves successful responses from the cache and stores new ones. Cached pages failing the
+/// request's validator are fetched again.
+#[derive(Clone, Debug)]
+pub struct CacheLayer {
+    cache: Cache,
+}
+
+impl CacheLayer {
+    pub fn new(cache: Cache) -> Self {
+        CacheLayer { cache }
+    }
+}
+
+impl<F: Fetch> Layer<F> for CacheLayer {
+    type Fetch = CacheFetch<F>;
+
+    fn layer(&self, inner: F) -> Self::Fetch {
+        CacheFetch {
+            inner,
+            cache: self.cache.clone(),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct CacheFetch<F> {
+    inner: F,
+    cache: Cache,
+}
+
+#[async_trait]
+impl<F: Fetch> Fetch for CacheFetch<F> {
+    async fn fetch(&self, request: &Request) -> Result<Solution, Error> {
+        let url = request.url.as_str();
+        if let Some(response) = self.cache.get(url)?
+            && request.is_valid(&response)
+        {
+            log::debug!("{} found in cache", url);
+            return Ok(Solution {
+                url: url.into(),
+                status: 200,
+                headers: Value::Null,
+                response,
+                cookies: Value::Null,
+                user_agent: String::new(),
+            });
+        }
+        log::debug!("{} not found in cache", url);
+        let solution = self.inner.fetch(request).await?;
+        if solution.status == 200 {
+            self.cache.insert(url, solution.response.as_bytes())?;
+        }
+        Ok(solution)
+    }
+
+    async fn recover(&self, seconds: u64) -> Result<(), Error> {
+        self.inner.recover(seconds).await
+    }
+    async fn download(&self, url: &str, accept: &str) -> Result<Vec<u8>, Error> {
+        self.inner.download(url, accept).await
+    }
+}
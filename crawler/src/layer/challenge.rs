@@ -0,0 +1,74 @@
+//! Challenge detection layer
+
+use crate::{
+    error::Error,
+    layer::{Fetch, Layer},
+    request::Request,
+    response::Solution,
+};
+use async_trait::async_trait;
+
+/// Markers of a challenge or block page that slipped through the solver
+static CHALLENGE_MARKERS: &[&str] = &[
+    "<title>Just a moment...</title>",
+    "<title>Attention Required! | Cloudflare</title>",
+    "challenges.cloudflare.com/turnstile",
+    "id=\"challenge-form\"",
+    "cf-error-details",
+];
+
+/// Turns challenge pages and responses failing the request's validator into recoverable errors
+/// so an outer retry layer can try again
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ChallengeLayer;
+
+impl ChallengeLayer {
+    pub fn new() -> Self {
+        ChallengeLayer
+    }
+}
+
+impl<F: Fetch> Layer<F> for ChallengeLayer {
+    type Fetch = ChallengeFetch<F>;
+
+    fn layer(&self, inner: F) -> Self::Fetch {
+        ChallengeFetch { inner }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct ChallengeFetch<F> {
+    inner: F,
+}
+
+/// Returns true if the response looks like a challenge page
+pub fn is_challenge(html: &str) -> bool {
+    CHALLENGE_MARKERS.iter().any(|marker| html.contains(marker))
+}
+
+#[async_trait]
+impl<F: Fetch> Fetch for ChallengeFetch<F> {
+    async fn fetch(&self, request: &Request) -> Result<Solution, Error> {
+        let solution = self.inner.fetch(request).await?;
+        if is_challenge(&solution.response) {
+            Err(Error::solution(format!(
+                "challenge page returned for {} (HTTP status {})",
+                request.url, solution.status
+            )))
+        } else if !request.is_valid(&solution.response) {
+            Err(Error::solution(format!(
+                "unexpected page returned for {} (HTTP status {})",
+                request.url, solution.status
+            )))
+        } else {
+            Ok(solution)
+        }
+    }
+
+    async fn recover(&self, seconds: u64) -> Result<(), Error> {
+        self.inner.recover(seconds).await
+    }
+    async fn download(&self, url: &str, accept: &str) -> Result<Vec<u8>, Error> {
+        self.inner.download(url, accept).await
+    }
+}
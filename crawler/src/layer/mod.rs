@@ -0,0 +1,118 @@
+//! Composable client layers
+//!
+//! Every concern around a request (caching, retries, rate limiting, tracing, challenge detection)
+//! is a `Layer` wrapping a `Fetch`. Applications choose and order the layers with a `Stack`. The
+//! first layer added is the innermost one.
+//!
+//! ```ignore
+//! let fetcher = Stack::new(client)
+//!     .layer(ChallengeLayer::new())
+//!     .layer(RetryLayer::new(5))
+//!     .layer(TraceLayer::new())
+//!     .boxed();
+//! ```
+
+use crate::{client::Client, error::Error, request::Request, response::Solution};
+use async_trait::async_trait;
+use std::sync::Arc;
+
+#[cfg(feature = "cache")]
+mod cache;
+mod challenge;
+mod rate_limit;
+mod retry;
+mod trace;
+
+#[cfg(feature = "cache")]
+pub use cache::{CacheFetch, CacheLayer};
+pub use challenge::{ChallengeFetch, ChallengeLayer, is_challenge};
+pub use rate_limit::{RateLimitFetch, RateLimitLayer};
+pub use retry::{RetryFetch, RetryLayer};
+pub use trace::{TraceFetch, TraceLayer};
+
+/// Turns a `Request` into a `Solution`
+#[async_trait]
+pub trait Fetch: Send + Sync {
+    /// Processes the request
+    async fn fetch(&self, request: &Request) -> Result<Solution, Error>;
+
+    /// Attempts to recover after a failure. Layers forward this to the base fetcher.
+    async fn recover(&self, seconds: u64) -> Result<(), Error>;
+
+    /// Downloads binary content (images and the like) without the solver
+    async fn download(&self, url: &str, accept: &str) -> Result<Vec<u8>, Error>;
+}
+
+/// Wraps a `Fetch` with extra behavior
+pub trait Layer<F> {
+    type Fetch: Fetch;
+
+    /// Wraps the inner fetcher
+    fn layer(&self, inner: F) -> Self::Fetch;
+}
+
+/// Type-erased fetcher
+pub type BoxFetch = Arc<dyn Fetch>;
+
+#[async_trait]
+impl Fetch for Client {
+    async fn fetch(&self, request: &Request) -> Result<Solution, Error> {
+        self.request(request).await
+    }
+
+    async fn recover(&self, seconds: u64) -> Result<(), Error> {
+        Client::recover(self, seconds).await
+    }
+
+    async fn download(&self, url: &str, accept: &str) -> Result<Vec<u8>, Error> {
+        Client::download(self, url, accept).await
+    }
+}
+
+#[async_trait]
+impl<F: Fetch + ?Sized> Fetch for Arc<F> {
+    async fn fetch(&self, request: &Request) -> Result<Solution, Error> {
+        self.as_ref().fetch(request).await
+    }
+
+    async fn recover(&self, seconds: u64) -> Result<(), Error> {
+        self.as_ref().recover(seconds).await
+    }
+
+    async fn download(&self, url: &str, accept: &str) -> Result<Vec<u8>, Error> {
+        self.as_ref().download(url, accept).await
+    }
+}
+
+/// Builds a stack of layers around a base fetcher
+#[derive(Clone, Debug)]
+pub struct Stack<F> {
+    inner: F,
+}
+
+impl<F: Fetch> Stack<F> {
+    /// Starts a stack with the base fetcher (usually a `Client`)
+    pub fn new(inner: F) -> Self {
+        Stack { inner }
+    }
+
+    /// Wraps the current stack with the layer
+    pub fn layer<L: Layer<F>>(self, layer: L) -> Stack<L::Fetch> {
+        Stack {
+            inner: layer.layer(self.inner),
+        }
+    }
+
+    /// Returns the fetcher
+    pub fn build(self) -> F {
+        self.inner
+    }
+
+    /// Returns the type-erased fetcher
+    pub fn boxed(self) -> BoxFetch
+    where
+        F: 'static,
+    {
+        Arc::new(self.inner)
+    }
+}
@@ -0,0 +1,78 @@
+//! Rate limit layer
+
+use crate::{
+    error::Error,
+    layer::{Fetch, Layer},
+    request::Request,
+    response::Solution,
+};
+use async_trait::async_trait;
+use std::{sync::Arc, time::Duration};
+use tokio::{
+    sync::Mutex,
+    time::{Instant, sleep_until},
+};
+
+/// Spaces requests out by a minimum interval. A zero interval disables the limit.
+#[derive(Clone, Copy, Debug)]
+pub struct RateLimitLayer {
+    interval: Duration,
+}
+
+impl RateLimitLayer {
+    /// Creates a rate limit layer with the minimum interval between requests
+    pub fn new(interval: Duration) -> Self {
+        RateLimitLayer { interval }
+    }
+}
+
+impl<F: Fetch> Layer<F> for RateLimitLayer {
+    type Fetch = RateLimitFetch<F>;
+
+    fn layer(&self, inner: F) -> Self::Fetch {
+        RateLimitFetch {
+            inner,
+            interval: self.interval,
+            next: Arc::new(Mutex::new(None)),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct RateLimitFetch<F> {
+    inner: F,
+    interval: Duration,
+
+    /// Earliest time the next request may start
+    next: Arc<Mutex<Option<Instant>>>,
+}
+
+impl<F> RateLimitFetch<F> {
+    /// Waits for the next slot
+    async fn wait(&self) {
+        if !self.interval.is_zero() {
+            let mut next = self.next.lock().await;
+            if let Some(instant) = *next {
+                sleep_until(instant).await;
+            }
+            *next = Some(Instant::now() + self.interval);
+        }
+    }
+}
+
+#[async_trait]
+impl<F: Fetch> Fetch for RateLimitFetch<F> {
+    async fn fetch(&self, request: &Request) -> Result<Solution, Error> {
+        self.wait().await;
+        self.inner.fetch(request).await
+    }
+
+    async fn recover(&self, seconds: u64) -> Result<(), Error> {
+        self.inner.recover(seconds).await
+    }
+
+    async fn download(&self, url: &str, accept: &str) -> Result<Vec<u8>, Error> {
+        self.wait().await;
+        self.inner.download(url, accept).await
+    }
+}
@@ -0,0 +1,85 @@
+//! Retry layer
+
+use crate::{
+    error::Error,
+    layer::{Fetch, Layer},
+    request::Request,
+    response::Solution,
+};
+use async_trait::async_trait;
+
+/// Retries non-fatal errors, recovering the base fetcher between attempts
+#[derive(Clone, Copy, Debug)]
+pub struct RetryLayer {
+    attempts: usize,
+    recover_timeout: u64,
+}
+
+impl RetryLayer {
+    /// Creates a retry layer with the number of attempts
+    pub fn new(attempts: usize) -> Self {
+        RetryLayer {
+            attempts,
+            recover_timeout: 60,
+        }
+    }
+
+    /// Seconds to wait for recovery (proxy restarts) [default: 60]
+    pub fn recover_timeout(mut self, seconds: u64) -> Self {
+        self.recover_timeout = seconds;
+        self
+    }
+}
+
+impl<F: Fetch> Layer<F> for RetryLayer {
+    type Fetch = RetryFetch<F>;
+
+    fn layer(&self, inner: F) -> Self::Fetch {
+        RetryFetch {
+            inner,
+            attempts: self.attempts,
+            recover_timeout: self.recover_timeout,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct RetryFetch<F> {
+    inner: F,
+    attempts: usize,
+    recover_timeout: u64,
+}
+
+#[async_trait]
+impl<F: Fetch> Fetch for RetryFetch<F> {
+    async fn fetch(&self, request: &Request) -> Result<Solution, Error> {
+        let mut attempts = 0;
+        loop {
+            match self.inner.fetch(request).await {
+                Ok(res) => return Ok(res),
+                Err(mut e) => match e.fatal {
+                    true => {
+                        log::error!("fatal: {}", e);
+                        return Err(e);
+                    }
+                    false => {
+                        attempts += 1;
+                        log::error!("({}/{}) attempts: {}", attempts, self.attempts, e);
+                        if attempts >= self.attempts {
+                            e.fatal = true;
+                            return Err(e);
+                        }
+                        self.inner.recover(self.recover_timeout).await?;
+                    }
+                },
+            }
+        }
+    }
+
+    async fn recover(&self, seconds: u64) -> Result<(), Error> {
+        self.inner.recover(seconds).await
+    }
+    async fn download(&self, url: &str, accept: &str) -> Result<Vec<u8>, Error> {
+        self.inner.download(url, accept).await
+    }
+}
@@ -0,0 +1,73 @@
+//! Trace layer
+
+use crate::{
+    error::Error,
+    layer::{Fetch, Layer},
+    request::Request,
+    response::Solution,
+};
+use async_trait::async_trait;
+use std::time::Instant;
+
+/// Logs every request with its outcome and duration
+#[derive(Clone, Copy, Debug, Default)]
+pub struct TraceLayer;
+
+impl TraceLayer {
+    pub fn new() -> Self {
+        TraceLayer
+    }
+}
+
+impl<F: Fetch> Layer<F> for TraceLayer {
+    type Fetch = TraceFetch<F>;
+
+    fn layer(&self, inner: F) -> Self::Fetch {
+        TraceFetch { inner }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct TraceFetch<F> {
+    inner: F,
+}
+
+#[async_trait]
+impl<F: Fetch> Fetch for TraceFetch<F> {
+    async fn fetch(&self, request: &Request) -> Result<Solution, Error> {
+        log::debug!("{} {}", request.cmd, request.url);
+        let start = Instant::now();
+        let res = self.inner.fetch(request).await;
+        let elapsed = start.elapsed().as_millis();
+        match &res {
+            Ok(solution) => log::debug!(
+                "{} {} -> {} ({} ms)",
+                request.cmd,
+                request.url,
+                solution.status,
+                elapsed
+            ),
+            Err(e) => log::debug!("{} {} -> {} ({} ms)", request.cmd, request.url, e, elapsed),
+        }
+        res
+    }
+
+    async fn recover(&self, seconds: u64) -> Result<(), Error> {
+        log::debug!("recovering");
+        let start = Instant::now();
+        let res = self.inner.recover(seconds).await;
+        log::debug!("recovered ({} ms)", start.elapsed().as_millis());
+        res
+    }
+    async fn download(&self, url: &str, accept: &str) -> Result<Vec<u8>, Error> {
+        log::debug!("DOWNLOAD {}", url);
+        let start = Instant::now();
+        let res = self.inner.download(url, accept).await;
+        let elapsed = start.elapsed().as_millis();
+        match &res {
+            Ok(data) => log::debug!("DOWNLOAD {} -> {} bytes ({} ms)", url, data.len(), elapsed),
+            Err(e) => log::debug!("DOWNLOAD {} -> {} ({} ms)", url, e, elapsed),
+        }
+        res
+    }
+}
@@ -7,10 +7,11 @@ mod response;
 
 pub use client::{Client, ClientBuilder};
 pub use error::{Error, ErrorType};
-pub use request::{Cookie, Request, RequestBuilder, WaitFor};
+pub use request::{Cookie, Request, RequestBuilder, Validator, WaitFor};
 pub use reqwest::Url;
 pub use response::Solution;
 
+pub mod layer;
 pub mod proxy;
 
 #[cfg(feature = "cache")]
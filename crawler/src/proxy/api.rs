@@ -7,7 +7,7 @@ use crate::{
 use reqwest::Client;
 use serde_json::{Map, Value};
 use std::{fmt, time::Duration};
-use tokio::time::timeout;
+use tokio::time::{sleep, timeout};
 
 #[derive(Clone, Debug)]
 pub struct Api {
@@ -85,14 +85,14 @@ impl Api {
     /// Restarts the proxy. Can timeout.
     pub async fn restart(&self, seconds: u64) -> Result<(), Error> {
         let _ = self.put_state("stopped").await;
-        std::thread::sleep(Duration::from_millis(1000));
+        sleep(Duration::from_millis(1000)).await;
         self.wait_for_status(ProxyStatus::Stopped, seconds).await?;
         let _ = self.put_state("running").await;
-        std::thread::sleep(Duration::from_millis(1000));
+        sleep(Duration::from_millis(1000)).await;
         self.wait_for_status(ProxyStatus::Running, seconds).await?;
-        std::thread::sleep(Duration::from_millis(1000));
+        sleep(Duration::from_millis(1000)).await;
         self.wait_for_ip(seconds).await?;
-        std::thread::sleep(Duration::from_millis(1000));
+        sleep(Duration::from_millis(1000)).await;
         Ok(())
     }
 
@@ -106,7 +106,7 @@ impl Api {
                         return;
                     }
                 }
-                std::thread::sleep(Duration::from_millis(1000));
+                sleep(Duration::from_millis(1000)).await;
             }
         })
         .await
@@ -124,7 +124,7 @@ impl Api {
                     log::debug!("public IP: {}", ip);
                     return;
                 }
-                std::thread::sleep(Duration::from_millis(1000));
+                sleep(Duration::from_millis(1000)).await;
             }
         })
         .await
@@ -0,0 +1,105 @@
+//! IP leak guard
+//!
+//! A VPN container that falls back to the host network silently exposes the direct IP. The guard
+//! looks up the direct public IP once and refuses any proxy reporting the same address.
+
+use crate::{error::Error, proxy::Proxy};
+use serde_json::Value;
+use std::time::Duration;
+use tokio::sync::OnceCell;
+
+/// Time allowed for the direct IP lookup
+const LOOKUP_TIMEOUT: Duration = Duration::from_secs(30);
+
+#[derive(Debug)]
+pub struct LeakGuard {
+    /// Public IP lookup endpoint (plain text or JSON with `ip`/`public_ip`)
+    lookup: String,
+
+    /// Direct public IP, looked up once
+    direct: OnceCell<String>,
+}
+
+impl LeakGuard {
+    /// Creates a guard using the lookup endpoint
+    pub fn new(lookup: &str) -> Self {
+        LeakGuard {
+            lookup: lookup.into(),
+            direct: OnceCell::new(),
+        }
+    }
+
+    /// Returns a reference to the lookup endpoint
+    pub fn lookup(&self) -> &str {
+        self.lookup.as_str()
+    }
+
+    /// Returns the direct public IP, bypassing every proxy
+    pub async fn direct_ip(&self) -> Result<&str, Error> {
+        let ip = self
+            .direct
+            .get_or_try_init(|| async {
+                let client = reqwest::Client::builder()
+                    .no_proxy()
+                    .timeout(LOOKUP_TIMEOUT)
+                    .build()
+                    .map_err(Error::leak)?;
+                let res = client
+                    .get(&self.lookup)
+                    .send()
+                    .await
+                    .and_then(|res| res.error_for_status())
+                    .map_err(Error::leak)?
+                    .text()
+                    .await
+                    .map_err(Error::leak)?;
+                let ip = parse_ip(&res).ok_or(Error::leak(format!(
+                    "no IP in lookup response: {}",
+                    res.trim()
+                )))?;
+                log::debug!("direct public IP: {}", ip);
+                Ok::<String, Error>(ip)
+            })
+            .await?;
+        Ok(ip.as_str())
+    }
+
+    /// Refuses the proxy if its public IP matches the direct one or either lookup fails
+    pub async fn check(&self, proxy: &Proxy) -> Result<(), Error> {
+        let direct = self
+            .direct_ip()
+            .await
+            .map_err(|e| Error::leak(format!("refusing proxy {}: {}", proxy.url, e.message)))?;
+        match proxy.ip().await {
+            Some(ip) if ip == direct => Err(Error::leak(format!(
+                "refusing proxy {}: it exposes the direct IP {}",
+                proxy.url, ip
+            ))),
+            Some(ip) => {
+                log::debug!("proxy {} public IP: {}", proxy.url, ip);
+                Ok(())
+            }
+            None => Err(Error::leak(format!(
+                "refusing proxy {}: unable to determine its public IP",
+                proxy.url
+            ))),
+        }
+    }
+}
+
+/// Reads the IP from a plain text or JSON lookup response
+fn parse_ip(res: &str) -> Option<String> {
+    let res = res.trim();
+    let ip = match serde_json::from_str::<Value>(res) {
+        Ok(json) => json["ip"]
+            .as_str()
+            .or(json["public_ip"].as_str())?
+            .trim()
+            .to_string(),
+        Err(_) => res.to_string(),
+    };
+    match ip.is_empty() || ip.contains(char::is_whitespace) {
+        true => None,
+        false => Some(ip),
+    }
+}
@@ -0,0 +1,164 @@
+//! Exit IP history
+//!
+//! Remembers how each exit IP fared on each site so a restart that lands on an IP the site
+//! already blocked can be retried right away.
+
+use crate::error::Error;
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    fmt, fs,
+    path::{Path, PathBuf},
+    sync::{
+        Mutex,
+        atomic::{AtomicBool, Ordering},
+    },
+};
+
+/// How long a bad outcome keeps an exit IP on the site's blocklist
+pub const BLOCK_HOURS: i64 = 24;
+
+/// Unsolved challenges in a row that put an exit IP on the site's blocklist (a single one is
+/// often just a solver hiccup)
+pub const BLOCK_CHALLENGES: usize = 3;
+
+#[derive(Clone, Copy, Debug, PartialOrd, PartialEq, Ord, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Outcome {
+    /// Page served
+    Ok,
+
+    /// Challenge could not be solved
+    Challenged,
+
+    /// HTTP 403
+    Forbidden,
+
+    /// Rate limited or explicitly blocked
+    Banned,
+}
+
+impl fmt::Display for Outcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Outcome::Ok => f.write_str("ok"),
+            Outcome::Challenged => f.write_str("challenged"),
+            Outcome::Forbidden => f.write_str("403"),
+            Outcome::Banned => f.write_str("banned"),
+        }
+    }
+}
+
+/// Outcomes of one exit IP on one site
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct Record {
+    /// Last outcome
+    pub last: Option<Outcome>,
+
+    /// Time of the last outcome
+    pub last_seen: Option<DateTime<Utc>>,
+
+    /// Outcome counters
+    #[serde(default)]
+    pub counts: BTreeMap<Outcome, usize>,
+
+    /// Unsolved challenges since the last other outcome
+    #[serde(default)]
+    pub challenges: usize,
+}
+
+impl Record {
+    /// Returns true if the last outcome was bad and recent. Challenges only count after
+    /// [`BLOCK_CHALLENGES`] in a row.
+    pub fn is_blocked(&self, now: DateTime<Utc>) -> bool {
+        match (self.last, self.last_seen) {
+            (Some(Outcome::Ok), _) | (None, _) | (_, None) => false,
+            (Some(Outcome::Challenged), _) if self.challenges < BLOCK_CHALLENGES => false,
+            (Some(_), Some(seen)) => now - seen < Duration::hours(BLOCK_HOURS),
+        }
+    }
+}
+
+/// Site -> exit IP -> record
+pub type Records = BTreeMap<String, BTreeMap<String, Record>>;
+
+#[derive(Debug)]
+pub struct IpHistory {
+    path: PathBuf,
+    records: Mutex<Records>,
+
+    /// Set when the records changed since the file was written
+    dirty: AtomicBool,
+
+    /// Serializes writes of the file
+    writing: Mutex<()>,
+}
+
+impl IpHistory {
+    /// Loads the history file (an empty history if it does not exist yet)
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        let path = path.as_ref();
+        let records = match path.is_file() {
+            true => serde_json::from_str(&fs::read_to_string(path)?).map_err(Error::json)?,
+            false => Records::new(),
+        };
+        Ok(IpHistory {
+            path: path.into(),
+            records: Mutex::new(records),
+            dirty: AtomicBool::new(false),
+            writing: Mutex::new(()),
+        })
+    }
+
+    /// Returns a copy of every record
+    pub fn records(&self) -> Records {
+        self.records.lock().expect("ip history poisoned").clone()
+    }
+
+    /// Records an outcome. The file is written by [`IpHistory::save`].
+    pub fn record(&self, site: &str, ip: &str, outcome: Outcome) {
+        let mut records = self.records.lock().expect("ip history poisoned");
+        let record = records
+            .entry(site.into())
+            .or_default()
+            .entry(ip.into())
+            .or_default();
+        log::debug!("{} on {}: {}", ip, site, outcome);
+        record.last = Some(outcome);
+        record.last_seen = Some(Utc::now());
+        *record.counts.entry(outcome).or_default() += 1;
+        record.challenges = match outcome {
+            Outcome::Challenged => record.challenges + 1,
+            _ => 0,
+        };
+        self.dirty.store(true, Ordering::SeqCst);
+    }
+
+    /// Writes the history file if it changed. Saves queued while another one is writing find
+    /// nothing left to do, so a burst of outcomes is written once or twice. Blocks on file I/O.
+    pub fn save(&self) -> Result<(), Error> {
+        let _writing = self.writing.lock().expect("ip history poisoned");
+        if !self.dirty.swap(false, Ordering::SeqCst) {
+            return Ok(());
+        }
+        let json = serde_json::to_string_pretty(&self.records()).map_err(Error::json)?;
+        let mut tmp = self.path.as_os_str().to_owned();
+        tmp.push(".part");
+        let res = fs::write(&tmp, json).and_then(|_| fs::rename(&tmp, &self.path));
+        if res.is_err() {
+            self.dirty.store(true, Ordering::SeqCst);
+        }
+        res.map_err(Error::from)
+    }
+
+    /// Returns true if the exit IP is on the site's recent blocklist
+    pub fn is_blocked(&self, site: &str, ip: &str) -> bool {
+        let records = self.records.lock().expect("ip history poisoned");
+        records
+            .get(site)
+            .and_then(|ips| ips.get(ip))
+            .map(|record| record.is_blocked(Utc::now()))
+            .unwrap_or(false)
+    }
+}
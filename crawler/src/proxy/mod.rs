@@ -10,9 +10,13 @@ use std::fmt;
 
 mod api;
 mod auth;
+mod guard;
+mod history;
 
 pub use api::Api;
 pub use auth::{BasicAuth, Credentials};
+pub use guard::LeakGuard;
+pub use history::{IpHistory, Outcome, Record, Records, BLOCK_HOURS};
 
 #[derive(Clone, Copy, Debug, PartialOrd, PartialEq, Ord, Eq)]
 pub enum ProxyStatus {
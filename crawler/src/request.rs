@@ -2,6 +2,7 @@
 
 use crate::proxy::Proxy;
 use serde::Serialize;
+use std::{fmt, sync::Arc};
 
 #[derive(Clone, Debug, Serialize)]
 #[serde(tag = "waitType", content = "waitFor")]
@@ -140,6 +141,16 @@ impl Cookie {
     }
 }
 
+/// Check of a response, e.g. that the page has the expected content
+#[derive(Clone)]
+pub struct Validator(Arc<dyn Fn(&str) -> bool + Send + Sync>);
+
+impl fmt::Debug for Validator {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Validator")
+    }
+}
+
 #[derive(Clone, Debug, Serialize)]
 pub struct Request {
     pub(crate) cmd: String,
@@ -155,6 +166,8 @@ pub struct Request {
     pub(crate) no_kill: bool,
     #[serde(rename = "postData", skip_serializing_if = "Option::is_none")]
     pub(crate) post_data: Option<String>,
+    #[serde(skip)]
+    pub(crate) validator: Option<Validator>,
 }
 
 impl Request {
@@ -165,6 +178,13 @@ impl Request {
     pub fn post(url: &str) -> RequestBuilder {
         RequestBuilder::post(url)
     }
+
+    /// Returns true if the response passes the request's validator (if any)
+    pub fn is_valid(&self, response: &str) -> bool {
+        self.validator
+            .as_ref()
+            .is_none_or(|validator| (validator.0)(response))
+    }
 }
 
 #[derive(Clone, Debug, Serialize)]
@@ -188,6 +208,7 @@ impl RequestBuilder {
             cookies: None,
             no_kill: true,
             post_data: None,
+            validator: None,
         })
     }
 
@@ -200,6 +221,7 @@ impl RequestBuilder {
             cookies: None,
             no_kill: true,
             post_data: Some("".into()),
+            validator: None,
         })
     }
 
@@ -230,6 +252,13 @@ impl RequestBuilder {
         self
     }
 
+    /// Treats responses failing `validator` as recoverable errors, so they are retried and never
+    /// cached
+    pub fn validate(mut self, validator: impl Fn(&str) -> bool + Send + Sync + 'static) -> Self {
+        self.0.validator = Some(Validator(Arc::new(validator)));
+        self
+    }
+
     pub fn post_data(mut self, post_data: &[(&str, &str)]) -> Self {
         let mut form = form_urlencoded::Serializer::new(String::new());
         for (k, v) in post_data {
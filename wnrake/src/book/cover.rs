@@ -0,0 +1,93 @@
+//! Cover images
+
+use crate::error::Error;
+use image::{DynamicImage, ImageFormat, codecs::jpeg::JpegEncoder, imageops::FilterType};
+use std::io::Cursor;
+
+/// Largest cover e-readers display without scaling
+const MAX_WIDTH: u32 = 1600;
+const MAX_HEIGHT: u32 = 2560;
+
+/// JPEG quality of converted covers
+const JPEG_QUALITY: u8 = 90;
+
+/// Accept header for cover downloads. The `image` crate decodes AVIF only with the native
+/// dav1d library, which wnrake does not require, so ask for anything else.
+pub const ACCEPT: &str = "image/jpeg,image/png,image/webp;q=0.9,image/gif;q=0.8";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CoverFormat {
+    Jpeg,
+    Png,
+}
+
+impl CoverFormat {
+    /// Detects the format of a stored cover
+    pub fn detect(data: &[u8]) -> Option<Self> {
+        match image::guess_format(data) {
+            Ok(ImageFormat::Jpeg) => Some(CoverFormat::Jpeg),
+            Ok(ImageFormat::Png) => Some(CoverFormat::Png),
+            _ => None,
+        }
+    }
+
+    pub fn media_type(&self) -> &'static str {
+        match self {
+            CoverFormat::Jpeg => "image/jpeg",
+            CoverFormat::Png => "image/png",
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            CoverFormat::Jpeg => "jpg",
+            CoverFormat::Png => "png",
+        }
+    }
+}
+
+/// Turns a downloaded image into an EPUB cover: JPEG and PNG are kept, WebP and GIF become JPEG,
+/// and oversized images are scaled down. AVIF is not supported.
+pub fn prepare(data: &[u8]) -> Result<(CoverFormat, Vec<u8>), Error> {
+    let format = image::guess_format(data).map_err(Error::parser)?;
+    if format == ImageFormat::Avif {
+        return Err(Error::parser(
+            "AVIF covers are not supported, save the cover as cover.jpg or cover.png in the book \
+             directory",
+        ));
+    }
+    let image = image::load_from_memory_with_format(data, format).map_err(Error::parser)?;
+    let oversized = image.width() > MAX_WIDTH || image.height() > MAX_HEIGHT;
+    log::debug!("cover: {:?} {}x{}", format, image.width(), image.height());
+
+    match (format, oversized) {
+        (ImageFormat::Jpeg, false) => Ok((CoverFormat::Jpeg, data.to_vec())),
+        (ImageFormat::Png, false) => Ok((CoverFormat::Png, data.to_vec())),
+        (ImageFormat::Png, true) => Ok((CoverFormat::Png, encode_png(&resize(&image))?)),
+        (_, true) => Ok((CoverFormat::Jpeg, encode_jpeg(&resize(&image))?)),
+        (_, false) => Ok((CoverFormat::Jpeg, encode_jpeg(&image)?)),
+    }
+}
+
+fn resize(image: &DynamicImage) -> DynamicImage {
+    log::debug!("resizing cover to fit {}x{}", MAX_WIDTH, MAX_HEIGHT);
+    image.resize(MAX_WIDTH, MAX_HEIGHT, FilterType::Lanczos3)
+}
+
+pub fn encode_jpeg(image: &DynamicImage) -> Result<Vec<u8>, Error> {
+    let mut data = Vec::new();
+    let encoder = JpegEncoder::new_with_quality(&mut data, JPEG_QUALITY);
+    image
+        .to_rgb8()
+        .write_with_encoder(encoder)
+        .map_err(Error::parser)?;
+    Ok(data)
+}
+
+pub fn encode_png(image: &DynamicImage) -> Result<Vec<u8>, Error> {
+    let mut data = Cursor::new(Vec::new());
+    image
+        .write_to(&mut data, ImageFormat::Png)
+        .map_err(Error::parser)?;
+    Ok(data.into_inner())
+}
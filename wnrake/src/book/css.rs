@@ -1,3 +1,8 @@
+//! Stylesheets
+
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
 pub static CSS_TEMPLATE: &str = r#"
 @namespace epub "http://www.idpf.org/2007/ops";
 
@@ -103,6 +108,40 @@ nav ul ul ul {
   padding-left: 30px !important;
 }
 
+/* Author and translator notes */
+
+aside.note {
+  margin: 12px 0;
+  padding: 4px 10px;
+  border-left: 3px solid #A0A0A0;
+  font-size: 0.9em;
+}
+
+p.note-label {
+  font-weight: bold;
+}
+
+section.notes {
+  margin-top: 20px;
+  border-top: 1px solid #A0A0A0;
+}
+
+/* Footnotes */
+
+a[epub|type~='noteref'] {
+  font-size: 0.8em;
+  text-decoration: none;
+}
+
+aside.footnote {
+  margin: 8px 0;
+  font-size: 0.9em;
+}
+
+p.footnote-back {
+  margin: 0;
+}
+
 /* Set the size for the cover image. */
 
 img.cover-image {
@@ -178,3 +217,146 @@ div.table p.title {
   padding: 0;
 }
 "#;
+
+/// Built-in themes, applied on top of the template
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum Theme {
+    Serif,
+    Sans,
+    /// High contrast for e-ink readers
+    Eink,
+    Dark,
+}
+
+impl Theme {
+    /// Font stack used after any embedded fonts
+    fn font_family(&self) -> &'static str {
+        match self {
+            Theme::Serif | Theme::Eink => {
+                "Cambria, \"Liberation Serif\", \"Bitstream Vera Serif\", Georgia, Times, \"Times New Roman\", serif"
+            }
+            Theme::Sans | Theme::Dark => {
+                "\"Open Sans\", \"Liberation Sans\", \"DejaVu Sans\", Arial, Helvetica, sans-serif"
+            }
+        }
+    }
+
+    fn css(&self) -> &'static str {
+        match self {
+            Theme::Serif | Theme::Sans => "",
+            Theme::Eink => EINK_THEME,
+            Theme::Dark => DARK_THEME,
+        }
+    }
+}
+
+static EINK_THEME: &str = r#"
+body {
+  color: #000;
+  background: #fff;
+  line-height: 1.4;
+}
+
+h2, h4, h5, h6, span.gray50 {
+  color: #000;
+}
+
+.light-gray-background, tr:nth-of-type(even) {
+  background: transparent;
+}
+
+tr, tr th {
+  border-color: #000 !important;
+}
+"#;
+
+static DARK_THEME: &str = r#"
+body {
+  color: #ddd;
+  background: #121212;
+}
+
+h2 {
+  color: #e57373;
+}
+
+h4, h5, h6, span.gray50 {
+  color: #aaa;
+}
+
+a {
+  color: #90caf9;
+}
+
+th {
+  color: #fff;
+}
+
+tr:nth-of-type(even) {
+  background-color: #1e1e1e;
+}
+"#;
+
+/// Builds the stylesheet: `@font-face` rules for the embedded fonts (EPUB paths), the template,
+/// the theme and the user's CSS
+pub fn stylesheet(theme: Option<Theme>, fonts: &[String], user_css: Option<&str>) -> String {
+    let mut css = String::from("@namespace epub \"http://www.idpf.org/2007/ops\";\n");
+    let mut families = Vec::new();
+    for font in fonts {
+        let (family, weight, style) = font_face(font);
+        css.push_str(&format!(
+            "\n@font-face {{\n  font-family: \"{}\";\n  font-weight: {};\n  font-style: {};\n  src: url(\"{}\");\n}}\n",
+            family, weight, style, font
+        ));
+        if !families.contains(&family) {
+            families.push(family);
+        }
+    }
+    css.push_str(
+        CSS_TEMPLATE
+            .trim_start()
+            .trim_start_matches("@namespace epub \"http://www.idpf.org/2007/ops\";"),
+    );
+    if let Some(theme) = theme {
+        css.push_str(theme.css());
+    }
+    if theme.is_some() || !families.is_empty() {
+        let mut stack = families
+            .iter()
+            .map(|family| format!("\"{}\"", family))
+            .collect::<Vec<_>>();
+        if let Some(theme) = theme {
+            stack.push(theme.font_family().to_string());
+        }
+        css.push_str(&format!(
+            "\nbody {{\n  font-family: {};\n}}\n",
+            stack.join(", ")
+        ));
+    }
+    if let Some(user_css) = user_css {
+        css.push_str("\n/* user stylesheet */\n");
+        css.push_str(user_css);
+    }
+    css
+}
+
+/// Family, weight and style of a font, from file names like `NotoSans-BoldItalic.ttf`
+fn font_face(path: &str) -> (String, &'static str, &'static str) {
+    let stem = Path::new(path)
+        .file_stem()
+        .and_then(|stem| stem.to_str())
+        .unwrap_or("font");
+    let (family, variant) = match stem.rsplit_once('-') {
+        Some((family, variant)) => (family, variant.to_lowercase()),
+        None => (stem, String::new()),
+    };
+    let face = match variant.as_str() {
+        "regular" => ("normal", "normal"),
+        "bold" => ("bold", "normal"),
+        "italic" => ("normal", "italic"),
+        "bolditalic" => ("bold", "italic"),
+        _ => return (stem.to_string(), "normal", "normal"),
+    };
+    (family.to_string(), face.0, face.1)
+}
@@ -0,0 +1,221 @@
+//! XHTML fix-up for EPUB content
+//!
+//! Parsed chapters are serialized as HTML. EPUB readers want XML: closed void elements,
+//! escaped text, unique IDs and a language on the root element.
+
+use crate::book::NoteMode;
+use ego_tree::{NodeId, NodeRef};
+use scraper::{Html, Node};
+use std::collections::{HashMap, HashSet};
+
+/// Elements without content, written as `<x/>`
+const VOID_ELEMENTS: &[&str] = &[
+    "area", "base", "br", "col", "embed", "hr", "img", "input", "link", "meta", "source", "track",
+    "wbr",
+];
+
+/// Rewrites an HTML document as well-formed XHTML. `path` is the document's path inside the
+/// EPUB, used to link the stylesheet. Notes (`<aside class="note">`) are kept, removed or moved
+/// to the end of the body according to `notes`.
+pub fn to_xhtml(html: &str, lang: &str, path: &str, notes: NoteMode) -> String {
+    let document = Html::parse_document(html);
+    let stylesheet = format!("{}stylesheet.css", "../".repeat(path.matches('/').count()));
+    let (ids, renamed) = unique_ids(&document);
+    let mut writer = XhtmlWriter {
+        out: String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<!DOCTYPE html>\n"),
+        ids,
+        renamed,
+        lang,
+        stylesheet,
+        notes,
+        end_notes: Vec::new(),
+    };
+    writer.element(*document.root_element());
+    writer.out.push('\n');
+    writer.out
+}
+
+struct XhtmlWriter<'a> {
+    out: String,
+
+    /// Fixed ID of every element with one
+    ids: HashMap<NodeId, String>,
+
+    /// Original ID -> fixed ID of its first element, for `#fragment` links
+    renamed: HashMap<String, String>,
+    lang: &'a str,
+    stylesheet: String,
+    notes: NoteMode,
+
+    /// Notes gathered for the end of the body
+    end_notes: Vec<NodeRef<'a, Node>>,
+}
+
+impl<'a> XhtmlWriter<'a> {
+    fn node(&mut self, node: NodeRef<'a, Node>) {
+        match node.value() {
+            Node::Element(_) => self.element(node),
+            Node::Text(text) => self.out.push_str(&escape(text)),
+            // Comments, doctypes and processing instructions are dropped
+            _ => {}
+        }
+    }
+
+    fn element(&mut self, node: NodeRef<'a, Node>) {
+        let Node::Element(el) = node.value() else {
+            return;
+        };
+        let name = el.name();
+        if name == "aside" && el.classes().any(|class| class == "note") {
+            match self.notes {
+                NoteMode::Inline => {}
+                NoteMode::Strip => return,
+                NoteMode::End => {
+                    self.end_notes.push(node);
+                    return;
+                }
+            }
+        }
+        self.out.push('<');
+        self.out.push_str(name);
+        if name == "html" {
+            self.out.push_str(&format!(
+                " xmlns=\"http://www.w3.org/1999/xhtml\" xmlns:epub=\"http://www.idpf.org/2007/ops\" lang=\"{0}\" xml:lang=\"{0}\"",
+                escape_attr(self.lang)
+            ));
+        } else {
+            for (attr, value) in el.attrs() {
+                if !is_xml_name(attr) || attr == "xmlns" {
+                    continue;
+                }
+                let value = match (attr, value.strip_prefix('#')) {
+                    ("id", _) => self.ids[&node.id()].clone(),
+                    ("href", Some(fragment)) => match self.renamed.get(fragment) {
+                        Some(id) => format!("#{}", id),
+                        None => value.to_string(),
+                    },
+                    _ => value.to_string(),
+                };
+                self.out
+                    .push_str(&format!(" {}=\"{}\"", attr, escape_attr(&value)));
+            }
+        }
+        if VOID_ELEMENTS.contains(&name) {
+            self.out.push_str("/>");
+            return;
+        }
+        self.out.push('>');
+        for child in node.children() {
+            self.node(child);
+        }
+        if name == "body" && !self.end_notes.is_empty() {
+            self.out.push_str("<section class=\"notes\">");
+            for note in std::mem::take(&mut self.end_notes) {
+                for child in note.children() {
+                    self.node(child);
+                }
+            }
+            self.out.push_str("</section>");
+        }
+        if name == "head" {
+            self.out.push_str(&format!(
+                "<link rel=\"stylesheet\" type=\"text/css\" href=\"{}\"/>",
+                self.stylesheet
+            ));
+        }
+        self.out.push_str(&format!("</{}>", name));
+    }
+}
+
+/// Makes every ID a valid XML name and renames duplicates, in document order
+fn unique_ids(document: &Html) -> (HashMap<NodeId, String>, HashMap<String, String>) {
+    let mut used = HashSet::new();
+    let mut ids = HashMap::new();
+    let mut renamed = HashMap::new();
+    for node in document.tree.nodes() {
+        let Some(old) = node.value().as_element().and_then(|el| el.attr("id")) else {
+            continue;
+        };
+        let mut base = old
+            .chars()
+            .map(
+                |c| match c.is_alphanumeric() || c == '-' || c == '_' || c == '.' {
+                    true => c,
+                    false => '-',
+                },
+            )
+            .collect::<String>();
+        if !base.starts_with(|c: char| c.is_alphabetic() || c == '_') {
+            base = format!("id-{}", base);
+        }
+        let mut id = base.clone();
+        let mut i = 2;
+        while !used.insert(id.clone()) {
+            id = format!("{}-{}", base, i);
+            i += 1;
+        }
+        renamed.entry(old.to_string()).or_insert_with(|| id.clone());
+        ids.insert(node.id(), id);
+    }
+    (ids, renamed)
+}
+
+/// Attribute names that are valid in XHTML (`epub:` and `xml:` are the declared prefixes)
+fn is_xml_name(name: &str) -> bool {
+    let name = match name.split_once(':') {
+        Some(("epub" | "xml", name)) => name,
+        Some(_) => return false,
+        None => name,
+    };
+    name.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == '.')
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+fn escape_attr(text: &str) -> String {
+    escape(text).replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn body(html: &str) -> String {
+        let xhtml = to_xhtml(
+            &format!("<html><head></head><body>{}</body></html>", html),
+            "en",
+            "text/chapter.xhtml",
+            NoteMode::Inline,
+        );
+        let start = xhtml.find("<body>").unwrap() + "<body>".len();
+        let end = xhtml.find("</body>").unwrap();
+        xhtml[start..end].to_string()
+    }
+
+    #[test]
+    fn duplicate_ids() {
+        assert_eq!(
+            body("<a href=\"#n\">1</a><p id=\"n\">a</p><p id=\"n\">b</p>"),
+            "<a href=\"#n\">1</a><p id=\"n\">a</p><p id=\"n-2\">b</p>"
+        );
+    }
+
+    #[test]
+    fn renamed_ids_keep_links() {
+        assert_eq!(
+            body("<sup><a href=\"#1 note\">1</a></sup><aside id=\"1 note\">x</aside>"),
+            "<sup><a href=\"#id-1-note\">1</a></sup><aside id=\"id-1-note\">x</aside>"
+        );
+        assert_eq!(
+            body("<a href=\"other.xhtml#1\">x</a><a href=\"#missing\">y</a><p id=\"1\"></p>"),
+            "<a href=\"other.xhtml#1\">x</a><a href=\"#missing\">y</a><p id=\"id-1\"></p>"
+        );
+    }
+}
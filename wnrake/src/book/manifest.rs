@@ -0,0 +1,365 @@
+//! Project manifest
+
+use crate::{
+    book::{BookInfo, ChapterInfo, ChapterList, Theme, UrlCache},
+    error::Error,
+    parser::{self, TypographyRule},
+    utils::{self, ChapterRange},
+    workspace::Workspace,
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, HashMap, VecDeque},
+    fmt, fs,
+    path::Path,
+};
+
+/// Current manifest schema version
+pub const SCHEMA_VERSION: u32 = 1;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ChapterStatus {
+    /// Not downloaded yet
+    #[default]
+    Pending,
+
+    /// Raw page is in the staging directory
+    Downloaded,
+
+    /// Parsed into the book directory
+    Parsed,
+
+    /// Last download or parse failed
+    Failed,
+}
+
+impl fmt::Display for ChapterStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ChapterStatus::Pending => f.write_str("pending"),
+            ChapterStatus::Downloaded => f.write_str("downloaded"),
+            ChapterStatus::Parsed => f.write_str("parsed"),
+            ChapterStatus::Failed => f.write_str("failed"),
+        }
+    }
+}
+
+/// State of one chapter. Paths are relative to the workspace.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct ChapterEntry {
+    /// Stable identifier, names the chapter's files (see `parser::chapter_id`)
+    #[serde(default)]
+    pub id: String,
+
+    /// Chapter URL
+    pub url: String,
+
+    /// Parsed title
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+
+    /// Volume according to the site
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub volume: Option<String>,
+
+    /// Downloaded page
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub staging: Option<String>,
+
+    /// Parsed chapter
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub parsed: Option<String>,
+
+    /// Download/parse status
+    #[serde(default)]
+    pub status: ChapterStatus,
+
+    /// SHA-256 of the downloaded page
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hash: Option<String>,
+
+    /// Time of the last download
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub downloaded_at: Option<DateTime<Utc>>,
+
+    /// Time of the last parse
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub parsed_at: Option<DateTime<Utc>>,
+
+    /// Replaced and rejected versions, oldest first
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub revisions: Vec<Revision>,
+}
+
+/// Another version of a chapter's page, kept by `recheck`
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Revision {
+    /// Stored page
+    pub staging: String,
+
+    /// Hash of the normalized chapter text
+    pub text_hash: String,
+
+    /// Time the version was replaced or rejected
+    pub saved_at: DateTime<Utc>,
+
+    /// The version was fetched and rejected (instead of replaced by a newer one)
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub rejected: bool,
+}
+
+impl ChapterEntry {
+    pub fn new(url: impl Into<String>) -> Self {
+        let url = url.into();
+        ChapterEntry {
+            id: parser::chapter_id(&url),
+            url,
+            ..Default::default()
+        }
+    }
+
+    /// Workspace-relative path of the downloaded page
+    pub fn staging_file(&self) -> String {
+        Workspace::staging_file(&utils::chapter_filename(&self.id))
+    }
+
+    /// Workspace-relative path of the parsed chapter
+    pub fn parsed_file(&self) -> String {
+        Workspace::book_file(&utils::chapter_filename(&self.id))
+    }
+
+    /// Records a successful download
+    pub fn downloaded(&mut self, staging: String, data: &[u8]) {
+        self.staging = Some(staging);
+        self.hash = Some(utils::hash(data));
+        self.downloaded_at = Some(Utc::now());
+        self.status = ChapterStatus::Downloaded;
+    }
+
+    /// Records a successful parse
+    pub fn parsed(&mut self, parsed: String, title: String) {
+        self.parsed = Some(parsed);
+        self.title = Some(title);
+        self.parsed_at = Some(Utc::now());
+        self.status = ChapterStatus::Parsed;
+    }
+
+    /// Returns true if the chapter has to be (re)parsed
+    pub fn is_stale(&self) -> bool {
+        self.status != ChapterStatus::Parsed
+            || self.parsed.as_deref() != Some(self.parsed_file().as_str())
+            || match (self.downloaded_at, self.parsed_at) {
+                (Some(downloaded_at), Some(parsed_at)) => downloaded_at > parsed_at,
+                _ => false,
+            }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Manifest {
+    /// Schema version
+    pub version: u32,
+
+    /// Book information
+    pub book: BookInfo,
+
+    /// Chapters in reading order
+    #[serde(default)]
+    pub chapters: Vec<ChapterEntry>,
+
+    /// Image URL -> stored image
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub images: BTreeMap<String, String>,
+
+    /// User-defined volumes, they take precedence over the site's
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub volumes: Vec<VolumeRange>,
+
+    /// Build style of this book, used when `build` gets no style options
+    #[serde(default, skip_serializing_if = "Style::is_default")]
+    pub style: Style,
+
+    /// Typographic rules used when parsing, the defaults if unset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub typography: Option<Vec<TypographyRule>>,
+}
+
+/// Theme, stylesheet and fonts of a book. Paths are relative to the workspace.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Style {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub theme: Option<Theme>,
+
+    /// CSS appended to the stylesheet (relative to the workspace, or absolute)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub css: Option<String>,
+
+    /// TTF/OTF fonts to embed (relative to the workspace, or absolute)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub fonts: Vec<String>,
+}
+
+impl Style {
+    pub fn is_default(&self) -> bool {
+        *self == Style::default()
+    }
+}
+
+/// Chapters grouped by the user
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct VolumeRange {
+    pub title: String,
+    pub chapters: ChapterRange,
+}
+
+impl Manifest {
+    pub fn new(book: BookInfo) -> Self {
+        Manifest {
+            version: SCHEMA_VERSION,
+            book,
+            chapters: Vec::new(),
+            images: BTreeMap::new(),
+            volumes: Vec::new(),
+            style: Style::default(),
+            typography: None,
+        }
+    }
+
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        let manifest: Manifest =
+            serde_json::from_str(&fs::read_to_string(path)?).map_err(Error::json)?;
+        if manifest.version > SCHEMA_VERSION {
+            return Err(Error::parser(format!(
+                "manifest version {} is newer than supported version {}",
+                manifest.version, SCHEMA_VERSION
+            )));
+        }
+        Ok(Manifest {
+            version: SCHEMA_VERSION,
+            ..manifest
+        })
+    }
+
+    pub fn to_file<P: AsRef<Path>>(&self, path: P) -> Result<(), Error> {
+        let json = serde_json::to_string_pretty(self).map_err(Error::json)?;
+        utils::write_file(path, json.as_bytes())
+    }
+
+    /// Returns the chapter URLs
+    pub fn urls(&self) -> UrlCache {
+        UrlCache(self.chapters.iter().map(|c| c.url.clone()).collect())
+    }
+
+    /// Replaces the chapter list, keeping the state of chapters that are still listed. A URL
+    /// listed more than once takes the old entries of that URL in order.
+    pub fn set_urls(&mut self, urls: UrlCache) {
+        let mut old = HashMap::<_, VecDeque<_>>::new();
+        for chapter in self.chapters.drain(..) {
+            old.entry(chapter.url.clone())
+                .or_default()
+                .push_back(chapter);
+        }
+        self.chapters = urls
+            .0
+            .into_iter()
+            .map(|url| {
+                old.get_mut(&url)
+                    .and_then(|entries| entries.pop_front())
+                    .unwrap_or_else(|| ChapterEntry::new(url))
+            })
+            .collect();
+    }
+
+    /// Sets the site's volumes from (chapter URL, volume) pairs
+    pub fn set_site_volumes(&mut self, volumes: Vec<(String, String)>) {
+        let volumes = volumes.into_iter().collect::<HashMap<_, _>>();
+        for chapter in self.chapters.iter_mut() {
+            chapter.volume = volumes.get(&chapter.url).cloned();
+        }
+    }
+
+    /// Returns the volume of the chapter at `index`
+    pub fn volume(&self, index: usize) -> Option<&str> {
+        match self.volumes.iter().find(|v| v.chapters.contains(index)) {
+            Some(range) => Some(range.title.as_str()),
+            None => self.chapters.get(index)?.volume.as_deref(),
+        }
+    }
+
+    /// Builds the chapter list of the selected chapters, which must have been parsed
+    pub fn chapter_list(&self, root: &Path, selected: &[bool]) -> Result<ChapterList, Error> {
+        let mut chapterlist = ChapterList::new();
+        for (i, chapter) in self.chapters.iter().enumerate() {
+            if !selected[i] {
+                continue;
+            }
+            match &chapter.parsed {
+                Some(parsed) => chapterlist.as_mut().push(ChapterInfo {
+                    path: root.join(parsed).to_string_lossy().into(),
+                    title: chapter.title.as_deref().unwrap_or("???").into(),
+                    volume: self.volume(i).map(String::from),
+                }),
+                None => {
+                    return Err(Error::parser(format!(
+                        "chapter {} has not been parsed: {}",
+                        i + 1,
+                        chapter.url
+                    )));
+                }
+            }
+        }
+        Ok(chapterlist)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn urls(urls: &[&str]) -> UrlCache {
+        UrlCache(urls.iter().map(|url| url.to_string()).collect())
+    }
+
+    #[test]
+    fn set_urls_keeps_state() {
+        let mut manifest = Manifest::new(BookInfo::new("Title", "Author", "https://a/"));
+        manifest.set_urls(urls(&["https://a/1", "https://a/2", "https://a/3"]));
+        manifest.chapters[1].downloaded("staging/2".into(), b"2");
+
+        manifest.set_urls(urls(&["https://a/0", "https://a/2", "https://a/4"]));
+        let states = manifest
+            .chapters
+            .iter()
+            .map(|c| (c.url.as_str(), c.status, c.staging.as_deref()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            states,
+            [
+                ("https://a/0", ChapterStatus::Pending, None),
+                ("https://a/2", ChapterStatus::Downloaded, Some("staging/2")),
+                ("https://a/4", ChapterStatus::Pending, None),
+            ]
+        );
+    }
+
+    #[test]
+    fn set_urls_keeps_duplicates_in_order() {
+        let mut manifest = Manifest::new(BookInfo::new("Title", "Author", "https://a/"));
+        manifest.set_urls(urls(&["https://a/1", "https://a/x", "https://a/x"]));
+        manifest.chapters[1].downloaded("staging/first".into(), b"1");
+        manifest.chapters[2].downloaded("staging/second".into(), b"2");
+
+        manifest.set_urls(urls(&["https://a/x", "https://a/x", "https://a/x"]));
+        let staging = manifest
+            .chapters
+            .iter()
+            .map(|c| c.staging.as_deref())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            staging,
+            [Some("staging/first"), Some("staging/second"), None]
+        );
+    }
+}
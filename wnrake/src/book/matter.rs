@@ -0,0 +1,142 @@
+//! Front and back matter pages
+//!
+//! Templates are plain text, one block per line: `# text` is a heading, `## text` a
+//! subheading, `---` a rule and anything else a paragraph. `{name}` placeholders are filled from
+//! the book; a line whose placeholders are all empty is left out.
+
+use crate::{
+    error::Error,
+    xhtml::{Element, XhtmlBuilder},
+};
+use std::{collections::HashMap, fs, path::Path};
+
+pub static TITLE_TEMPLATE: &str = "\
+# {title}
+## {author}
+";
+
+pub static ABOUT_TEMPLATE: &str = "\
+# About this book
+{description}
+---
+Tags: {tags}
+Status: {status}
+Chapters: {chapters}
+Published: {published}
+Updated: {updated}
+Source: {url}
+Downloaded: {downloaded}
+";
+
+pub static CLOSING_TEMPLATE: &str = "\
+# Thank you for reading
+{title} by {author}
+This copy was made from {url} on {downloaded}.
+";
+
+/// Page templates of a book
+#[derive(Clone, Debug)]
+pub struct Templates {
+    pub title: String,
+    pub about: String,
+
+    /// The closing page is optional
+    pub closing: Option<String>,
+}
+
+impl Default for Templates {
+    fn default() -> Self {
+        Templates {
+            title: TITLE_TEMPLATE.into(),
+            about: ABOUT_TEMPLATE.into(),
+            closing: None,
+        }
+    }
+}
+
+impl Templates {
+    /// Loads `title.txt`, `about.txt` and `closing.txt` from `dir`, falling back to the built-in
+    /// templates. There is a closing page if `closing.txt` exists or `closing` is set.
+    pub fn load(dir: &Path, closing: bool) -> Result<Self, Error> {
+        let load = |name: &str| -> Result<Option<String>, Error> {
+            let path = dir.join(name);
+            match path.is_file() {
+                true => {
+                    log::debug!("using template {:?}", path);
+                    Ok(Some(fs::read_to_string(path)?))
+                }
+                false => Ok(None),
+            }
+        };
+        Ok(Templates {
+            title: load("title.txt")?.unwrap_or_else(|| TITLE_TEMPLATE.into()),
+            about: load("about.txt")?.unwrap_or_else(|| ABOUT_TEMPLATE.into()),
+            closing: match load("closing.txt")? {
+                Some(template) => Some(template),
+                None => closing.then(|| CLOSING_TEMPLATE.into()),
+            },
+        })
+    }
+}
+
+/// Renders a template into a page titled `title`
+pub fn render(
+    title: &str,
+    template: &str,
+    values: &HashMap<&str, String>,
+) -> Result<String, Error> {
+    let xhtml = XhtmlBuilder::new(title);
+    let article = xhtml.article();
+    for line in template.lines() {
+        let (element, text) = match line.trim() {
+            "" => continue,
+            "---" => {
+                xhtml.append_element(article, Element::Hr)?;
+                continue;
+            }
+            line => match line.strip_prefix("## ") {
+                Some(text) => (Element::H2, text),
+                None => match line.strip_prefix("# ") {
+                    Some(text) => (Element::H1, text),
+                    None => (Element::P, line),
+                },
+            },
+        };
+        let Some(text) = fill(text, values) else {
+            continue;
+        };
+        // Multi-line values (descriptions) become one block per line
+        for text in text.lines().map(str::trim).filter(|text| !text.is_empty()) {
+            let node = xhtml.append_element(article, element)?;
+            xhtml.append_text(node, text.to_string());
+        }
+    }
+    Ok(xhtml.build())
+}
+
+/// Fills the placeholders of a line. Returns `None` if it has placeholders and all are empty.
+fn fill(line: &str, values: &HashMap<&str, String>) -> Option<String> {
+    let mut out = String::new();
+    let mut rest = line;
+    let (mut placeholders, mut filled) = (0, 0);
+    while let Some(start) = rest.find('{') {
+        let Some(end) = rest[start..].find('}').map(|end| start + end) else {
+            break;
+        };
+        out.push_str(&rest[..start]);
+        match values.get(&rest[start + 1..end]) {
+            Some(value) => {
+                placeholders += 1;
+                if !value.trim().is_empty() {
+                    filled += 1;
+                }
+                out.push_str(value);
+            }
+            // Not a placeholder
+            None => out.push_str(&rest[start..=end]),
+        }
+        rest = &rest[end + 1..];
+    }
+    out.push_str(rest);
+    (placeholders == 0 || filled > 0).then_some(out)
+}
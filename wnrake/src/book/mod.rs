@@ -2,32 +2,131 @@
 
 use crate::{
     error::Error,
+    images,
     xhtml::{Element, XhtmlBuilder},
 };
-use epub_builder::{EpubBuilder, EpubContent, ReferenceType, ZipLibrary};
+use chrono::{DateTime, Utc};
+use epub_builder::{EpubBuilder, EpubContent, EpubVersion, ReferenceType, TocElement, ZipLibrary};
+use serde::{Deserialize, Serialize};
 use std::{
-    fs::File,
-    io::{self, BufRead, Write},
+    collections::HashMap,
+    fmt,
+    fs::{self, File},
+    io::{self, BufRead, Cursor, Write},
+    path::{Path, PathBuf},
 };
 
-mod css;
+pub mod cover;
+pub mod css;
+mod fixup;
+mod manifest;
+pub mod matter;
+mod package;
+pub mod validate;
+
+pub use cover::CoverFormat;
+pub use css::Theme;
+pub use manifest::{ChapterEntry, ChapterStatus, Manifest, Revision, Style, VolumeRange};
+pub use matter::Templates;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum BookStatus {
+    Ongoing,
+    Completed,
+    Hiatus,
+    Dropped,
+}
 
-use css::CSS_TEMPLATE;
+impl BookStatus {
+    /// Recognizes the status labels used by the sites
+    pub fn from_label(label: &str) -> Option<Self> {
+        let label = label.trim().to_lowercase();
+        if label.contains("hiatus") {
+            Some(BookStatus::Hiatus)
+        } else if label.contains("dropped") || label.contains("abandoned") {
+            Some(BookStatus::Dropped)
+        } else if label.contains("complete") || label.contains("finished") {
+            Some(BookStatus::Completed)
+        } else if label.contains("ongoing") || label.contains("in progress") {
+            Some(BookStatus::Ongoing)
+        } else {
+            None
+        }
+    }
+}
 
-#[cfg(windows)]
-const LINE_ENDING: &'static [u8] = b"\r\n";
-#[cfg(not(windows))]
-const LINE_ENDING: &'static [u8] = b"\n";
+impl fmt::Display for BookStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BookStatus::Ongoing => f.write_str("Ongoing"),
+            BookStatus::Completed => f.write_str("Completed"),
+            BookStatus::Hiatus => f.write_str("Hiatus"),
+            BookStatus::Dropped => f.write_str("Dropped"),
+        }
+    }
+}
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
 pub struct BookInfo {
     pub title: String,
     pub author: String,
     pub url: String,
+
+    /// Synopsis
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub genres: Vec<String>,
+
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+
+    /// Language code [default: en]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub language: Option<String>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub status: Option<BookStatus>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cover_url: Option<String>,
+
+    /// Site or publisher
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub publisher: Option<String>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub published: Option<DateTime<Utc>>,
+
+    /// Last update
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub updated: Option<DateTime<Utc>>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub series: Option<String>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub series_index: Option<f64>,
 }
 
 impl BookInfo {
-    pub fn from_file(path: &str) -> Result<Self, Error> {
+    pub fn new(
+        title: impl Into<String>,
+        author: impl Into<String>,
+        url: impl Into<String>,
+    ) -> Self {
+        BookInfo {
+            title: title.into(),
+            author: author.into(),
+            url: url.into(),
+            ..Default::default()
+        }
+    }
+
+    /// Reads a legacy `bookinfo.txt`
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
         let mut title = String::new();
         let mut author = String::new();
         let mut url = String::new();
@@ -40,24 +139,9 @@ impl BookInfo {
         if title.is_empty() || author.is_empty() || url.is_empty() {
             Err(Error::parser("invalid bookinfo file"))
         } else {
-            Ok(BookInfo {
-                title: title.trim().to_string(),
-                author: author.trim().to_string(),
-                url: url.trim().to_string(),
-            })
+            Ok(BookInfo::new(title.trim(), author.trim(), url.trim()))
         }
     }
-
-    pub fn to_file(&self, path: &str) -> Result<(), Error> {
-        let mut file = File::create(path)?;
-        file.write_all(self.title.as_bytes())?;
-        file.write_all(LINE_ENDING)?;
-        file.write_all(self.author.as_bytes())?;
-        file.write_all(LINE_ENDING)?;
-        file.write_all(self.url.as_bytes())?;
-        file.write_all(LINE_ENDING)?;
-        Ok(())
-    }
 }
 
 #[derive(Clone, Debug)]
@@ -80,7 +164,8 @@ impl UrlCache {
         UrlCache(Vec::new())
     }
 
-    pub fn from_file(path: &str) -> Result<Self, Error> {
+    /// Reads a legacy `urlcache.txt`
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
         let file = io::BufReader::new(File::open(path)?);
         let urls = file
             .lines()
@@ -91,27 +176,114 @@ impl UrlCache {
             .collect::<Vec<String>>();
         Ok(UrlCache(urls))
     }
+}
 
-    pub fn to_file(&self, path: &str) -> Result<(), Error> {
-        let mut file = File::create(path)?;
-        for url in &self.0 {
-            file.write_all(url.as_bytes())?;
-            file.write_all(LINE_ENDING)?;
+#[derive(Clone, Debug)]
+pub struct Chapter {
+    pub title: String,
+    pub html: String,
+
+    /// Author and translator notes, kept apart from the text
+    pub notes: Vec<Note>,
+
+    /// Text dropped by the copyright filter
+    pub filtered: Vec<Filtered>,
+}
+
+impl Chapter {
+    /// Returns the chapter with its notes as `<aside class="note">` blocks around the text
+    pub fn document(&self) -> String {
+        let aside = |position: NotePosition| {
+            self.notes
+                .iter()
+                .filter(|note| note.position == position)
+                .map(|note| {
+                    format!(
+                        "<aside class=\"note {}\"><p class=\"note-label\">{}</p>{}</aside>",
+                        note.kind.class(),
+                        note.kind.label(),
+                        note.html
+                    )
+                })
+                .collect::<String>()
+        };
+        let (before, after) = (aside(NotePosition::Before), aside(NotePosition::After));
+        self.html
+            .replacen("<article>", &format!("{}<article>", before), 1)
+            .replacen("</article>", &format!("</article>{}", after), 1)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum NoteKind {
+    Author,
+    Translator,
+}
+
+impl NoteKind {
+    fn class(&self) -> &'static str {
+        match self {
+            NoteKind::Author => "author-note",
+            NoteKind::Translator => "translator-note",
+        }
+    }
+
+    fn label(&self) -> &'static str {
+        match self {
+            NoteKind::Author => "Author's note",
+            NoteKind::Translator => "Translator's note",
         }
-        Ok(())
     }
 }
 
+/// Where a note sits relative to the chapter text
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum NotePosition {
+    Before,
+    After,
+}
+
 #[derive(Clone, Debug)]
-pub struct Chapter {
-    pub title: String,
+pub struct Note {
+    pub kind: NoteKind,
+    pub position: NotePosition,
+
+    /// XHTML fragment
     pub html: String,
 }
 
+/// Text dropped from a chapter
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Filtered {
+    /// Text node of the chapter (1-based)
+    pub position: usize,
+
+    /// Rule that matched
+    pub reason: String,
+    pub text: String,
+}
+
+/// What the build does with notes
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum NoteMode {
+    /// Keeps the notes where they are, styled apart from the text
+    #[default]
+    Inline,
+
+    /// Removes the notes
+    Strip,
+
+    /// Gathers the notes at the end of the chapter
+    End,
+}
+
 #[derive(Clone, Debug)]
 pub struct ChapterInfo {
     pub path: String,
     pub title: String,
+
+    /// Volume (book, arc, ...) the chapter belongs to
+    pub volume: Option<String>,
 }
 
 #[derive(Clone, Debug)]
@@ -134,7 +306,26 @@ impl ChapterList {
         ChapterList(Vec::new())
     }
 
-    pub fn from_file(path: &str) -> Result<Self, Error> {
+    /// Splits the list into parts, starting a new part before every chapter `split` returns
+    /// true for. `split` gets the current part and the next chapter.
+    pub fn split_when<F>(self, mut split: F) -> Vec<ChapterList>
+    where
+        F: FnMut(&[ChapterInfo], &ChapterInfo) -> bool,
+    {
+        let mut parts = vec![ChapterList::new()];
+        for chapter in self.0 {
+            let part = parts.last_mut().expect("at least one part");
+            if !part.0.is_empty() && split(&part.0, &chapter) {
+                parts.push(ChapterList(vec![chapter]));
+            } else {
+                part.0.push(chapter);
+            }
+        }
+        parts
+    }
+
+    /// Reads a legacy `chapterlist.txt`
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
         let mut chapterlist = ChapterList::new();
         let file = io::BufReader::new(File::open(path)?);
         for line in file.lines().filter_map(|line| {
@@ -147,28 +338,47 @@ impl ChapterList {
             }
             let path = parts[0].into();
             let title = parts[1].into();
-            chapterlist.0.push(ChapterInfo { path, title });
+            chapterlist.0.push(ChapterInfo {
+                path,
+                title,
+                volume: None,
+            });
         }
         Ok(chapterlist)
     }
-
-    pub fn to_file(&self, path: &str) -> Result<(), Error> {
-        let mut file = File::create(path)?;
-        for chapterinfo in &self.0 {
-            file.write_all(chapterinfo.path.as_bytes())?;
-            file.write_all(b" ")?;
-            file.write_all(chapterinfo.title.as_bytes())?;
-            file.write_all(LINE_ENDING)?;
-        }
-        Ok(())
-    }
 }
 
 #[derive(Debug)]
 pub struct EpubBook {
     bookinfo: BookInfo,
     chapterlist: ChapterList,
-    cover: Option<File>,
+    cover: Option<Vec<u8>>,
+
+    /// Chapter images (EPUB path, file)
+    images: Vec<(String, PathBuf)>,
+
+    /// Adds a title page before each volume
+    volume_pages: bool,
+
+    /// Shown under the cover image, e.g. `Vol 2`
+    cover_label: Option<String>,
+
+    theme: Option<Theme>,
+
+    /// Appended to the stylesheet
+    user_css: Option<String>,
+
+    /// Embedded fonts (EPUB path, file)
+    fonts: Vec<(String, PathBuf)>,
+
+    /// Title, about and closing pages
+    templates: Templates,
+
+    /// What to do with author and translator notes
+    notes: NoteMode,
+
+    /// When the chapters were retrieved
+    downloaded: Option<DateTime<Utc>>,
 }
 
 /*
@@ -208,62 +418,329 @@ nav[epub|type~='toc'] > ul > li > ul > li {
 */
 
 impl EpubBook {
-    pub fn new(bookinfo: BookInfo, chapterlist: ChapterList, cover: Option<File>) -> Self {
+    pub fn new(bookinfo: BookInfo, chapterlist: ChapterList, cover: Option<Vec<u8>>) -> Self {
         EpubBook {
             bookinfo,
             chapterlist,
             cover,
+            images: Vec::new(),
+            volume_pages: false,
+            cover_label: None,
+            theme: None,
+            user_css: None,
+            fonts: Vec::new(),
+            templates: Templates::default(),
+            notes: NoteMode::default(),
+            downloaded: None,
+        }
+    }
+
+    pub fn volume_pages(&mut self, volume_pages: bool) {
+        self.volume_pages = volume_pages;
+    }
+
+    pub fn cover_label<S: Into<String>>(&mut self, label: S) {
+        self.cover_label = Some(label.into());
+    }
+
+    pub fn templates(&mut self, templates: Templates) {
+        self.templates = templates;
+    }
+
+    pub fn notes(&mut self, notes: NoteMode) {
+        self.notes = notes;
+    }
+
+    pub fn downloaded(&mut self, downloaded: Option<DateTime<Utc>>) {
+        self.downloaded = downloaded;
+    }
+
+    pub fn theme(&mut self, theme: Option<Theme>) {
+        self.theme = theme;
+    }
+
+    pub fn user_css<S: Into<String>>(&mut self, css: S) {
+        self.user_css = Some(css.into());
+    }
+
+    /// Embeds a TTF or OTF font as `fonts/<file name>`
+    pub fn add_font(&mut self, file: PathBuf) -> Result<(), Error> {
+        let name = file
+            .file_name()
+            .and_then(|name| name.to_str())
+            .ok_or_else(|| Error::epub(format!("invalid font file {:?}", file)))?;
+        if font_media_type(name).is_none() {
+            return Err(Error::epub(format!("{} is not a TTF or OTF font", name)));
         }
+        self.fonts.push((format!("fonts/{}", name), file));
+        Ok(())
+    }
+
+    /// Adds a chapter image. `path` is the path the chapters link to, e.g. `images/x.jpg`.
+    pub fn add_image(&mut self, path: &str, file: PathBuf) {
+        self.images.push((path.into(), file));
     }
 
-    pub fn to_file(&self, path: &str) -> Result<(), Error> {
+    pub fn to_file<P: AsRef<Path>>(&self, path: P) -> Result<(), Error> {
         let mut file = File::create(path)?;
         let mut output: Vec<u8> = Vec::new();
 
         let mut builder = EpubBuilder::new(ZipLibrary::new()?)?;
 
         builder
+            .epub_version(EpubVersion::V30)
             .metadata("title", self.bookinfo.title.as_str())?
             .metadata("author", self.bookinfo.author.as_str())?
-            .stylesheet(CSS_TEMPLATE.as_bytes())?;
+            .stylesheet(self.stylesheet().as_bytes())?;
+        self.add_metadata(&mut builder);
 
         if let Some(cover) = &self.cover {
+            let format =
+                CoverFormat::detect(cover).ok_or(Error::epub("cover is not a JPEG or PNG"))?;
+            let filename = format!("cover.{}", format.extension());
             let xhtml = XhtmlBuilder::new("Cover");
-            xhtml.append_image(xhtml.article(), "cover.jpg");
-            builder.add_cover_image("cover.jpg", cover, "image/jpeg")?;
+            xhtml.append_image(xhtml.article(), &filename);
+            if let Some(label) = &self.cover_label {
+                let label_id = xhtml.append_element(xhtml.article(), Element::H2)?;
+                xhtml.append_text(label_id, label.clone());
+            }
+            builder.add_cover_image(&filename, cover.as_slice(), format.media_type())?;
             builder.add_content(
-                EpubContent::new("cover.xhtml", xhtml.build().as_bytes())
+                EpubContent::new("cover.xhtml", self.xhtml("cover.xhtml", &xhtml.build()))
                     .title("Cover")
                     .reftype(ReferenceType::Cover),
             )?;
         }
 
-        let title_contents = {
-            let xhtml = XhtmlBuilder::with_header(self.bookinfo.title.as_str());
-            let author_id = xhtml.append_element(xhtml.article(), Element::H2)?;
-            xhtml.append_text(author_id, self.bookinfo.author.clone());
-            xhtml.build()
-        };
+        let values = self.template_values();
+        let title_contents = matter::render(&self.bookinfo.title, &self.templates.title, &values)?;
         builder.add_content(
-            EpubContent::new("title.xhtml", title_contents.as_bytes())
+            EpubContent::new("title.xhtml", self.xhtml("title.xhtml", &title_contents))
                 .title("Title")
                 .reftype(ReferenceType::TitlePage),
         )?;
+        let about_contents = matter::render("About", &self.templates.about, &values)?;
+        builder.add_content(
+            EpubContent::new("about.xhtml", self.xhtml("about.xhtml", &about_contents))
+                .title("About this book"),
+        )?;
 
-        builder.inline_toc();
+        for (path, file) in &self.images {
+            builder.add_resource(path.as_str(), File::open(file)?, images::media_type(path))?;
+        }
+        for (path, file) in &self.fonts {
+            let media_type = font_media_type(path).unwrap_or("application/font-sfnt");
+            builder.add_resource(path.as_str(), File::open(file)?, media_type)?;
+        }
 
+        // Chapters of a volume are nested under it in the TOC
+        let mut volume = None;
+        let mut volume_count = 0;
+        let mut body_start = Some(ReferenceType::Text);
         for chapter in self.chapterlist.as_ref() {
-            let contents = File::open(chapter.path.as_str())?;
-            let content_path = format!("{}.xhtml", chapter.path.trim_end_matches(".html"));
+            let contents = fs::read_to_string(chapter.path.as_str())?;
+            let stem = Path::new(&chapter.path)
+                .file_stem()
+                .and_then(|stem| stem.to_str())
+                .unwrap_or("chapter");
+            let content_path = format!("book/{}.xhtml", stem);
+            let contents = self.xhtml(&content_path, &contents);
+            let mut content =
+                EpubContent::new(content_path.as_str(), contents).title(chapter.title.as_str());
+
+            if let Some(title) = chapter.volume.as_deref() {
+                if volume != Some(title) {
+                    volume = Some(title);
+                    volume_count += 1;
+                    match self.volume_pages {
+                        true => {
+                            let path = format!("book/volume-{}.xhtml", volume_count);
+                            let xhtml = XhtmlBuilder::with_header(title).build();
+                            let mut page =
+                                EpubContent::new(path.as_str(), self.xhtml(&path, &xhtml))
+                                    .title(title);
+                            if let Some(reftype) = body_start.take() {
+                                page = page.reftype(reftype);
+                            }
+                            builder.add_content(page)?;
+                        }
+                        false => {
+                            // The first chapter carries the volume's TOC entry
+                            content = content.title(title).child(TocElement::new(
+                                content_path.as_str(),
+                                chapter.title.as_str(),
+                            ));
+                            if let Some(reftype) = body_start.take() {
+                                content = content.reftype(reftype);
+                            }
+                            builder.add_content(content)?;
+                            continue;
+                        }
+                    }
+                }
+                content = content.level(2);
+            } else {
+                volume = None;
+            }
+            if let Some(reftype) = body_start.take() {
+                content = content.reftype(reftype);
+            }
+            builder.add_content(content)?;
+        }
+
+        if let Some(closing) = &self.templates.closing {
+            let closing_contents = matter::render("Closing", closing, &values)?;
             builder.add_content(
-                EpubContent::new(content_path.as_str(), contents).title(chapter.title.as_str()),
+                EpubContent::new(
+                    "closing.xhtml",
+                    self.xhtml("closing.xhtml", &closing_contents),
+                )
+                .title("Closing")
+                .reftype(ReferenceType::Colophon),
             )?;
         }
 
         builder.generate(&mut output)?;
+        let extra = self.extra_metadata();
+        let output = package::rewrite_entry(&output, "OEBPS/content.opf", |opf| {
+            opf.replacen("  </metadata>", &format!("{}  </metadata>", extra), 1)
+        })?;
 
         file.write_all(output.as_ref())?;
 
         Ok(())
     }
+
+    /// Placeholder values of the page templates
+    fn template_values(&self) -> HashMap<&'static str, String> {
+        let info = &self.bookinfo;
+        let date = |date: Option<DateTime<Utc>>| {
+            date.map(|date| date.format("%Y-%m-%d").to_string())
+                .unwrap_or_default()
+        };
+        HashMap::from([
+            ("title", info.title.clone()),
+            ("author", info.author.clone()),
+            ("description", info.description.clone().unwrap_or_default()),
+            (
+                "tags",
+                info.genres
+                    .iter()
+                    .chain(info.tags.iter())
+                    .cloned()
+                    .collect::<Vec<_>>()
+                    .join(", "),
+            ),
+            ("url", info.url.clone()),
+            ("chapters", self.chapterlist.as_ref().len().to_string()),
+            ("downloaded", date(self.downloaded)),
+            ("built", date(Some(Utc::now()))),
+            (
+                "status",
+                info.status
+                    .map(|status| status.to_string())
+                    .unwrap_or_default(),
+            ),
+            ("publisher", info.publisher.clone().unwrap_or_default()),
+            ("published", date(info.published)),
+            ("updated", date(info.updated)),
+            ("series", info.series.clone().unwrap_or_default()),
+        ])
+    }
+
+    fn stylesheet(&self) -> String {
+        let fonts = self
+            .fonts
+            .iter()
+            .map(|(path, _)| path.clone())
+            .collect::<Vec<_>>();
+        css::stylesheet(self.theme, &fonts, self.user_css.as_deref())
+    }
+
+    fn language(&self) -> &str {
+        self.bookinfo.language.as_deref().unwrap_or("en")
+    }
+
+    /// Converts a page to well-formed XHTML
+    fn xhtml(&self, path: &str, html: &str) -> Cursor<Vec<u8>> {
+        Cursor::new(fixup::to_xhtml(html, self.language(), path, self.notes).into_bytes())
+    }
+
+    /// Writes the extended book information through the builder
+    fn add_metadata(&self, builder: &mut EpubBuilder<ZipLibrary>) {
+        let info = &self.bookinfo;
+        builder.set_languages(vec![self.language().to_string()]);
+        if let Some(description) = &info.description {
+            builder.add_description(description.as_str());
+        }
+        for subject in info.genres.iter().chain(info.tags.iter()) {
+            builder.add_subject(subject.as_str());
+        }
+        if let Some(status) = info.status {
+            builder.add_subject(status.to_string());
+        }
+        if let Some(published) = info.published {
+            builder.set_publication_date(published);
+        }
+    }
+
+    /// OPF metadata epub-builder has no setter for (publisher, source, site update date, calibre
+    /// series). `dcterms:modified` stays the build time.
+    fn extra_metadata(&self) -> String {
+        let info = &self.bookinfo;
+        let mut xml = String::new();
+        if let Some(publisher) = &info.publisher {
+            xml.push_str(&format!(
+                "    <dc:publisher>{}</dc:publisher>\n",
+                escape(publisher)
+            ));
+        }
+        if !info.url.is_empty() {
+            xml.push_str(&format!(
+                "    <dc:source>{}</dc:source>\n",
+                escape(&info.url)
+            ));
+        }
+        if let Some(updated) = info.updated {
+            xml.push_str(&format!(
+                "    <meta name=\"wnrake:updated\" content=\"{}\"/>\n",
+                updated.format("%Y-%m-%dT%H:%M:%SZ")
+            ));
+        }
+        if let Some(series) = &info.series {
+            xml.push_str(&format!(
+                "    <meta name=\"calibre:series\" content=\"{}\"/>\n",
+                escape(series)
+            ));
+            if let Some(index) = info.series_index {
+                xml.push_str(&format!(
+                    "    <meta name=\"calibre:series_index\" content=\"{}\"/>\n",
+                    index
+                ));
+            }
+        }
+        xml
+    }
+}
+
+/// EPUB 3.0.1 core media types of the supported fonts
+fn font_media_type(path: &str) -> Option<&'static str> {
+    match Path::new(path)
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .map(|ext| ext.to_lowercase())
+        .as_deref()
+    {
+        Some("ttf") => Some("application/font-sfnt"),
+        Some("otf") => Some("application/vnd.ms-opentype"),
+        _ => None,
+    }
+}
+
+/// Escapes text for OPF elements and attributes
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
 }
@@ -0,0 +1,35 @@
+//! EPUB package post-processing
+
+use crate::error::Error;
+use std::io::{Cursor, Read, Write};
+use zip::{CompressionMethod, ZipArchive, ZipWriter, write::SimpleFileOptions};
+
+/// Rewrites one text entry of an EPUB. `mimetype` stays the first, uncompressed entry.
+pub fn rewrite_entry<F>(epub: &[u8], name: &str, f: F) -> Result<Vec<u8>, Error>
+where
+    F: FnOnce(String) -> String,
+{
+    let mut f = Some(f);
+    let mut archive = ZipArchive::new(Cursor::new(epub))?;
+    let mut writer = ZipWriter::new(Cursor::new(Vec::new()));
+    for i in 0..archive.len() {
+        let mut entry = archive.by_index(i)?;
+        let options = match entry.name() {
+            "mimetype" => {
+                SimpleFileOptions::default().compression_method(CompressionMethod::Stored)
+            }
+            _ => SimpleFileOptions::default().compression_method(CompressionMethod::Deflated),
+        };
+        let mut data = Vec::new();
+        entry.read_to_end(&mut data)?;
+        if entry.name() == name
+            && let Some(f) = f.take()
+        {
+            let text = String::from_utf8(data).map_err(Error::epub)?;
+            data = f(text).into_bytes();
+        }
+        writer.start_file(entry.name(), options)?;
+        writer.write_all(&data)?;
+    }
+    Ok(writer.finish()?.into_inner())
+}
@@ -0,0 +1,613 @@
+//! EPUB validation
+//!
+//! Structural checks for the books wnrake writes: package layout, manifest, spine and
+//! navigation consistency, links and XHTML well-formedness.
+
+use crate::error::Error;
+use image::ImageFormat;
+use roxmltree::{Document, Node, ParsingOptions};
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    fmt,
+    io::{Cursor, Read},
+    path::Path,
+};
+use zip::{CompressionMethod, ZipArchive};
+
+const MIMETYPE: &str = "application/epub+zip";
+const CONTAINER: &str = "META-INF/container.xml";
+const XHTML: &str = "application/xhtml+xml";
+const NCX: &str = "application/x-dtbncx+xml";
+
+/// A problem found in an EPUB
+#[derive(Clone, Debug)]
+pub struct Problem {
+    /// Path inside the EPUB
+    pub file: String,
+
+    /// 1-based line, if the problem is inside a document
+    pub line: Option<u32>,
+
+    pub message: String,
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.line {
+            Some(line) => write!(f, "{}:{}: {}", self.file, line, self.message),
+            None => write!(f, "{}: {}", self.file, self.message),
+        }
+    }
+}
+
+/// Manifest item
+#[derive(Clone, Debug)]
+struct Item {
+    href: String,
+    media_type: String,
+    properties: String,
+    line: u32,
+}
+
+/// Validates an EPUB. Returns the problems found, an error only if it is not a ZIP file.
+pub fn validate(epub: &[u8]) -> Result<Vec<Problem>, Error> {
+    let mut archive = ZipArchive::new(Cursor::new(epub))?;
+    let mut files = BTreeMap::new();
+    for i in 0..archive.len() {
+        let mut entry = archive.by_index(i)?;
+        let mut data = Vec::new();
+        entry.read_to_end(&mut data)?;
+        files.insert(entry.name().to_string(), data);
+    }
+    let mut validator = Validator {
+        files,
+        problems: Vec::new(),
+    };
+
+    // mimetype must be the first, uncompressed entry
+    let first = archive.by_index(0)?;
+    if first.name() != "mimetype" {
+        validator.problem("mimetype", None, "must be the first entry of the archive");
+    } else if first.compression() != CompressionMethod::Stored {
+        validator.problem("mimetype", None, "must be stored uncompressed");
+    }
+    drop(first);
+    match validator.files.get("mimetype") {
+        Some(data) if data.as_slice() == MIMETYPE.as_bytes() => {}
+        Some(_) => validator.problem("mimetype", None, format!("must contain `{}`", MIMETYPE)),
+        None => validator.problem("mimetype", None, "missing"),
+    }
+
+    if let Some(opf) = validator.container() {
+        validator.package(&opf);
+    }
+    Ok(validator.problems)
+}
+
+struct Validator {
+    files: BTreeMap<String, Vec<u8>>,
+    problems: Vec<Problem>,
+}
+
+impl Validator {
+    fn problem(&mut self, file: &str, line: Option<u32>, message: impl Into<String>) {
+        self.problems.push(Problem {
+            file: file.to_string(),
+            line,
+            message: message.into(),
+        });
+    }
+
+    /// Returns the file as text if it exists and is UTF-8
+    fn text(&mut self, file: &str) -> Option<String> {
+        let data = match self.files.get(file) {
+            Some(data) => data.clone(),
+            None => {
+                self.problem(file, None, "missing");
+                return None;
+            }
+        };
+        match String::from_utf8(data) {
+            Ok(text) => Some(text),
+            Err(_) => {
+                self.problem(file, None, "not UTF-8");
+                None
+            }
+        }
+    }
+
+    /// Parses an XML file, reporting where it is not well-formed
+    fn parse<'a>(&mut self, file: &str, text: &'a str) -> Option<Document<'a>> {
+        let options = ParsingOptions {
+            allow_dtd: true,
+            ..ParsingOptions::default()
+        };
+        match Document::parse_with_options(text, options) {
+            Ok(document) => Some(document),
+            Err(e) => {
+                self.problem(file, Some(e.pos().row), format!("not well-formed: {}", e));
+                None
+            }
+        }
+    }
+
+    /// Returns the package document path from `container.xml`
+    fn container(&mut self) -> Option<String> {
+        let text = self.text(CONTAINER)?;
+        let document = self.parse(CONTAINER, &text)?;
+        let rootfile = document
+            .descendants()
+            .find(|node| node.has_tag_name("rootfile"));
+        let rootfile = match rootfile {
+            Some(rootfile) => rootfile,
+            None => {
+                self.problem(CONTAINER, None, "no rootfile");
+                return None;
+            }
+        };
+        let line = line(&document, rootfile);
+        if rootfile.attribute("media-type") != Some("application/oebps-package+xml") {
+            self.problem(
+                CONTAINER,
+                Some(line),
+                "rootfile media-type must be application/oebps-package+xml",
+            );
+        }
+        match rootfile.attribute("full-path") {
+            Some(path) if self.files.contains_key(path) => Some(path.to_string()),
+            Some(path) => {
+                self.problem(
+                    CONTAINER,
+                    Some(line),
+                    format!("rootfile {} is missing", path),
+                );
+                None
+            }
+            None => {
+                self.problem(CONTAINER, Some(line), "rootfile has no full-path");
+                None
+            }
+        }
+    }
+
+    /// Checks the package document and everything it lists
+    fn package(&mut self, opf: &str) {
+        let Some(text) = self.text(opf) else {
+            return;
+        };
+        let Some(document) = self.parse(opf, &text) else {
+            return;
+        };
+        let version = document.root_element().attribute("version").unwrap_or("");
+        let base = parent_dir(opf);
+
+        // Manifest
+        let mut items = HashMap::new();
+        for node in document.descendants().filter(|n| n.has_tag_name("item")) {
+            let line = line(&document, node);
+            let (Some(id), Some(href), Some(media_type)) = (
+                node.attribute("id"),
+                node.attribute("href"),
+                node.attribute("media-type"),
+            ) else {
+                self.problem(opf, Some(line), "item needs id, href and media-type");
+                continue;
+            };
+            let item = Item {
+                href: resolve(&base, href),
+                media_type: media_type.to_string(),
+                properties: node.attribute("properties").unwrap_or("").to_string(),
+                line,
+            };
+            if items.insert(id.to_string(), item).is_some() {
+                self.problem(opf, Some(line), format!("duplicate item id `{}`", id));
+            }
+        }
+        let mut listed = HashSet::new();
+        let mut ids = items.keys().cloned().collect::<Vec<_>>();
+        ids.sort_by_key(|id| items[id].line);
+        for id in ids.iter() {
+            let item = items[id].clone();
+            if !listed.insert(item.href.clone()) {
+                self.problem(
+                    opf,
+                    Some(item.line),
+                    format!("{} is listed twice", item.href),
+                );
+            }
+            match self.files.get(&item.href) {
+                Some(data) => {
+                    if let Some(expected) = media_type(&item.href, data)
+                        && !expected.contains(&item.media_type.as_str())
+                    {
+                        self.problem(
+                            opf,
+                            Some(item.line),
+                            format!(
+                                "{} is declared as {} but is {}",
+                                item.href, item.media_type, expected[0]
+                            ),
+                        );
+                    }
+                }
+                None => self.problem(opf, Some(item.line), format!("{} is missing", item.href)),
+            }
+        }
+        for file in self.files.keys().cloned().collect::<Vec<_>>() {
+            if file != "mimetype"
+                && !file.starts_with("META-INF/")
+                && file != opf
+                && !listed.contains(&file)
+            {
+                self.problem(&file, None, "not listed in the manifest");
+            }
+        }
+
+        // Spine
+        let spine = document.descendants().find(|n| n.has_tag_name("spine"));
+        match spine {
+            Some(spine) => {
+                if let Some(toc) = spine.attribute("toc") {
+                    match items.get(toc) {
+                        Some(item) if item.media_type == NCX => {}
+                        _ => self.problem(
+                            opf,
+                            Some(line(&document, spine)),
+                            format!("spine toc `{}` is not an NCX item", toc),
+                        ),
+                    }
+                }
+                let itemrefs = spine
+                    .children()
+                    .filter(|n| n.has_tag_name("itemref"))
+                    .collect::<Vec<_>>();
+                if itemrefs.is_empty() {
+                    self.problem(opf, Some(line(&document, spine)), "spine is empty");
+                }
+                for itemref in itemrefs {
+                    let line = line(&document, itemref);
+                    let idref = itemref.attribute("idref").unwrap_or("");
+                    match items.get(idref) {
+                        Some(item) if item.media_type == XHTML => {}
+                        Some(item) => self.problem(
+                            opf,
+                            Some(line),
+                            format!("spine item {} is not XHTML", item.href),
+                        ),
+                        None => self.problem(
+                            opf,
+                            Some(line),
+                            format!("spine refers to unknown item `{}`", idref),
+                        ),
+                    }
+                }
+            }
+            None => self.problem(opf, None, "no spine"),
+        }
+
+        // Navigation document (EPUB 3)
+        let navs = items
+            .values()
+            .filter(|item| item.properties.split_whitespace().any(|p| p == "nav"))
+            .collect::<Vec<_>>();
+        if version.starts_with('3') && navs.len() != 1 {
+            self.problem(
+                opf,
+                None,
+                format!("needs exactly one nav item, found {}", navs.len()),
+            );
+        }
+        let nav = navs.first().map(|item| item.href.clone());
+
+        // Content documents and the NCX
+        let mut documents = items
+            .values()
+            .filter(|item| item.media_type == XHTML || item.media_type == NCX)
+            .map(|item| item.href.clone())
+            .collect::<Vec<_>>();
+        documents.sort();
+        let mut anchors = HashMap::new();
+        let mut links = Vec::new();
+        for file in documents.iter() {
+            if let Some(text) = self.text(file)
+                && let Some(document) = self.parse(file, &text)
+            {
+                anchors.insert(file.clone(), self.ids(file, &document));
+                links.extend(self.links(file, &document));
+            }
+        }
+        if let Some(nav) = &nav
+            && let Some(text) = self.text(nav)
+            && let Some(document) = self.parse(nav, &text)
+            && !document.descendants().any(|n| {
+                n.has_tag_name("nav")
+                    && n.attributes().any(|a| {
+                        a.name() == "type" && a.value().split_whitespace().any(|t| t == "toc")
+                    })
+            })
+        {
+            self.problem(nav, None, "no toc nav element");
+        }
+
+        // Links
+        for (file, line, target) in links {
+            let (path, fragment) = match target.split_once('#') {
+                Some((path, fragment)) => (path.to_string(), Some(fragment.to_string())),
+                None => (target.clone(), None),
+            };
+            let path = match path.is_empty() {
+                true => file.clone(),
+                false => resolve(&parent_dir(&file), &path),
+            };
+            if !listed.contains(&path) {
+                let message = match self.files.contains_key(&path) {
+                    true => format!("links to {}, which is not in the manifest", path),
+                    false => format!("broken link to {}", path),
+                };
+                self.problem(&file, Some(line), message);
+                continue;
+            }
+            if let Some(fragment) = fragment
+                && !fragment.is_empty()
+                && let Some(ids) = anchors.get(&path)
+                && !ids.contains(&fragment)
+            {
+                self.problem(
+                    &file,
+                    Some(line),
+                    format!("broken link to {}#{}", path, fragment),
+                );
+            }
+        }
+    }
+
+    /// Returns the IDs of a document, reporting duplicates
+    fn ids(&mut self, file: &str, document: &Document) -> HashSet<String> {
+        let mut ids = HashSet::new();
+        for node in document.descendants().filter(|n| n.is_element()) {
+            if let Some(id) = node.attribute("id")
+                && !ids.insert(id.to_string())
+            {
+                self.problem(
+                    file,
+                    Some(line(document, node)),
+                    format!("duplicate id `{}`", id),
+                );
+            }
+        }
+        ids
+    }
+
+    /// Returns the internal links of a document (file, line, target)
+    fn links(&self, file: &str, document: &Document) -> Vec<(String, u32, String)> {
+        document
+            .descendants()
+            .filter(|n| n.is_element())
+            .flat_map(|node| {
+                ["href", "src"]
+                    .into_iter()
+                    .filter_map(move |attr| node.attribute(attr).map(|value| (node, value)))
+            })
+            .filter(|(_, value)| !value.contains(':'))
+            .map(|(node, value)| (file.to_string(), line(document, node), value.to_string()))
+            .collect()
+    }
+}
+
+fn line(document: &Document, node: Node) -> u32 {
+    document.text_pos_at(node.range().start).row
+}
+
+/// Directory of a path inside the EPUB, with a trailing slash
+fn parent_dir(path: &str) -> String {
+    match path.rfind('/') {
+        Some(i) => path[..=i].to_string(),
+        None => String::new(),
+    }
+}
+
+/// Resolves a relative href (`../images/x.png`) against a directory
+fn resolve(base: &str, href: &str) -> String {
+    let mut parts = base
+        .split('/')
+        .filter(|p| !p.is_empty())
+        .collect::<Vec<_>>();
+    let href = percent_decode(href);
+    for part in href.split('/') {
+        match part {
+            "" | "." => {}
+            ".." => {
+                parts.pop();
+            }
+            part => parts.push(part),
+        }
+    }
+    parts.join("/")
+}
+
+fn percent_decode(text: &str) -> String {
+    let bytes = text.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%'
+            && let Some(byte) = text
+                .get(i + 1..i + 3)
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+        {
+            out.push(byte);
+            i += 3;
+        } else {
+            out.push(bytes[i]);
+            i += 1;
+        }
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+/// Media types a file may be declared as, the first being the preferred one. `None` if the
+/// type is not checked.
+fn media_type(path: &str, data: &[u8]) -> Option<&'static [&'static str]> {
+    let extension = Path::new(path)
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .map(|ext| ext.to_lowercase());
+    match extension.as_deref() {
+        Some("xhtml") | Some("html") | Some("htm") => Some(&[XHTML]),
+        Some("css") => Some(&["text/css"]),
+        Some("ncx") => Some(&[NCX]),
+        Some("svg") => Some(&["image/svg+xml"]),
+        Some("ttf") => Some(&[
+            "font/ttf",
+            "application/font-sfnt",
+            "application/x-font-ttf",
+        ]),
+        Some("otf") => Some(&[
+            "font/otf",
+            "application/font-sfnt",
+            "application/vnd.ms-opentype",
+        ]),
+        Some("woff") => Some(&["font/woff", "application/font-woff"]),
+        Some("woff2") => Some(&["font/woff2"]),
+        _ => match image::guess_format(data) {
+            Ok(ImageFormat::Jpeg) => Some(&["image/jpeg"]),
+            Ok(ImageFormat::Png) => Some(&["image/png"]),
+            Ok(ImageFormat::Gif) => Some(&["image/gif"]),
+            Ok(ImageFormat::WebP) => Some(&["image/webp"]),
+            _ => None,
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use zip::{ZipWriter, write::SimpleFileOptions};
+
+    const CONTAINER_XML: &str = r#"<?xml version="1.0"?>
+<container version="1.0" xmlns="urn:oasis:names:tc:opendocument:xmlns:container">
+<rootfiles><rootfile full-path="OEBPS/content.opf" media-type="application/oebps-package+xml"/></rootfiles>
+</container>"#;
+
+    const NAV: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<html xmlns="http://www.w3.org/1999/xhtml" xmlns:epub="http://www.idpf.org/2007/ops"><head><title>Nav</title></head>
+<body><nav epub:type="toc"><ol><li><a href="chapter.xhtml#start">Chapter</a></li></ol></nav></body>
+</html>"#;
+
+    fn opf(chapter_type: &str) -> String {
+        format!(
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<package xmlns="http://www.idpf.org/2007/opf" version="3.0">
+<manifest>
+<item id="nav" href="nav.xhtml" media-type="application/xhtml+xml" properties="nav"/>
+<item id="chapter" href="chapter.xhtml" media-type="{}"/>
+</manifest>
+<spine><itemref idref="nav"/><itemref idref="chapter"/></spine>
+</package>"#,
+            chapter_type
+        )
+    }
+
+    fn chapter(body: &str) -> String {
+        format!(
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<html xmlns="http://www.w3.org/1999/xhtml"><head><title>Chapter</title></head>
+<body>{}</body>
+</html>"#,
+            body
+        )
+    }
+
+    /// Zips the files in order, compressing those marked so
+    fn zip(files: &[(&str, String, bool)]) -> Vec<u8> {
+        let mut writer = ZipWriter::new(Cursor::new(Vec::new()));
+        for (name, data, compressed) in files {
+            let method = match compressed {
+                true => CompressionMethod::Deflated,
+                false => CompressionMethod::Stored,
+            };
+            writer
+                .start_file(
+                    *name,
+                    SimpleFileOptions::default().compression_method(method),
+                )
+                .unwrap();
+            writer.write_all(data.as_bytes()).unwrap();
+        }
+        writer.finish().unwrap().into_inner()
+    }
+
+    /// Builds a book with the chapter's body and media type
+    fn book(body: &str, chapter_type: &str) -> Vec<(&'static str, String, bool)> {
+        vec![
+            ("mimetype", MIMETYPE.into(), false),
+            (CONTAINER, CONTAINER_XML.into(), true),
+            ("OEBPS/content.opf", opf(chapter_type), true),
+            ("OEBPS/nav.xhtml", NAV.into(), true),
+            ("OEBPS/chapter.xhtml", chapter(body), true),
+        ]
+    }
+
+    fn messages(files: &[(&str, String, bool)]) -> Vec<String> {
+        validate(&zip(files))
+            .unwrap()
+            .iter()
+            .map(|problem| problem.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn valid_book() {
+        let files = book(r#"<p id="start">Text</p>"#, XHTML);
+        assert_eq!(messages(&files), Vec::<String>::new());
+    }
+
+    #[test]
+    fn mimetype() {
+        let mut files = book(r#"<p id="start">Text</p>"#, XHTML);
+        files.swap(0, 1);
+        assert_eq!(
+            messages(&files),
+            ["mimetype: must be the first entry of the archive"]
+        );
+
+        let mut files = book(r#"<p id="start">Text</p>"#, XHTML);
+        files[0].2 = true;
+        assert_eq!(messages(&files), ["mimetype: must be stored uncompressed"]);
+    }
+
+    #[test]
+    fn broken_links() {
+        let files = book(
+            r#"<p id="start"><a href="missing.xhtml">a</a> <a href="nav.xhtml#nowhere">b</a></p>"#,
+            XHTML,
+        );
+        assert_eq!(
+            messages(&files),
+            [
+                "OEBPS/chapter.xhtml:3: broken link to OEBPS/missing.xhtml",
+                "OEBPS/chapter.xhtml:3: broken link to OEBPS/nav.xhtml#nowhere",
+            ]
+        );
+    }
+
+    #[test]
+    fn duplicate_id() {
+        let files = book(r#"<p id="start">a</p><p id="start">b</p>"#, XHTML);
+        assert_eq!(
+            messages(&files),
+            ["OEBPS/chapter.xhtml:3: duplicate id `start`"]
+        );
+    }
+
+    #[test]
+    fn media_type_mismatch() {
+        let files = book(r#"<p id="start">Text</p>"#, "text/css");
+        let messages = messages(&files);
+        assert!(messages.contains(
+            &"OEBPS/content.opf:5: OEBPS/chapter.xhtml is declared as text/css but is application/xhtml+xml"
+                .to_string()
+        ));
+    }
+}
@@ -1,9 +1,17 @@
 //! client
 
-use crate::error::Error;
-use crawler::{cache::Cache, config::Config, Client, Request};
+use crate::{error::Error, interrupt};
+use crawler::{
+    Client, Request,
+    cache::Cache,
+    config::Config,
+    layer::{
+        BoxFetch, CacheLayer, ChallengeLayer, Fetch, RateLimitLayer, RetryLayer, Stack, TraceLayer,
+    },
+};
 
-#[derive(Clone, Debug)]
+/// Cloning is cheap, clones share the same sessions and concurrency limit
+#[derive(Clone)]
 pub struct WnrakeClient {
     /// Client
     pub client: Client,
@@ -13,101 +21,92 @@ pub struct WnrakeClient {
 
     /// Download attempts
     pub attempts: usize,
+
+    /// Layered fetcher built around `client`
+    fetcher: BoxFetch,
+}
+
+impl std::fmt::Debug for WnrakeClient {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("WnrakeClient")
+            .field("client", &self.client)
+            .field("cache", &self.cache)
+            .field("attempts", &self.attempts)
+            .finish()
+    }
 }
 
 impl WnrakeClient {
     /// Build `WnrakeClient` from `Config`
     pub fn from_config(config: &Config) -> Result<Self, Error> {
-        let cache = match config.has_cache() {
-            true => Some(config.to_cache()?),
-            false => None,
-        };
-        Ok(Self {
-            client: config.to_client()?,
-            cache,
-            attempts: 5,
-        })
+        Self::build(config, config.to_client()?, config.has_cache())
     }
 
     /// Build `WnrakeClient` from `Config`
     pub fn from_config_with_proxy(config: &Config, proxy: &str) -> Result<Self, Error> {
-        let cache = match config.has_cache() {
+        Self::build(
+            config,
+            config.to_client_with_proxy(proxy)?,
+            config.has_cache(),
+        )
+    }
+
+    /// Build `WnrakeClient` from `Config`, always fetching fresh pages
+    pub fn from_config_without_cache(config: &Config) -> Result<Self, Error> {
+        Self::build(config, config.to_client()?, false)
+    }
+
+    /// The wnrake stack, from the outside in: cache, trace, retry, rate-limit, challenge-detect
+    fn build(config: &Config, client: Client, use_cache: bool) -> Result<Self, Error> {
+        let cache = match use_cache {
             true => Some(config.to_cache()?),
             false => None,
         };
+        let attempts = 5;
+        let stack = Stack::new(client.clone())
+            .layer(ChallengeLayer::new())
+            .layer(RateLimitLayer::new(config.rate_limit()))
+            .layer(RetryLayer::new(attempts).recover_timeout(config.recover_timeout()))
+            .layer(TraceLayer::new());
+        let fetcher = match &cache {
+            Some(cache) => stack.layer(CacheLayer::new(cache.clone())).boxed(),
+            None => stack.boxed(),
+        };
         Ok(Self {
-            client: config.to_client_with_proxy(proxy)?,
+            client,
             cache,
-            attempts: 5,
+            attempts,
+            fetcher,
         })
     }
 
     /// Processes download request
-    pub async fn request(&mut self, request: &Request) -> Result<String, Error> {
-        let url = request.url.clone();
-        let resource = match &self.cache {
-            Some(cache) => match cache.get(&url)? {
-                Some(res) => {
-                    log::debug!("{} found in cache", &url);
-                    Some(res)
-                }
-                None => {
-                    log::debug!("{} not found in cache", &url);
-                    None
-                }
-            },
-            None => None,
+    pub async fn request(&self, request: &Request) -> Result<String, Error> {
+        let solution = tokio::select! {
+            res = self.fetcher.fetch(request) => res?,
+            _ = interrupt::interrupted() => return Err(Error::interrupted()),
         };
-        let resource = resource.unwrap_or(self.n_requests(request).await?);
-        if let Some(cache) = &mut self.cache {
-            cache.insert(&url, resource.as_bytes())?;
-        }
-        Ok(resource)
-    }
-
-    #[inline]
-    async fn n_requests(&mut self, request: &Request) -> Result<String, Error> {
-        let mut attempts = 0;
-        loop {
-            match self._request(request).await {
-                Ok(res) => return Ok(res),
-                Err(mut e) => match e.fatal {
-                    true => {
-                        log::error!("fatal: {}", e);
-                        return Err(e);
-                    }
-                    false => {
-                        attempts = attempts + 1;
-                        log::error!("({}/{}) attempts: {}", attempts, self.attempts, e);
-                        if attempts >= self.attempts {
-                            e.fatal = true;
-                            return Err(e);
-                        }
-                        self.client.recover(60).await?;
-                    }
-                },
-            }
+        match solution.status {
+            200 => Ok(solution.response),
+            status => Err(Error::status(format!("returned HTTP status {}", status))),
         }
     }
 
-    #[inline]
-    async fn _request(&mut self, request: &Request) -> Result<String, Error> {
-        match self.client.request(request).await {
-            Ok(solution) => match solution.status {
-                200 => Ok(solution.response),
-                status => Err(Error::status(format!("returned HTTP status {}", status))),
-            },
-            Err(e) => Err(e.into()),
+    /// Downloads binary content (bypasses the solver and the cache, keeps the rate limit)
+    pub async fn download(&self, url: &str, accept: &str) -> Result<Vec<u8>, Error> {
+        tokio::select! {
+            res = self.fetcher.download(url, accept) => Ok(res?),
+            _ = interrupt::interrupted() => Err(Error::interrupted()),
         }
     }
 
     /// Convenience function for the typical HTTP GET
-    pub async fn get(&mut self, url: &str) -> Result<String, Error> {
+    pub async fn get(&self, url: &str) -> Result<String, Error> {
         self.request(&Request::get(url).build()).await
     }
 
     /// Convenience function for the typical HTTP POST
-    pub async fn post(&mut self, url: &str, post_data: &[(&str, &str)]) -> Result<String, Error> {
+    pub async fn post(&self, url: &str, post_data: &[(&str, &str)]) -> Result<String, Error> {
         self.request(&Request::post(url).post_data(post_data).build())
             .await
     }
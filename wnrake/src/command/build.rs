@@ -1,28 +1,90 @@
 //! build command
 
 use crate::{
-    book::{BookInfo, ChapterList, EpubBook},
+    book::{BookStatus, ChapterInfo, ChapterList, EpubBook, NoteMode, Style, Templates, Theme},
+    command::{Selection, validate},
     error::Error,
+    utils::ChapterRange,
+    workspace::Workspace,
 };
 use clap::Args;
 use crawler::config::Config;
 use std::{
-    fs::{File, remove_file, rename},
-    path::Path,
+    collections::{BTreeSet, HashMap},
+    fs::{self, remove_file, rename},
+    path::{self, PathBuf},
     process::Command,
 };
 
 #[derive(Args, Clone, Debug)]
 pub struct Build {
-    /// Appends `(Ongoing)` to the title
-    #[arg(long)]
+    /// Only builds these chapters (e.g. `100-250`), like `--only`
+    #[arg(long, value_name = "RANGE", conflicts_with_all = ["from", "to", "only"])]
+    chapters: Option<ChapterRange>,
+
+    #[command(flatten)]
+    selection: Selection,
+
+    #[command(flatten)]
+    options: BuildOptions,
+}
+
+/// How the book is built, shared with `update` (which builds every chapter)
+#[derive(Args, Clone, Debug)]
+pub struct BuildOptions {
+    /// Overrides the publication status
+    #[arg(long, value_enum)]
+    status: Option<BookStatus>,
+
+    /// Same as `--status ongoing`
+    #[arg(long, conflicts_with = "status")]
     ongoing: bool,
 
-    /// Appends `(Hiatus)` to the title
-    #[arg(long)]
+    /// Same as `--status hiatus`
+    #[arg(long, conflicts_with = "status")]
     hiatus: bool,
 
-    /// Fixes some EPUB issues
+    /// Adds a title page before each volume
+    #[arg(long)]
+    volume_pages: bool,
+
+    /// Adds a closing page (always added if the book has a `templates/closing.txt`)
+    #[arg(long)]
+    closing_page: bool,
+
+    /// What to do with author and translator notes
+    #[arg(long, value_enum, default_value_t)]
+    notes: NoteMode,
+
+    /// Stylesheet theme
+    #[arg(long, value_enum)]
+    theme: Option<Theme>,
+
+    /// Appends a CSS file to the stylesheet
+    #[arg(long, value_name = "FILE")]
+    css: Option<String>,
+
+    /// Embeds a TTF/OTF font; fonts named `Family-Bold.ttf` etc. become variants of `Family`
+    #[arg(long = "font", value_name = "FILE")]
+    fonts: Vec<String>,
+
+    /// Saves --theme, --css and --font as this book's style
+    #[arg(long)]
+    save_style: bool,
+
+    /// Splits the book every N chapters
+    #[arg(long, value_name = "N", conflicts_with = "split_size", value_parser = clap::value_parser!(u64).range(1..))]
+    split_chapters: Option<u64>,
+
+    /// Splits the book into parts of about N megabytes
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(u64).range(1..))]
+    split_size: Option<u64>,
+
+    /// Splits the book into one part per volume
+    #[arg(long, conflicts_with_all = ["split_chapters", "split_size"])]
+    split_volumes: bool,
+
+    /// Also runs the EPUB through Calibre's `ebook-convert` (optional, the EPUB is valid without it)
     #[arg(long)]
     epub: bool,
 
@@ -32,66 +94,247 @@ pub struct Build {
 }
 
 impl Build {
-    pub fn execute<'a>(&self, _config: &Config) -> Result<(), Error> {
-        let mut bookinfo = BookInfo::from_file("bookinfo.txt")?;
-        let chapterlist = ChapterList::from_file("chapterlist.txt")?;
-        let cover = {
-            let path = Path::new("cover.jpg");
-            match path.is_file() {
-                true => Some(File::open(path)?),
-                false => None,
-            }
+    pub fn execute(&self, config: &Config, workspace: &Workspace) -> Result<(), Error> {
+        let selection = self.selection.with_range(self.chapters);
+        self.options.execute(config, workspace, &selection)
+    }
+}
+
+impl BuildOptions {
+    /// Builds the selected chapters
+    pub fn execute(
+        &self,
+        _config: &Config,
+        workspace: &Workspace,
+        selection: &Selection,
+    ) -> Result<(), Error> {
+        let mut manifest = workspace.load()?;
+        let style = self.style(&manifest.style)?;
+        if self.save_style {
+            manifest.style = style.clone();
+            workspace.save(&manifest)?;
+        }
+        let templates = Templates::load(&workspace.templates_dir(), self.closing_page)?;
+        let downloaded = manifest
+            .chapters
+            .iter()
+            .filter_map(|chapter| chapter.downloaded_at)
+            .max();
+        let user_css = match &style.css {
+            Some(css) => Some(fs::read_to_string(workspace.path(css))?),
+            None => None,
+        };
+        let selected = selection.resolve(&manifest.chapters)?;
+        let chapterlist = manifest.chapter_list(workspace.root(), &selected)?;
+        if chapterlist.as_ref().is_empty() {
+            return Err(Error::parser("no chapters to build"));
+        }
+        let mut bookinfo = manifest.book.clone();
+        let cover = match workspace.cover_path() {
+            Some(path) => Some(fs::read(path)?),
+            None => None,
         };
 
         log::info!("Title: {}", bookinfo.title);
         log::info!("Author: {}", bookinfo.author);
         log::info!("Chapters: {}", chapterlist.as_ref().len());
+        if self.hiatus {
+            bookinfo.status = Some(BookStatus::Hiatus);
+        } else if self.ongoing {
+            bookinfo.status = Some(BookStatus::Ongoing);
+        } else if self.status.is_some() {
+            bookinfo.status = self.status;
+        }
+        if let Some(status) = bookinfo.status {
+            log::info!("Status: {}", status);
+        }
         if cover.is_none() {
             log::warn!("No cover found");
         }
-        log::info!("Building epub ...");
 
-        if self.hiatus {
-            bookinfo.title = format!("{} (Hiatus)", bookinfo.title.as_str());
-        } else if self.ongoing {
-            bookinfo.title = format!("{} (Ongoing)", bookinfo.title.as_str());
+        // Chapter images, shipped only with the parts that use them
+        let mut images = Vec::new();
+        for path in manifest.images.values().collect::<BTreeSet<_>>() {
+            let file = workspace.path(path);
+            match file.is_file() {
+                true => images.push((path.as_str(), file)),
+                false => log::warn!("missing image {}", path),
+            }
+        }
+
+        let parts = self.split(chapterlist, &images)?;
+        let title = match selection.is_all() {
+            true => bookinfo.title.clone(),
+            false => format!("{} - Ch {}", bookinfo.title, Selection::label(&selected)),
+        };
+        let total = parts.len();
+        for (i, part) in parts.into_iter().enumerate() {
+            let mut bookinfo = bookinfo.clone();
+            let label = format!("Vol {}", i + 1);
+            bookinfo.title = match total {
+                1 => title.clone(),
+                _ => format!("{} - {}", title, label),
+            };
+            if total > 1 {
+                log::info!("{}: {} chapters", label, part.as_ref().len());
+                // The parts form a series unless the book is already part of one
+                if bookinfo.series.is_none() {
+                    bookinfo.series = Some(title.clone());
+                    bookinfo.series_index = Some((i + 1) as f64);
+                }
+            }
+            let filename = workspace
+                .path(format!("{}.epub", bookinfo.title.as_str()))
+                .to_string_lossy()
+                .to_string();
+            let used = referenced_images(&part, &images)?;
+            let mut epub = EpubBook::new(bookinfo, part, cover.clone());
+            epub.volume_pages(self.volume_pages);
+            epub.templates(templates.clone());
+            epub.downloaded(downloaded);
+            epub.notes(self.notes);
+            epub.theme(style.theme);
+            if let Some(css) = &user_css {
+                epub.user_css(css.as_str());
+            }
+            for font in style.fonts.iter() {
+                epub.add_font(workspace.path(font))?;
+            }
+            if total > 1 {
+                epub.cover_label(label);
+            }
+            for (path, file) in used {
+                epub.add_image(path, file.clone());
+            }
+            log::info!("Building {} ...", filename);
+            epub.to_file(filename.as_str())?;
+            validate::check(&filename)?;
+            self.convert(&filename);
         }
 
-        let filename = format!("{}.epub", bookinfo.title.as_str());
-        let epub = EpubBook::new(bookinfo, chapterlist, cover);
-        epub.to_file(filename.as_str())?;
+        log::info!("Complete");
+        Ok(())
+    }
 
+    /// Style options override the book's style. Files given on the command line are made
+    /// absolute: they are used as given, and the book's files are relative to the workspace.
+    fn style(&self, book: &Style) -> Result<Style, Error> {
+        let absolute = |path: &String| -> Result<String, Error> {
+            Ok(path::absolute(path)?.to_string_lossy().into())
+        };
+        Ok(Style {
+            theme: self.theme.or(book.theme),
+            css: match &self.css {
+                Some(css) => Some(absolute(css)?),
+                None => book.css.clone(),
+            },
+            fonts: match self.fonts.is_empty() {
+                true => book.fonts.clone(),
+                false => self.fonts.iter().map(absolute).collect::<Result<_, _>>()?,
+            },
+        })
+    }
+
+    /// Splits the chapters into the requested parts
+    fn split(
+        &self,
+        chapterlist: ChapterList,
+        images: &[(&str, PathBuf)],
+    ) -> Result<Vec<ChapterList>, Error> {
+        if let Some(count) = self.split_chapters {
+            Ok(chapterlist.split_when(|part, _| part.len() as u64 >= count))
+        } else if let Some(megabytes) = self.split_size {
+            // Chapter files and their images, before compression
+            let limit = megabytes * 1024 * 1024;
+            let mut sizes = HashMap::new();
+            for chapter in chapterlist.as_ref() {
+                let mut size = fs::metadata(&chapter.path)?.len();
+                for (_, file) in referenced_images(&ChapterList(vec![chapter.clone()]), images)? {
+                    size += fs::metadata(file)?.len();
+                }
+                sizes.insert(chapter.path.clone(), size);
+            }
+            Ok(chapterlist.split_when(|part, chapter| {
+                part.iter()
+                    .chain([chapter])
+                    .map(|chapter| sizes[&chapter.path])
+                    .sum::<u64>()
+                    > limit
+            }))
+        } else if self.split_volumes {
+            Ok(chapterlist.split_when(|part, chapter| {
+                part.last()
+                    .is_some_and(|last: &ChapterInfo| last.volume != chapter.volume)
+            }))
+        } else {
+            Ok(vec![chapterlist])
+        }
+    }
+
+    /// Runs the external conversions on a built book
+    fn convert(&self, filename: &str) {
         if self.epub {
-            log::info!("Converting to EPUB ...");
+            log::info!("Converting to EPUB with ebook-convert ...");
             let tmp_filename = format!("{} (tmp).epub", filename.trim_end_matches(".epub"));
-            match Command::new("ebook-convert")
-                .args([filename.as_str(), tmp_filename.as_str()])
-                .output()
-            {
-                Ok(_) => match remove_file(filename.as_str()) {
-                    Ok(_) => {
-                        if let Err(e) = rename(tmp_filename.as_str(), filename.as_str()) {
-                            log::error!("{}", e);
-                        }
+            match ebook_convert(&[filename, tmp_filename.as_str()]) {
+                Ok(_) => {
+                    if let Err(e) =
+                        remove_file(filename).and_then(|_| rename(&tmp_filename, filename))
+                    {
+                        log::error!("{}", e);
                     }
-                    Err(e) => log::error!("{}", e),
-                },
-                Err(e) => log::error!("{}", e),
+                }
+                Err(e) => {
+                    let _ = remove_file(&tmp_filename);
+                    log::error!("{} (keeping the unconverted EPUB)", e);
+                }
             }
         }
 
         if self.azw3 {
             log::info!("Converting to AZW3 ...");
             let azw3_filename = format!("{}.azw3", filename.trim_end_matches(".epub"));
-            if let Err(e) = Command::new("ebook-convert")
-                .args([filename.as_str(), azw3_filename.as_str(), "--no-inline-toc"])
-                .output()
-            {
+            if let Err(e) = ebook_convert(&[filename, azw3_filename.as_str(), "--no-inline-toc"]) {
                 log::error!("{}", e);
             }
         }
+    }
+}
 
-        log::info!("Complete");
-        Ok(())
+/// Runs Calibre's `ebook-convert`, failing with its stderr if it does not succeed
+fn ebook_convert(args: &[&str]) -> Result<(), Error> {
+    let output = Command::new("ebook-convert")
+        .args(args)
+        .output()
+        .map_err(|e| Error::io(format!("ebook-convert: {}", e)))?;
+    match output.status.success() {
+        true => Ok(()),
+        false => {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            Err(Error::io(format!(
+                "ebook-convert failed ({}): {}",
+                output.status,
+                stderr.trim()
+            )))
+        }
+    }
+}
+
+/// Returns the images the chapters link to
+fn referenced_images<'a>(
+    chapterlist: &ChapterList,
+    images: &'a [(&'a str, PathBuf)],
+) -> Result<Vec<&'a (&'a str, PathBuf)>, Error> {
+    let mut used = vec![false; images.len()];
+    for chapter in chapterlist.as_ref() {
+        let contents = fs::read_to_string(&chapter.path)?;
+        for (i, (path, _)) in images.iter().enumerate() {
+            used[i] |= contents.contains(path);
+        }
     }
+    Ok(images
+        .iter()
+        .zip(used)
+        .filter_map(|(image, used)| used.then_some(image))
+        .collect())
 }
@@ -1,61 +1,74 @@
 //! crawl command
 
 use crate::{
-    book::UrlCache,
+    book::{ChapterEntry, Manifest},
     client::WnrakeClient,
+    command::Selection,
     error::Error,
+    interrupt,
     parser::{Downloader, Parser, WnParser},
     utils,
+    workspace::Workspace,
 };
 use clap::Args;
 use crawler::config::Config;
-use std::{fs::File, io::Write, path::Path};
 
 #[derive(Args, Clone, Debug)]
-pub struct Crawl;
+pub struct Crawl {
+    #[command(flatten)]
+    selection: Selection,
+}
 
 impl Crawl {
-    pub async fn execute<'a>(&self, config: &Config) -> Result<(), Error> {
-        let mut client = WnrakeClient::from_config(config)?;
+    pub async fn execute<'a>(&self, config: &Config, workspace: &Workspace) -> Result<(), Error> {
+        let client = WnrakeClient::from_config(config)?;
 
         log::debug!("Solver={}", client.client.solver());
         log::debug!("Proxy={:?}", client.client.proxy());
         log::debug!("Cache={:?}", &client.cache);
 
         client.client.create_session().await?;
-        let res = self.do_work(&mut client).await;
+        let res = self.do_work(&client, workspace).await;
         client.client.destroy_session().await?;
         res
     }
 
-    async fn do_work(&self, client: &mut WnrakeClient) -> Result<(), Error> {
+    async fn do_work(&self, client: &WnrakeClient, workspace: &Workspace) -> Result<(), Error> {
         // Make staging directory
-        utils::ensure_dir("staging")?;
+        utils::ensure_dir(workspace.staging_dir())?;
 
-        // Load URL cache
-        log::debug!("loading urlcache.txt");
-        let mut url_cache = UrlCache::from_file("urlcache.txt")?;
+        // Load manifest
+        let mut manifest = workspace.load()?;
+        if manifest.chapters.is_empty() {
+            return Err(Error::parser("crawling needs at least one chapter URL"));
+        }
 
-        let res = self.try_do_work(client, &mut url_cache).await;
-        url_cache.to_file("urlcache.txt")?;
+        let res = self.try_do_work(client, workspace, &mut manifest).await;
+        workspace.save(&manifest)?;
         res
     }
 
     async fn try_do_work(
         &self,
-        client: &mut WnrakeClient,
-        url_cache: &mut UrlCache,
+        client: &WnrakeClient,
+        workspace: &Workspace,
+        manifest: &mut Manifest,
     ) -> Result<(), Error> {
-        let url_cache = url_cache.as_mut();
-        let total_chapters = url_cache.len();
+        let chapters = &mut manifest.chapters;
+        let total_chapters = chapters.len();
         log::debug!("total chapters: {}", total_chapters);
+        let selected = self.selection.resolve(chapters)?;
 
         for i in 0..total_chapters - 1 {
-            let url = &url_cache[i];
+            interrupt::check()?;
+            if !selected[i] {
+                continue;
+            }
+            let url = chapters[i].url.clone();
 
             // Get path
-            let filename = utils::url_to_filename(i, url);
-            let path = Path::join(Path::new("staging"), &filename);
+            let staging = chapters[i].staging_file();
+            let path = workspace.path(&staging);
             match path.is_file() {
                 true => {
                     log::info!("({:>4}/{:>4}) Using cached {}", i + 1, total_chapters, url);
@@ -67,21 +80,27 @@ impl Crawl {
 
                     // Download
                     log::info!("({:>4}/{:>4}) Downloading {}", i + 1, total_chapters, url);
-                    let chapter = parser.get_chapter(client, url).await?;
+                    let chapter = parser.get_chapter(client, &url).await?;
 
                     // Write file
-                    let mut file = File::create(path)?;
-                    file.write_all(chapter.as_bytes())?;
+                    utils::write_file(&path, chapter.as_bytes())?;
+                    chapters[i].downloaded(staging, chapter.as_bytes());
                 }
             }
         }
 
+        // Only follow the next page links if the last chapter is selected
+        if !selected[total_chapters - 1] {
+            return Ok(());
+        }
         let mut index = total_chapters - 1;
-        let mut next_url = &url_cache[index];
         loop {
+            interrupt::check()?;
+            let next_url = chapters[index].url.clone();
+
             // Get path
-            let filename = utils::url_to_filename(index, next_url);
-            let path = Path::join(Path::new("staging"), &filename);
+            let staging = chapters[index].staging_file();
+            let path = workspace.path(&staging);
 
             // Load parser
             let parser = WnParser::try_from(next_url.as_str())?;
@@ -89,19 +108,16 @@ impl Crawl {
 
             // Download
             log::info!("({:>4}/   ?) Downloading {}", index + 1, next_url);
-            let chapter = parser.get_chapter(client, next_url).await?;
+            let chapter = parser.get_chapter(client, &next_url).await?;
 
             // Write file
-            let mut file = File::create(path)?;
-            file.write_all(chapter.as_bytes())?;
+            utils::write_file(&path, chapter.as_bytes())?;
+            chapters[index].downloaded(staging, chapter.as_bytes());
 
             // Get next page
             index = index + 1;
             match parser.next_page(&chapter)? {
-                Some(url) => {
-                    url_cache.push(url);
-                    next_url = url_cache.last().expect("UrlCache should not be empty");
-                }
+                Some(url) => chapters.push(ChapterEntry::new(url)),
                 None => break,
             }
         }
@@ -1,6 +1,6 @@
 //! debug command
 
-use crate::{client::WnrakeClient, error::Error};
+use crate::{client::WnrakeClient, error::Error, interrupt};
 use clap::Args;
 use crawler::config::Config;
 use std::io::{self, Write};
@@ -10,19 +10,24 @@ pub struct Debug;
 
 impl Debug {
     pub async fn execute<'a>(&self, config: &Config) -> Result<(), Error> {
-        let mut client = WnrakeClient::from_config(config)?;
+        let client = WnrakeClient::from_config(config)?;
 
         log::debug!("Solver={}", client.client.solver());
         log::debug!("Proxy={:?}", client.client.proxy());
         log::debug!("Cache={:?}", &client.cache);
 
         client.client.create_session().await?;
-        let mut buffer = String::new();
-        io::stdout().write(b"Press [Enter] ")?;
+        io::stdout().write_all(b"Press [Enter] ")?;
         io::stdout().flush()?;
-        let input = io::stdin();
-        input.read_line(&mut buffer)?;
+        let input = tokio::task::spawn_blocking(|| {
+            let mut buffer = String::new();
+            io::stdin().read_line(&mut buffer).map(|_| ())
+        });
+        let res = tokio::select! {
+            res = input => res.map_err(Error::io).and_then(|res| Ok(res?)),
+            _ = interrupt::interrupted() => Err(Error::interrupted()),
+        };
         client.client.destroy_session().await?;
-        Ok(())
+        res
     }
 }
@@ -1,95 +1,102 @@
 //! download command
 
 use crate::{
-    book::UrlCache,
+    book::{ChapterStatus, Manifest},
     client::WnrakeClient,
+    command::Selection,
     error::Error,
+    interrupt,
     parser::{Downloader, WnParser},
     utils,
+    workspace::Workspace,
 };
 use clap::Args;
 use crawler::config::Config;
-use std::{collections::VecDeque, fs::File, io::Write, path::Path, sync::Arc};
+use std::{collections::VecDeque, sync::Arc};
 use tokio::sync::Mutex;
 
+/// Chapter index, URL and staging file
+type Task = (usize, String, String);
+
 #[derive(Args, Clone, Debug)]
 pub struct Download {
     /// Use multiple threads [one for each configured proxy]
     #[arg(short = 't', long)]
     use_threads: bool,
+
+    #[command(flatten)]
+    selection: Selection,
 }
 
 impl Download {
-    pub async fn execute<'a>(&self, config: &Config) -> Result<(), Error> {
-        if self.use_threads {
-            let proxies = config.proxies().map(|k| k.as_str()).collect::<Vec<_>>();
-            let proxy_len = proxies.len();
-            match proxy_len {
-                0 => Err(Error::crawler("must have at least 1 proxy configured")),
-                1 => {
-                    log::warn!("only 1 proxy found, falling back to single thread");
-                    let mut client =
-                        WnrakeClient::from_config_with_proxy(config, proxies.first().unwrap())?;
-                    self.single_thread(&mut client).await
-                }
-                _ => self.multi_thread(config, proxies).await,
-            }
-        } else {
-            let mut client = WnrakeClient::from_config(config)?;
-            self.single_thread(&mut client).await
+    pub fn new(use_threads: bool) -> Self {
+        Download {
+            use_threads,
+            selection: Selection::default(),
         }
     }
 
-    async fn single_thread(&self, client: &mut WnrakeClient) -> Result<(), Error> {
-        log::debug!("Solver={}", client.client.solver());
-        log::debug!("Proxy={:?}", client.client.proxy());
-        log::debug!("Cache={:?}", &client.cache);
-
-        client.client.create_session().await?;
-        let res = async || -> Result<(), Error> {
-            // Make staging directory
-            utils::ensure_dir("staging")?;
-
-            // Load URL cache
-            log::debug!("loading urlcache.txt");
-            let url_cache = UrlCache::from_file("urlcache.txt")?;
-            let total_chapters = url_cache.as_ref().len();
-            log::debug!("total chapters: {}", total_chapters);
-
-            for (i, url) in url_cache.as_ref().iter().enumerate() {
-                download_chapter(client, i, total_chapters, url).await?;
+    pub async fn execute<'a>(&self, config: &Config, workspace: &Workspace) -> Result<(), Error> {
+        let clients = if self.use_threads {
+            let proxies = config.proxies().map(|k| k.as_str()).collect::<Vec<_>>();
+            if proxies.is_empty() {
+                return Err(Error::crawler("must have at least 1 proxy configured"));
             }
-            Ok(())
-        }()
-        .await;
-        client.client.destroy_session().await?;
-        res
+            proxies
+                .into_iter()
+                .map(|proxy| WnrakeClient::from_config_with_proxy(config, proxy))
+                .collect::<Result<Vec<_>, _>>()?
+        } else {
+            vec![WnrakeClient::from_config(config)?]
+        };
+        self.download(clients, workspace).await
     }
 
-    async fn multi_thread(&self, config: &Config, proxies: Vec<&str>) -> Result<(), Error> {
+    async fn download(
+        &self,
+        clients: Vec<WnrakeClient>,
+        workspace: &Workspace,
+    ) -> Result<(), Error> {
         // Make staging directory
-        utils::ensure_dir("staging")?;
+        utils::ensure_dir(workspace.staging_dir())?;
 
-        // Load URL cache
-        log::debug!("loading urlcache.txt");
-        let url_cache = UrlCache::from_file("urlcache.txt")?;
-        let total_chapters = url_cache.as_ref().len();
+        // Load manifest
+        let manifest = workspace.load()?;
+        let total_chapters = manifest.chapters.len();
         log::debug!("total chapters: {}", total_chapters);
+        let selected = self.selection.resolve(&manifest.chapters)?;
 
         // Wrap work queue
         let url_cache = Arc::new(Mutex::new(
-            url_cache.0.into_iter().enumerate().collect::<VecDeque<_>>(),
+            manifest
+                .chapters
+                .iter()
+                .enumerate()
+                .filter(|(i, _)| selected[*i])
+                .map(|(i, chapter)| (i, chapter.url.clone(), chapter.staging_file()))
+                .collect::<VecDeque<_>>(),
         ));
+        let manifest = Arc::new(Mutex::new(manifest));
 
-        // Build workers
+        // Build workers (one for each concurrent request a client allows)
         let mut workers = Vec::new();
-        for proxy in proxies {
-            log::debug!("{:?}", proxy);
-            workers.push(Worker {
-                client: WnrakeClient::from_config_with_proxy(config, proxy)?,
-                total_chapters: total_chapters,
-                urls: url_cache.clone(),
-            })
+        for client in clients.iter() {
+            log::debug!("Solver={}", client.client.solver());
+            log::debug!("Proxy={:?}", client.client.proxy());
+            log::debug!("Cache={:?}", &client.cache);
+            if let Err(e) = client.client.create_session().await {
+                log::error!("worker: {}", e);
+                continue;
+            }
+            for _ in 0..client.client.concurrency() {
+                workers.push(Worker {
+                    client: client.clone(),
+                    workspace: workspace.clone(),
+                    total_chapters,
+                    urls: url_cache.clone(),
+                    manifest: manifest.clone(),
+                });
+            }
         }
 
         // Do work
@@ -99,35 +106,53 @@ impl Download {
             .collect::<Vec<_>>();
 
         // Wait for work to complete
+        let mut error = None;
         for (i, future) in futures.into_iter().enumerate() {
             match future.await {
-                Ok(_) => log::debug!("worker {} finished", i),
+                Ok(Ok(_)) => log::debug!("worker {} finished", i),
+                Ok(Err(e)) => {
+                    log::debug!("worker {}: {}", i, e);
+                    error.get_or_insert(e);
+                }
                 Err(e) => log::warn!("worker {}: {}", i, e),
             }
         }
 
+        // Destroy sessions
+        for client in clients.iter() {
+            let _ = client.client.destroy_session().await;
+        }
+
+        // Save downloads
+        workspace.save(&*manifest.as_ref().lock().await)?;
+
         // Check if all URLs were consumed
+        interrupt::check()?;
         let urls = url_cache.as_ref().lock().await;
-        match urls.len() {
-            0 => Ok(()),
-            _ => Err(Error::crawler("not all URLs were downloaded successfully")),
+        match (urls.len(), error) {
+            (0, _) => Ok(()),
+            (_, Some(e)) => Err(e),
+            (_, None) => Err(Error::crawler("not all URLs were downloaded successfully")),
         }
     }
 }
 
+/// Returns the staging file and the page if it was downloaded (`None` if it was cached)
 async fn download_chapter(
-    client: &mut WnrakeClient,
+    client: &WnrakeClient,
+    workspace: &Workspace,
     i: usize,
     total_chapters: usize,
     url: &str,
-) -> Result<(), Error> {
+    staging: String,
+) -> Result<Option<(String, String)>, Error> {
     // Get path
-    let filename = utils::url_to_filename(i, url);
-    let path = Path::join(Path::new("staging"), &filename);
+    let path = workspace.path(&staging);
 
     match path.is_file() {
         true => {
             log::info!("({:>4}/{:>4}) Using cached {}", i + 1, total_chapters, url);
+            Ok(None)
         }
         false => {
             // Load parser
@@ -139,54 +164,60 @@ async fn download_chapter(
             let chapter = parser.get_chapter(client, url).await?;
 
             // Write file
-            let mut file = File::create(path)?;
-            file.write_all(chapter.as_bytes())?;
+            utils::write_file(&path, chapter.as_bytes())?;
+            Ok(Some((staging, chapter)))
         }
     }
-    Ok(())
 }
 
 #[derive(Clone, Debug)]
 struct Worker {
     client: WnrakeClient,
+    workspace: Workspace,
     total_chapters: usize,
-    urls: Arc<Mutex<VecDeque<(usize, String)>>>,
+    urls: Arc<Mutex<VecDeque<Task>>>,
+    manifest: Arc<Mutex<Manifest>>,
 }
 
 impl Worker {
-    pub async fn do_work(mut self) {
-        log::debug!("Solver={}", self.client.client.solver());
-        log::debug!("Proxy={:?}", self.client.client.proxy());
-        log::debug!("Cache={:?}", &self.client.cache);
-
-        // Create session
-        if let Err(e) = self.client.client.create_session().await {
-            log::error!("worker: {}", e);
-            return;
-        }
-
-        // Work loop
-        loop {
+    pub async fn do_work(self) -> Result<(), Error> {
+        while !interrupt::is_interrupted() {
             let task = {
                 let mut urls = self.urls.as_ref().lock().await;
                 urls.pop_front()
             };
             match task {
-                Some((i, url)) => {
-                    if let Err(e) =
-                        download_chapter(&mut self.client, i, self.total_chapters, &url).await
+                Some((i, url, staging)) => {
+                    match download_chapter(
+                        &self.client,
+                        &self.workspace,
+                        i,
+                        self.total_chapters,
+                        &url,
+                        staging.clone(),
+                    )
+                    .await
                     {
-                        log::warn!("worker: {}", e);
-                        let mut urls = self.urls.as_ref().lock().await;
-                        urls.push_front((i, url));
-                        break;
+                        Ok(Some((staging, chapter))) => {
+                            let mut manifest = self.manifest.as_ref().lock().await;
+                            manifest.chapters[i].downloaded(staging, chapter.as_bytes());
+                        }
+                        Ok(None) => {}
+                        Err(e) => {
+                            log::warn!("worker: {}", e);
+                            if !interrupt::is_interrupted() {
+                                let mut manifest = self.manifest.as_ref().lock().await;
+                                manifest.chapters[i].status = ChapterStatus::Failed;
+                            }
+                            let mut urls = self.urls.as_ref().lock().await;
+                            urls.push_front((i, url, staging));
+                            return Err(e);
+                        }
                     }
                 }
                 None => break,
             }
         }
-
-        // Destroy session
-        let _ = self.client.client.destroy_session().await;
+        Ok(())
     }
 }
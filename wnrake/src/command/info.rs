@@ -1,10 +1,12 @@
 //! info command
 
 use crate::{
-    book::BookInfo,
+    book::{Manifest, cover},
     client::WnrakeClient,
     error::Error,
     parser::{Downloader, Parser, WnParser},
+    utils,
+    workspace::Workspace,
 };
 use clap::Args;
 use crawler::config::Config;
@@ -15,39 +17,47 @@ pub struct Info {
 }
 
 impl Info {
-    pub async fn execute<'a>(&self, config: &Config) -> Result<(), Error> {
-        let mut client = WnrakeClient::from_config(config)?;
+    pub fn new(url: Option<String>) -> Self {
+        Info { url }
+    }
+
+    pub async fn execute<'a>(&self, config: &Config, workspace: &Workspace) -> Result<(), Error> {
+        let client = WnrakeClient::from_config(config)?;
 
         log::debug!("Solver={}", client.client.solver());
         log::debug!("Proxy={:?}", client.client.proxy());
         log::debug!("Cache={:?}", &client.cache);
 
         client.client.create_session().await?;
-        let res = self.do_work(&mut client).await;
+        let res = self.do_work(&client, workspace).await;
         client.client.destroy_session().await?;
         res
     }
 
-    async fn do_work(&self, client: &mut WnrakeClient) -> Result<(), Error> {
-        // Load bookinfo
+    async fn do_work(&self, client: &WnrakeClient, workspace: &Workspace) -> Result<(), Error> {
+        // Load manifest
+        let manifest = workspace.try_load()?;
         let mut title = None;
         let mut author = None;
-        let url = match &self.url {
-            Some(url) => {
-                log::debug!("ignoring bookinfo.txt");
+        let url = match (&self.url, &manifest) {
+            (Some(url), _) => {
+                log::debug!("ignoring manifest book info");
                 url.clone()
             }
-            None => {
-                log::debug!("reading from bookinfo.txt");
-                let bookinfo = BookInfo::from_file("bookinfo.txt")
-                    .or(Err(Error::io("bookinfo.txt does not exit")))?;
+            (None, Some(manifest)) => {
                 log::debug!("Current book info:");
-                log::debug!("  {}", &bookinfo.title);
-                log::debug!("  {}", &bookinfo.author);
-                log::debug!("  {}", &bookinfo.url);
-                title = Some(bookinfo.title);
-                author = Some(bookinfo.author);
-                bookinfo.url
+                log::debug!("  {}", &manifest.book.title);
+                log::debug!("  {}", &manifest.book.author);
+                log::debug!("  {}", &manifest.book.url);
+                title = Some(manifest.book.title.clone());
+                author = Some(manifest.book.author.clone());
+                manifest.book.url.clone()
+            }
+            (None, None) => {
+                return Err(Error::io(format!(
+                    "{:?} does not exist",
+                    workspace.manifest_path()
+                )));
             }
         };
 
@@ -71,25 +81,53 @@ impl Info {
             log::debug!("using author: {}", &bookinfo.author);
         }
 
-        // Write bookinfo
-        log::debug!("writing to bookinfo.txt");
-        bookinfo.to_file("bookinfo.txt")?;
-
         // Fetch url cache
         log::debug!("fetching chapter list");
         let url_cache = parser.get_chapterlist(client, &url, &res).await?;
         log::debug!("found {} chapters", url_cache.0.len());
 
-        // Write url cache
-        log::debug!("writing to urlcache.txt");
-        url_cache.to_file("urlcache.txt")?;
+        // Write manifest (chapters that are still listed keep their state)
+        let mut manifest = match manifest {
+            Some(manifest) if manifest.book.url == url => manifest,
+            _ => Manifest::new(bookinfo.clone()),
+        };
+        manifest.book = bookinfo;
+        manifest.set_urls(url_cache);
+        manifest.set_site_volumes(parser.parse_volumes(&res)?);
+        workspace.save(&manifest)?;
+
+        // Download cover (a cover that is already there is kept)
+        if let Some(cover_url) = &manifest.book.cover_url {
+            match workspace.cover_path() {
+                Some(path) => log::debug!("keeping {:?}", path),
+                None => {
+                    if let Err(e) = self.download_cover(client, workspace, cover_url).await {
+                        log::warn!("unable to download cover: {}", e);
+                    }
+                }
+            }
+        }
 
         // Print results
-        log::info!("Title: {}", &bookinfo.title);
-        log::info!("Author: {}", &bookinfo.author);
-        log::info!("URL: {}", &bookinfo.url);
-        log::info!("Chapters: {}", url_cache.0.len());
+        log::info!("Title: {}", &manifest.book.title);
+        log::info!("Author: {}", &manifest.book.author);
+        log::info!("URL: {}", &manifest.book.url);
+        log::info!("Chapters: {}", manifest.chapters.len());
 
         Ok(())
     }
+
+    async fn download_cover(
+        &self,
+        client: &WnrakeClient,
+        workspace: &Workspace,
+        url: &str,
+    ) -> Result<(), Error> {
+        log::info!("Downloading cover {}", url);
+        let data = client.download(url, cover::ACCEPT).await?;
+        let (format, data) = cover::prepare(&data)?;
+        let path = workspace.cover_file(format);
+        log::debug!("writing {:?}", path);
+        utils::write_file(path, &data)
+    }
 }
@@ -0,0 +1,50 @@
+//! ips command
+
+use crate::error::Error;
+use chrono::Utc;
+use clap::Args;
+use crawler::config::Config;
+
+#[derive(Args, Clone, Debug)]
+pub struct Ips {
+    /// Only show this site
+    site: Option<String>,
+}
+
+impl Ips {
+    pub fn execute(&self, config: &Config) -> Result<(), Error> {
+        let history = match config.to_ip_history()? {
+            Some(history) => history,
+            None => return Err(Error::crawler("no ip_history file configured")),
+        };
+        let now = Utc::now();
+        for (site, ips) in history.records() {
+            if self.site.as_ref().is_some_and(|s| *s != site) {
+                continue;
+            }
+            println!("{}", site);
+            for (ip, record) in ips {
+                let last = match record.last {
+                    Some(outcome) => outcome.to_string(),
+                    None => String::from("-"),
+                };
+                let seen = match record.last_seen {
+                    Some(seen) => seen.format("%Y-%m-%d %H:%M").to_string(),
+                    None => String::from("-"),
+                };
+                let counts = record
+                    .counts
+                    .iter()
+                    .map(|(outcome, count)| format!("{}={}", outcome, count))
+                    .collect::<Vec<_>>()
+                    .join(" ");
+                let blocked = match record.is_blocked(now) {
+                    true => " [blocked]",
+                    false => "",
+                };
+                println!("  {:<39} {:<10} {} {}{}", ip, last, seen, counts, blocked);
+            }
+        }
+        Ok(())
+    }
+}
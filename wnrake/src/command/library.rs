@@ -0,0 +1,271 @@
+//! library command
+
+use crate::{
+    book::BookStatus,
+    command::{Info, Update},
+    error::Error,
+    interrupt,
+    library::{LibraryEntry, LibraryIndex},
+    parser::WnParser,
+    utils,
+    workspace::Workspace,
+};
+use clap::{Args, Subcommand};
+use crawler::config::Config;
+use std::{collections::VecDeque, fs, sync::Arc};
+use tokio::sync::Mutex;
+
+#[derive(Args, Clone, Debug)]
+pub struct Library {
+    #[command(subcommand)]
+    command: LibraryCommand,
+}
+
+#[derive(Subcommand, Clone, Debug)]
+enum LibraryCommand {
+    /// Adds a book to the library
+    Add {
+        /// Book URL
+        url: String,
+    },
+
+    /// Lists the books of the library
+    List,
+
+    /// Removes a book from the library
+    Remove {
+        /// Book directory or URL
+        name: String,
+
+        /// Also deletes the book directory
+        #[arg(long)]
+        delete: bool,
+    },
+
+    /// Updates books
+    Update {
+        /// Book directories or URLs
+        #[arg(required_unless_present = "all", conflicts_with = "all")]
+        names: Vec<String>,
+
+        /// Updates every ongoing book (books marked completed or dropped are skipped)
+        #[arg(long)]
+        all: bool,
+
+        /// Updates one book per configured proxy at a time
+        #[arg(short = 'p', long, conflicts_with = "use_threads")]
+        use_proxies: bool,
+
+        #[command(flatten)]
+        update: Box<Update>,
+    },
+}
+
+impl Library {
+    /// The workspace directory is the library root
+    pub async fn execute(&self, config: &Config, workspace: &Workspace) -> Result<(), Error> {
+        let mut library = LibraryIndex::open(workspace.root())?;
+        match &self.command {
+            LibraryCommand::Add { url } => add(config, &mut library, url).await,
+            LibraryCommand::List => list(&library),
+            LibraryCommand::Remove { name, delete } => remove(&mut library, name, *delete),
+            LibraryCommand::Update {
+                names,
+                all,
+                use_proxies,
+                update,
+            } => {
+                let books = match all {
+                    true => library
+                        .books()
+                        .iter()
+                        .enumerate()
+                        .filter(|(_, book)| {
+                            !matches!(
+                                book.status,
+                                Some(BookStatus::Completed) | Some(BookStatus::Dropped)
+                            )
+                        })
+                        .map(|(i, _)| i)
+                        .collect::<Vec<_>>(),
+                    false => names
+                        .iter()
+                        .map(|name| find(&library, name))
+                        .collect::<Result<Vec<_>, _>>()?,
+                };
+                let proxies = match use_proxies {
+                    true => config.proxies().cloned().collect::<Vec<_>>(),
+                    false => Vec::new(),
+                };
+                if *use_proxies && proxies.is_empty() {
+                    return Err(Error::crawler("must have at least 1 proxy configured"));
+                }
+                update_books(config, library, books, update, proxies).await
+            }
+        }
+    }
+}
+
+fn find(library: &LibraryIndex, name: &str) -> Result<usize, Error> {
+    library
+        .find(name)
+        .ok_or_else(|| Error::parser(format!("`{}` is not in the library", name)))
+}
+
+async fn add(config: &Config, library: &mut LibraryIndex, url: &str) -> Result<(), Error> {
+    if library.find(url).is_some() {
+        return Err(Error::parser(format!("{} is already in the library", url)));
+    }
+    let parser = WnParser::try_from(url)?;
+    log::debug!("using parser {:?}", parser);
+
+    // Fetch the book info in a temporary workspace, named after the title afterwards
+    let tmp = format!(".new-{}", &utils::hash(url.as_bytes())[..16]);
+    let workspace = Workspace::new(library.root().join(&tmp));
+    utils::ensure_dir(workspace.root())?;
+    if let Err(e) = Info::new(Some(url.into()))
+        .execute(config, &workspace)
+        .await
+    {
+        let _ = fs::remove_dir_all(workspace.root());
+        return Err(e);
+    }
+    let manifest = workspace.load()?;
+    let dir = library.dir_name(&manifest.book.title);
+    fs::rename(workspace.root(), library.root().join(&dir))?;
+
+    let mut entry = LibraryEntry::new(dir, url);
+    entry.refresh(&manifest);
+    entry.updated_at = None;
+    log::info!("Added {} as {}", entry.title, entry.dir);
+    library.add(entry);
+    library.save()
+}
+
+fn list(library: &LibraryIndex) -> Result<(), Error> {
+    for book in library.books() {
+        let status = match book.status {
+            Some(status) => status.to_string(),
+            None => String::from("-"),
+        };
+        let updated = match book.updated_at {
+            Some(updated) => updated.format("%Y-%m-%d %H:%M").to_string(),
+            None => String::from("never"),
+        };
+        println!(
+            "{:<30} {:<9} {:>5} {:<16} {}",
+            book.dir, status, book.chapters, updated, book.url
+        );
+    }
+    println!("{} books", library.books().len());
+    Ok(())
+}
+
+fn remove(library: &mut LibraryIndex, name: &str, delete: bool) -> Result<(), Error> {
+    let entry = library.remove(find(library, name)?);
+    if delete {
+        let dir = library.workspace(&entry).root().to_path_buf();
+        log::info!("Deleting {:?}", dir);
+        fs::remove_dir_all(dir)?;
+    }
+    log::info!("Removed {}", entry.dir);
+    library.save()
+}
+
+/// Updates the books one after another, or one per proxy at a time
+async fn update_books(
+    config: &Config,
+    library: LibraryIndex,
+    books: Vec<usize>,
+    update: &Update,
+    proxies: Vec<String>,
+) -> Result<(), Error> {
+    let total = books.len();
+    let queue = Arc::new(Mutex::new(books.into_iter().collect::<VecDeque<_>>()));
+    let library = Arc::new(Mutex::new(library));
+    let configs = match proxies.is_empty() {
+        true => vec![config.clone()],
+        false => proxies
+            .iter()
+            .map(|proxy| config.with_proxy(proxy))
+            .collect::<Result<Vec<_>, _>>()?,
+    };
+
+    // Do work
+    let futures = configs
+        .into_iter()
+        .map(|config| {
+            let worker = Worker {
+                config,
+                update: update.clone(),
+                queue: queue.clone(),
+                library: library.clone(),
+            };
+            tokio::spawn(worker.do_work())
+        })
+        .collect::<Vec<_>>();
+
+    // Wait for work to complete
+    let mut failed = 0;
+    for future in futures.into_iter() {
+        match future.await {
+            Ok(count) => failed += count,
+            Err(e) => log::warn!("worker: {}", e),
+        }
+    }
+    interrupt::check()?;
+    match failed {
+        0 => {
+            log::info!("Updated {} books", total);
+            Ok(())
+        }
+        _ => Err(Error::crawler(format!(
+            "{} of {} books failed to update",
+            failed, total
+        ))),
+    }
+}
+
+#[derive(Clone, Debug)]
+struct Worker {
+    config: Config,
+    update: Update,
+    queue: Arc<Mutex<VecDeque<usize>>>,
+    library: Arc<Mutex<LibraryIndex>>,
+}
+
+impl Worker {
+    /// Returns the number of failed books
+    async fn do_work(self) -> usize {
+        let mut failed = 0;
+        while !interrupt::is_interrupted() {
+            let index = match self.queue.as_ref().lock().await.pop_front() {
+                Some(index) => index,
+                None => break,
+            };
+            let (entry, workspace) = {
+                let library = self.library.as_ref().lock().await;
+                let entry = library.books()[index].clone();
+                let workspace = library.workspace(&entry);
+                (entry, workspace)
+            };
+            log::info!("Updating {} ({})", entry.title, entry.dir);
+            if let Err(e) = self.update.execute(&self.config, &workspace).await {
+                log::error!("{}: {}", entry.dir, e);
+                failed += 1;
+                continue;
+            }
+
+            // Record the update
+            let mut library = self.library.as_ref().lock().await;
+            match workspace.load() {
+                Ok(manifest) => library.get_mut(index).refresh(&manifest),
+                Err(e) => log::warn!("{}: {}", entry.dir, e),
+            }
+            if let Err(e) = library.save() {
+                log::error!("unable to save library: {}", e);
+            }
+        }
+        failed
+    }
+}
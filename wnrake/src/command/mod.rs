@@ -5,11 +5,25 @@ mod crawl;
 mod debug;
 mod download;
 mod info;
+mod ips;
+mod library;
 mod parse;
+mod recheck;
+mod selection;
+mod update;
+mod validate;
+mod volumes;
 
-pub use build::Build;
+pub use build::{Build, BuildOptions};
 pub use crawl::Crawl;
 pub use debug::Debug;
 pub use download::Download;
 pub use info::Info;
+pub use ips::Ips;
+pub use library::Library;
 pub use parse::Parse;
+pub use recheck::Recheck;
+pub use selection::Selection;
+pub use update::Update;
+pub use validate::Validate;
+pub use volumes::Volumes;
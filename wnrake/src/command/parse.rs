@@ -1,49 +1,160 @@
 //! parse command
 
 use crate::{
-    book::{ChapterInfo, ChapterList, UrlCache},
+    book::{Chapter, ChapterStatus, Manifest},
+    client::WnrakeClient,
+    command::Selection,
     error::Error,
-    parser::{Parser, WnParser},
+    images::Images,
+    interrupt,
+    parser::{
+        self, DEFAULT_TYPOGRAPHY, Dropped, FilterRules, ParseOptions, Parser, Typography,
+        TypographyRule, WnParser,
+    },
     utils,
+    workspace::Workspace,
 };
 use clap::Args;
 use crawler::config::Config;
 use std::{
-    fs::{File, copy, read_to_string},
-    io::Write,
+    collections::HashSet,
+    fs::{copy, read_to_string},
     path::Path,
     sync::Arc,
 };
 use tokio::sync::Mutex;
 
 #[derive(Args, Clone, Debug)]
-pub struct Parse;
+pub struct Parse {
+    /// Does not download chapter images (already stored images are still used)
+    #[arg(long)]
+    skip_images: bool,
+
+    /// Only parses new, moved or re-downloaded chapters
+    #[arg(long)]
+    changed: bool,
+
+    /// Typographic rules, comma separated [default: the book's, or whitespace,invisible,nfc]
+    #[arg(long, value_enum, value_name = "RULES", value_delimiter = ',')]
+    typography: Option<Vec<TypographyRule>>,
+
+    /// Leaves the text as it is
+    #[arg(long, conflicts_with = "typography")]
+    no_typography: bool,
+
+    /// Saves --typography or --no-typography as this book's rules
+    #[arg(long)]
+    save_typography: bool,
+
+    /// Copyright filter rules [default: filter-rules.json in the book directory, if any]
+    #[arg(long, value_name = "FILE")]
+    filter_rules: Option<String>,
+
+    #[command(flatten)]
+    selection: Selection,
+}
 
 impl Parse {
-    pub async fn execute<'a>(&self, _config: &Config) -> Result<(), Error> {
+    pub fn new(skip_images: bool, changed: bool) -> Self {
+        Parse {
+            skip_images,
+            changed,
+            typography: None,
+            no_typography: false,
+            save_typography: false,
+            filter_rules: None,
+            selection: Selection::default(),
+        }
+    }
+
+    pub async fn execute<'a>(&self, config: &Config, workspace: &Workspace) -> Result<(), Error> {
         // Make book directory
-        utils::ensure_dir("book")?;
+        utils::ensure_dir(workspace.book_dir())?;
 
-        // Load URL cache
-        log::debug!("loading urlcache.txt");
-        let url_cache = UrlCache::from_file("urlcache.txt")?;
-        let total_chapters = url_cache.as_ref().len();
-        log::debug!("total chapters: {}", total_chapters);
+        // Load manifest
+        let mut manifest = workspace.load()?;
 
-        // Build results
-        let titles: Arc<Mutex<Vec<Option<String>>>> =
-            Arc::new(Mutex::new(vec![None; url_cache.as_ref().len()]));
+        // Typography options override the book's rules
+        let rules = match self.no_typography {
+            true => Some(Vec::new()),
+            false => self.typography.clone(),
+        };
+        if self.save_typography && rules.is_some() {
+            manifest.typography = rules.clone();
+            workspace.save(&manifest)?;
+        }
+        let rules = rules
+            .or_else(|| manifest.typography.clone())
+            .unwrap_or_else(|| DEFAULT_TYPOGRAPHY.to_vec());
+        log::debug!("typography: {:?}", rules);
+
+        // Copyright filter rules
+        let filter_rules = match &self.filter_rules {
+            Some(file) => Some(file.into()),
+            None => Some(workspace.filter_rules_path()).filter(|path| path.is_file()),
+        };
+        let filter = match filter_rules {
+            Some(path) => FilterRules::load(&path, &manifest.book.url)?,
+            None => FilterRules::default(),
+        };
+        let options = Arc::new(ParseOptions {
+            typography: Typography::new(&rules),
+            filter,
+        });
+        let total_chapters = manifest.chapters.len();
+        log::debug!("total chapters: {}", total_chapters);
+        let selected = self.selection.resolve(&manifest.chapters)?;
 
         // Build workers
-        let workers = url_cache
-            .0
-            .into_iter()
+        let chapters = manifest
+            .chapters
+            .iter()
             .enumerate()
-            .map(|(i, url)| Worker {
-                total_chapters: total_chapters,
+            .filter(|(i, chapter)| {
+                selected[*i]
+                    && (!self.changed
+                        || chapter.is_stale()
+                        || !workspace.path(chapter.parsed_file()).is_file())
+            })
+            .map(|(i, chapter)| {
+                let staging = chapter
+                    .staging
+                    .clone()
+                    .unwrap_or_else(|| chapter.staging_file());
+                (i, chapter.url.clone(), staging, chapter.parsed_file())
+            })
+            .collect::<Vec<_>>();
+        if self.changed || !self.selection.is_all() {
+            log::info!("{} chapters to parse", chapters.len());
+        }
+        let client = match self.skip_images {
+            true => None,
+            false => Some(WnrakeClient::from_config(config)?),
+        };
+        let images = Arc::new(Images::new(
+            client,
+            workspace.clone(),
+            manifest.images.clone(),
+        ));
+        let manifest = Arc::new(Mutex::new(manifest));
+        let parsed = chapters
+            .iter()
+            .map(|(_, url, _, _)| url.clone())
+            .collect::<HashSet<_>>();
+        let dropped = Arc::new(Mutex::new(Vec::new()));
+        let workers = chapters
+            .into_iter()
+            .map(|(i, url, staging, parsed)| Worker {
+                workspace: workspace.clone(),
+                total_chapters,
                 index: i,
-                url: url,
-                titles: titles.clone(),
+                url,
+                staging,
+                parsed,
+                manifest: manifest.clone(),
+                images: images.clone(),
+                options: options.clone(),
+                dropped: dropped.clone(),
             })
             .collect::<Vec<_>>();
 
@@ -58,47 +169,79 @@ impl Parse {
             let _ = future.await;
         }
 
-        // Build chapter list
-        let chapters = titles.as_ref().lock().await;
-        let chapter_list = ChapterList(
-            chapters
-                .iter()
-                .enumerate()
-                .map(|(index, title)| {
-                    let parsed_filename = utils::index_to_filename(index);
-                    let filename = Path::join(Path::new("book"), &parsed_filename);
-                    ChapterInfo {
-                        path: filename.as_os_str().to_str().expect("bad filename?").into(),
-                        title: title.as_deref().unwrap_or("???").into(),
-                    }
-                })
-                .collect::<Vec<_>>(),
-        );
+        // Update the copyright filter audit log
+        let dropped = std::mem::take(&mut *dropped.lock().await);
+        let audit = workspace.filter_log_path();
+        if !dropped.is_empty() {
+            log::info!(
+                "{} texts dropped by the copyright filter (see {:?})",
+                dropped.len(),
+                audit
+            );
+        }
+        if !dropped.is_empty() || audit.is_file() {
+            parser::write_audit(&audit, &parsed, dropped)?;
+        }
 
-        // Write chapter list
-        chapter_list.to_file("chapterlist.txt")
+        // Write manifest
+        let mut manifest = manifest.as_ref().lock().await;
+        manifest.images = images.known().await;
+        workspace.save(&manifest)?;
+        interrupt::check()
     }
 }
 
 #[derive(Clone, Debug)]
 struct Worker {
+    workspace: Workspace,
     total_chapters: usize,
     index: usize,
     url: String,
-    titles: Arc<Mutex<Vec<Option<String>>>>,
+    staging: String,
+    parsed: String,
+    manifest: Arc<Mutex<Manifest>>,
+    images: Arc<Images>,
+    options: Arc<ParseOptions>,
+
+    /// Text dropped by the copyright filter
+    dropped: Arc<Mutex<Vec<Dropped>>>,
 }
 
 impl Worker {
     pub async fn do_work(self) {
+        if interrupt::is_interrupted() {
+            return;
+        }
+
         // Get downloaded chapter
-        let raw_filename = utils::url_to_filename(self.index, &self.url);
-        let raw_path = Path::join(Path::new("staging"), &raw_filename);
+        let raw_path = self.workspace.path(&self.staging);
+
+        // Get parsed filename
+        let parsed_path = self.workspace.path(&self.parsed);
 
-        if raw_path.is_file() && self.url.starts_with("file:") {
-            // Get parsed filename
-            let parsed_filename = utils::index_to_filename(self.index);
-            let parsed_path = Path::join(Path::new("book"), &parsed_filename);
+        let res = self.parse(&raw_path, &parsed_path).await;
 
+        // Populate results
+        let mut manifest = self.manifest.as_ref().lock().await;
+        let chapter = &mut manifest.chapters[self.index];
+        match res {
+            Some(Ok(title)) => chapter.parsed(self.parsed.clone(), title),
+            Some(Err(e)) => {
+                log::error!(
+                    "({:>4},{:>4}) failed to parse chapter: {:?}",
+                    self.index + 1,
+                    self.total_chapters,
+                    e
+                );
+                chapter.status = ChapterStatus::Failed;
+            }
+            None => {}
+        }
+    }
+
+    /// Parses the chapter and returns its title, `None` when it has not been downloaded
+    async fn parse(&self, raw_path: &Path, parsed_path: &Path) -> Option<Result<String, Error>> {
+        Some(if raw_path.is_file() && self.url.starts_with("file:") {
             // Parse chapter
             log::info!(
                 "({:>4}/{:>4}) parsing {}",
@@ -107,61 +250,29 @@ impl Worker {
                 self.url
             );
 
-            // Populate titles
-            {
-                let mut titles = self.titles.as_ref().lock().await;
-                titles[self.index] = Some(format!("Chapter {}", self.index + 1));
-            }
-
             // Just copy it
-            if let Err(e) = copy(raw_path, parsed_path) {
-                log::error!(
-                    "({:>4},{:>4}) failed to parse chapter: {:?}",
-                    self.index + 1,
-                    self.total_chapters,
-                    e
-                );
-            }
+            copy(raw_path, parsed_path)
+                .map(|_| format!("Chapter {}", self.index + 1))
+                .map_err(Error::from)
         } else {
             match raw_path.is_file() {
                 // File exists
-                true => match || -> Result<String, Error> {
-                    // Load parser
-                    let parser = WnParser::try_from(self.url.as_str())?;
-                    log::debug!("using parser {:?}", parser);
-
-                    // Get parsed filename
-                    let parsed_filename = utils::index_to_filename(self.index);
-                    let parsed_path = Path::join(Path::new("book"), &parsed_filename);
-
-                    // Parse chapter
-                    log::info!(
-                        "({:>4}/{:>4}) parsing {}",
-                        self.index + 1,
-                        self.total_chapters,
-                        self.url
-                    );
-                    let html = read_to_string(raw_path)?;
-                    let chapter = parser.parse_chapter(&html)?;
-
-                    // Write chapter
-                    let mut file = File::create(parsed_path)?;
-                    file.write_all(chapter.html.as_bytes())?;
-
-                    // Return title
-                    Ok(chapter.title)
-                }() {
-                    Ok(title) => {
-                        // Populate results
-                        let mut titles = self.titles.as_ref().lock().await;
-                        titles[self.index] = Some(title);
+                true => match self.parse_raw(raw_path) {
+                    Ok(chapter) => {
+                        self.dropped
+                            .lock()
+                            .await
+                            .extend(chapter.filtered.iter().map(|filtered| Dropped {
+                                chapter: self.index + 1,
+                                url: self.url.clone(),
+                                filtered: filtered.clone(),
+                            }));
+
+                        // Store images and write chapter
+                        let html = self.images.localize(&self.url, chapter.document()).await;
+                        utils::write_file(parsed_path, html.as_bytes()).map(|_| chapter.title)
                     }
-                    Err(e) => log::error!(
-                        "({:>4},{:>4}) failed to parse chapter: {:?}",
-                        self.index + 1,
-                        self.total_chapters,
-                        e
-                    ),
+                    Err(e) => Err(e),
                 },
 
                 // File does not exist
@@ -172,8 +283,25 @@ impl Worker {
                         self.total_chapters,
                         raw_path.as_os_str().to_str()
                     );
+                    return None;
                 }
             }
-        }
+        })
+    }
+
+    fn parse_raw(&self, raw_path: &Path) -> Result<Chapter, Error> {
+        // Load parser
+        let parser = WnParser::try_from(self.url.as_str())?;
+        log::debug!("using parser {:?}", parser);
+
+        // Parse chapter
+        log::info!(
+            "({:>4}/{:>4}) parsing {}",
+            self.index + 1,
+            self.total_chapters,
+            self.url
+        );
+        let html = read_to_string(raw_path)?;
+        parser.parse_chapter(&html, &self.options)
     }
 }
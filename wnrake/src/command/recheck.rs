@@ -0,0 +1,264 @@
+//! recheck command
+
+use crate::{
+    book::{ChapterEntry, Revision},
+    client::WnrakeClient,
+    command::{Parse, Selection},
+    error::Error,
+    interrupt,
+    parser::{
+        DEFAULT_TYPOGRAPHY, Downloader, FilterRules, ParseOptions, Parser, Typography, WnParser,
+    },
+    utils::{self, ChapterRange},
+    workspace::Workspace,
+};
+use chrono::{Duration, Utc};
+use clap::Args;
+use crawler::config::Config;
+use scraper::{Html, Selector};
+use similar::TextDiff;
+use std::{
+    fs,
+    io::{self, BufRead, IsTerminal, Write},
+};
+
+#[derive(Args, Clone, Debug)]
+pub struct Recheck {
+    /// Only rechecks these chapters (e.g. `10-20`, `100-`), like `--only`
+    #[arg(long, value_name = "RANGE", conflicts_with_all = ["from", "to", "only"])]
+    chapters: Option<ChapterRange>,
+
+    /// Only rechecks chapters downloaded in the last N days
+    #[arg(long, value_name = "N")]
+    days: Option<i64>,
+
+    /// Accepts every changed chapter without asking
+    #[arg(long, conflicts_with = "keep")]
+    accept: bool,
+
+    /// Keeps the stored version of every changed chapter without asking
+    #[arg(long)]
+    keep: bool,
+
+    #[command(flatten)]
+    selection: Selection,
+}
+
+/// What to do with a changed chapter
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Decision {
+    Accept,
+    Keep,
+}
+
+impl Recheck {
+    pub async fn execute(&self, config: &Config, workspace: &Workspace) -> Result<(), Error> {
+        // Always fetch fresh pages
+        let client = WnrakeClient::from_config_without_cache(config)?;
+        client.client.create_session().await?;
+        let res = self.do_work(&client, workspace).await;
+        client.client.destroy_session().await?;
+        let accepted = res?;
+
+        // Parse the accepted chapters
+        if accepted > 0 {
+            Parse::new(false, true).execute(config, workspace).await?;
+            log::info!("Run `wnrake build` to update the book");
+        }
+        Ok(())
+    }
+
+    /// Returns the number of accepted chapters
+    async fn do_work(&self, client: &WnrakeClient, workspace: &Workspace) -> Result<usize, Error> {
+        let mut manifest = workspace.load()?;
+        let total_chapters = manifest.chapters.len();
+        let selected = self
+            .selection
+            .with_range(self.chapters)
+            .resolve(&manifest.chapters)?;
+        let since = self.days.map(|days| Utc::now() - Duration::days(days));
+        let options = ParseOptions {
+            typography: Typography::new(
+                manifest.typography.as_deref().unwrap_or(DEFAULT_TYPOGRAPHY),
+            ),
+            filter: match workspace.filter_rules_path() {
+                path if path.is_file() => FilterRules::load(&path, &manifest.book.url)?,
+                _ => FilterRules::default(),
+            },
+        };
+
+        let (mut checked, mut changed, mut accepted) = (0, 0, 0);
+        for (i, selected) in selected.into_iter().enumerate() {
+            interrupt::check()?;
+            let chapter = &manifest.chapters[i];
+            if !selected
+                || since.is_some_and(|since| chapter.downloaded_at.is_none_or(|at| at < since))
+                || chapter.url.starts_with("file:")
+            {
+                continue;
+            }
+            let staging = match &chapter.staging {
+                Some(staging) if workspace.path(staging).is_file() => staging.clone(),
+                _ => {
+                    log::debug!("({:>4}/{:>4}) not downloaded", i + 1, total_chapters);
+                    continue;
+                }
+            };
+
+            // Fetch the current version
+            let parser = WnParser::try_from(chapter.url.as_str())?;
+            log::info!(
+                "({:>4}/{:>4}) Rechecking {}",
+                i + 1,
+                total_chapters,
+                chapter.url
+            );
+            let page = match parser.get_chapter(client, &chapter.url).await {
+                Ok(page) => page,
+                Err(e) => {
+                    interrupt::check()?;
+                    log::warn!("({:>4}/{:>4}) {}", i + 1, total_chapters, e);
+                    continue;
+                }
+            };
+            checked += 1;
+
+            // Compare the normalized text
+            let old = chapter_text(
+                &parser,
+                &fs::read_to_string(workspace.path(&staging))?,
+                &options,
+            )?;
+            let new = chapter_text(&parser, &page, &options)?;
+            let (old_hash, new_hash) = (utils::hash(old.as_bytes()), utils::hash(new.as_bytes()));
+            if old_hash == new_hash {
+                log::debug!("({:>4}/{:>4}) unchanged", i + 1, total_chapters);
+                continue;
+            }
+            if chapter
+                .revisions
+                .iter()
+                .any(|revision| revision.rejected && revision.text_hash == new_hash)
+            {
+                log::info!(
+                    "({:>4}/{:>4}) changed, but this version was rejected before",
+                    i + 1,
+                    total_chapters
+                );
+                continue;
+            }
+            changed += 1;
+
+            // Show the changes and decide
+            let title = chapter.title.clone().unwrap_or_else(|| chapter.url.clone());
+            log::info!("({:>4}/{:>4}) {} changed:", i + 1, total_chapters, title);
+            print_diff(&old, &new);
+            let decision = self.decide(&title)?;
+
+            // Keep the other version as a revision
+            let chapter = &mut manifest.chapters[i];
+            match decision {
+                Decision::Accept => {
+                    let revision =
+                        save_revision(workspace, chapter, &fs::read(workspace.path(&staging))?)?;
+                    chapter.revisions.push(Revision {
+                        text_hash: old_hash,
+                        ..revision
+                    });
+                    utils::write_file(workspace.path(&staging), page.as_bytes())?;
+                    chapter.downloaded(staging, page.as_bytes());
+                    accepted += 1;
+                }
+                Decision::Keep => {
+                    let revision = save_revision(workspace, chapter, page.as_bytes())?;
+                    chapter.revisions.push(Revision {
+                        text_hash: new_hash,
+                        rejected: true,
+                        ..revision
+                    });
+                }
+            }
+            workspace.save(&manifest)?;
+        }
+
+        log::info!(
+            "{} chapters checked, {} changed, {} accepted",
+            checked,
+            changed,
+            accepted
+        );
+        Ok(accepted)
+    }
+
+    fn decide(&self, title: &str) -> Result<Decision, Error> {
+        if self.accept {
+            return Ok(Decision::Accept);
+        }
+        if self.keep || !io::stdin().is_terminal() {
+            return Ok(Decision::Keep);
+        }
+        loop {
+            print!("Accept the new version of \"{}\"? [y/N] ", title);
+            io::stdout().flush()?;
+            let mut answer = String::new();
+            io::stdin().lock().read_line(&mut answer)?;
+            match answer.trim().to_lowercase().as_str() {
+                "y" | "yes" => return Ok(Decision::Accept),
+                "" | "n" | "no" => return Ok(Decision::Keep),
+                _ => continue,
+            }
+        }
+    }
+}
+
+/// Returns the chapter's text, one block per line with whitespace collapsed
+fn chapter_text(parser: &WnParser, html: &str, options: &ParseOptions) -> Result<String, Error> {
+    let chapter = parser.parse_chapter(html, options)?;
+    let document = Html::parse_document(&chapter.html);
+    Ok(document
+        .select(&Selector::parse("article > *")?)
+        .map(|block| {
+            block
+                .text()
+                .flat_map(|text| text.split_whitespace())
+                .collect::<Vec<_>>()
+                .join(" ")
+        })
+        .filter(|line| !line.is_empty())
+        .collect::<Vec<_>>()
+        .join("\n"))
+}
+
+/// Stores a version of the page under `revisions/`. The caller fills in the hash.
+fn save_revision(
+    workspace: &Workspace,
+    chapter: &ChapterEntry,
+    data: &[u8],
+) -> Result<Revision, Error> {
+    let saved_at = Utc::now();
+    let staging = Workspace::revision_file(&format!(
+        "{}-{}.html",
+        chapter.id,
+        saved_at.format("%Y%m%d%H%M%S")
+    ));
+    utils::ensure_dir(workspace.revisions_dir())?;
+    utils::write_file(workspace.path(&staging), data)?;
+    Ok(Revision {
+        staging,
+        text_hash: String::new(),
+        saved_at,
+        rejected: false,
+    })
+}
+
+fn print_diff(old: &str, new: &str) {
+    let diff = TextDiff::from_lines(old, new);
+    let unified = diff
+        .unified_diff()
+        .context_radius(1)
+        .header("stored", "current")
+        .to_string();
+    for line in unified.lines() {
+        log::info!("  {}", line);
+    }
+}
@@ -0,0 +1,224 @@
+//! Chapter selection shared by the commands that work on chapters
+
+use crate::{book::ChapterEntry, error::Error, utils::ChapterRange};
+use clap::Args;
+use std::{fmt, str::FromStr};
+
+/// Chapters to work on. Selecting never changes the stored chapter list.
+#[derive(Args, Clone, Debug, Default)]
+pub struct Selection {
+    /// Starts at this chapter (1-based index or URL pattern, `*` matches anything)
+    #[arg(long, value_name = "CHAPTER")]
+    from: Option<ChapterPattern>,
+
+    /// Stops after this chapter (1-based index or URL pattern)
+    #[arg(long, value_name = "CHAPTER")]
+    to: Option<ChapterPattern>,
+
+    /// Only these chapters (index, range like `10-20`, or URL pattern; repeatable)
+    #[arg(long, value_name = "CHAPTERS", conflicts_with_all = ["from", "to"])]
+    only: Vec<ChapterPattern>,
+}
+
+impl Selection {
+    /// Returns true if every chapter is selected
+    pub fn is_all(&self) -> bool {
+        self.from.is_none() && self.to.is_none() && self.only.is_empty()
+    }
+
+    /// Adds the range of a `--chapters` option to the selection
+    pub fn with_range(&self, range: Option<ChapterRange>) -> Selection {
+        let mut selection = self.clone();
+        selection.only.extend(range.map(ChapterPattern::Range));
+        selection
+    }
+
+    /// Returns a flag per chapter, true for the selected ones
+    pub fn resolve(&self, chapters: &[ChapterEntry]) -> Result<Vec<bool>, Error> {
+        if !self.only.is_empty() {
+            return Ok(chapters
+                .iter()
+                .enumerate()
+                .map(|(i, chapter)| self.only.iter().any(|only| only.matches(i, &chapter.url)))
+                .collect());
+        }
+        let start = match &self.from {
+            Some(from) => from.find(chapters, 0)?,
+            None => 0,
+        };
+        let end = match &self.to {
+            Some(to) => to.find(chapters, start)?,
+            None => chapters.len().saturating_sub(1),
+        };
+        Ok((0..chapters.len())
+            .map(|i| i >= start && i <= end)
+            .collect())
+    }
+
+    /// Describes the selected chapters as 1-based ranges (e.g. `10-20, 25`)
+    pub fn label(selected: &[bool]) -> String {
+        let mut ranges = Vec::new();
+        let mut start = None;
+        for (i, selected) in selected.iter().chain([&false]).enumerate() {
+            match (selected, start) {
+                (true, None) => start = Some(i + 1),
+                (false, Some(first)) if first == i => ranges.push(format!("{}", i)),
+                (false, Some(first)) => ranges.push(format!("{}-{}", first, i)),
+                _ => continue,
+            }
+            if !selected {
+                start = None;
+            }
+        }
+        ranges.join(", ")
+    }
+}
+
+/// A chapter index or range, or a URL pattern
+#[derive(Clone, Debug)]
+pub enum ChapterPattern {
+    Range(ChapterRange),
+    Url(String),
+}
+
+impl ChapterPattern {
+    fn matches(&self, index: usize, url: &str) -> bool {
+        match self {
+            ChapterPattern::Range(range) => range.contains(index),
+            ChapterPattern::Url(pattern) => glob_contains(url, pattern),
+        }
+    }
+
+    /// Returns the index of the first matching chapter at or after `start`
+    fn find(&self, chapters: &[ChapterEntry], start: usize) -> Result<usize, Error> {
+        chapters
+            .iter()
+            .enumerate()
+            .skip(start)
+            .find(|(i, chapter)| self.matches(*i, &chapter.url))
+            .map(|(i, _)| i)
+            .ok_or_else(|| Error::parser(format!("no chapter matches `{}`", self)))
+    }
+}
+
+impl fmt::Display for ChapterPattern {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ChapterPattern::Range(range) => write!(f, "{}", range),
+            ChapterPattern::Url(pattern) => write!(f, "{}", pattern),
+        }
+    }
+}
+
+impl FromStr for ChapterPattern {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        if s.is_empty() {
+            return Err(String::from("empty chapter pattern"));
+        }
+        match s.chars().all(|c| c.is_ascii_digit() || c == '-') {
+            true => Ok(ChapterPattern::Range(s.parse()?)),
+            false => Ok(ChapterPattern::Url(s.into())),
+        }
+    }
+}
+
+/// Returns true if `text` contains `pattern`, where `*` in the pattern matches anything
+fn glob_contains(text: &str, pattern: &str) -> bool {
+    let mut rest = text;
+    for part in pattern.split('*').filter(|part| !part.is_empty()) {
+        match rest.find(part) {
+            Some(at) => rest = &rest[at + part.len()..],
+            None => return false,
+        }
+    }
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::Parser;
+
+    #[derive(Parser)]
+    struct Cli {
+        #[command(flatten)]
+        selection: Selection,
+    }
+
+    fn selection(args: &[&str]) -> Result<Selection, clap::Error> {
+        Cli::try_parse_from(["wnrake"].iter().chain(args)).map(|cli| cli.selection)
+    }
+
+    fn chapters(count: usize) -> Vec<ChapterEntry> {
+        (1..=count)
+            .map(|i| ChapterEntry::new(format!("https://a/chapter-{}", i)))
+            .collect()
+    }
+
+    /// Resolves the selection over `count` chapters, as a label
+    fn resolve(args: &[&str], count: usize) -> Result<String, Error> {
+        let selected = selection(args).unwrap().resolve(&chapters(count))?;
+        Ok(Selection::label(&selected))
+    }
+
+    #[test]
+    fn ranges() {
+        assert_eq!(resolve(&[], 5).unwrap(), "1-5");
+        assert_eq!(resolve(&["--from", "3"], 5).unwrap(), "3-5");
+        assert_eq!(resolve(&["--to", "2"], 5).unwrap(), "1-2");
+        assert_eq!(resolve(&["--only", "4-"], 5).unwrap(), "4-5");
+        assert_eq!(resolve(&["--only=-2"], 5).unwrap(), "1-2");
+        assert_eq!(
+            resolve(&["--only", "2", "--only", "4-"], 6).unwrap(),
+            "2, 4-6"
+        );
+        assert_eq!(
+            resolve(&["--from", "*chapter-2", "--to", "4"], 5).unwrap(),
+            "2-4"
+        );
+    }
+
+    #[test]
+    fn out_of_range() {
+        assert_eq!(resolve(&["--only", "7-9"], 5).unwrap(), "");
+        assert_eq!(resolve(&["--only", "4-9"], 5).unwrap(), "4-5");
+        assert!(resolve(&["--from", "6"], 5).is_err());
+        assert!(resolve(&["--from", "4", "--to", "2"], 5).is_err());
+        assert!(resolve(&["--from", "*missing"], 5).is_err());
+    }
+
+    #[test]
+    fn bad_input() {
+        assert!(selection(&["--only", "0"]).is_err());
+        assert!(selection(&["--only", "5-3"]).is_err());
+        assert!(selection(&["--only=-"]).is_err());
+        assert!(selection(&["--only", "1-2-3"]).is_err());
+        assert!(selection(&["--from", ""]).is_err());
+        assert!(selection(&["--from", "1", "--only", "2"]).is_err());
+    }
+
+    #[test]
+    fn with_range() {
+        let selection = selection(&[]).unwrap();
+        assert!(selection.is_all());
+        let selection = selection.with_range(Some("2-3".parse().unwrap()));
+        assert!(!selection.is_all());
+        let selected = selection.resolve(&chapters(5)).unwrap();
+        assert_eq!(Selection::label(&selected), "2-3");
+    }
+
+    #[test]
+    fn label() {
+        assert_eq!(Selection::label(&[]), "");
+        assert_eq!(Selection::label(&[false, false]), "");
+        assert_eq!(Selection::label(&[true]), "1");
+        assert_eq!(Selection::label(&[true, true, false, true]), "1-2, 4");
+        assert_eq!(
+            Selection::label(&[false, true, false, true, true]),
+            "2, 4-5"
+        );
+    }
+}
@@ -0,0 +1,94 @@
+//! update command
+
+use crate::{
+    command::{BuildOptions, Download, Info, Parse, Selection},
+    error::Error,
+    workspace::Workspace,
+};
+use clap::Args;
+use crawler::config::Config;
+use std::collections::HashSet;
+
+#[derive(Args, Clone, Debug)]
+#[group(skip)]
+pub struct Update {
+    /// Use multiple threads [one for each configured proxy]
+    #[arg(short = 't', long)]
+    use_threads: bool,
+
+    /// Does not download chapter images (already stored images are still used)
+    #[arg(long)]
+    skip_images: bool,
+
+    #[command(flatten)]
+    build: BuildOptions,
+}
+
+impl Update {
+    pub async fn execute(&self, config: &Config, workspace: &Workspace) -> Result<(), Error> {
+        // Remember the chapter list of the last update
+        let old = workspace.load()?.urls().0;
+
+        // Refresh book info and chapter list (local title/author are kept)
+        Info::new(None).execute(config, workspace).await?;
+        let manifest = workspace.load()?;
+        let changes = Changes::new(&old, &manifest.urls().0);
+        if changes.removed > 0 {
+            log::warn!("{} chapters were removed", changes.removed);
+        }
+        if changes.moved > 0 {
+            log::warn!("{} chapters were reordered", changes.moved);
+        }
+
+        // Skip the rest when everything is up to date
+        let pending = manifest
+            .chapters
+            .iter()
+            .any(|chapter| chapter.is_stale() || !workspace.path(chapter.parsed_file()).is_file());
+        if !changes.any() && !pending {
+            log::info!("No new chapters since last update");
+            return Ok(());
+        }
+
+        // Download new chapters, parse what changed and rebuild
+        Download::new(self.use_threads)
+            .execute(config, workspace)
+            .await?;
+        Parse::new(self.skip_images, true)
+            .execute(config, workspace)
+            .await?;
+        self.build
+            .execute(config, workspace, &Selection::default())?;
+
+        log::info!("{} new chapters since last update", changes.added);
+        Ok(())
+    }
+}
+
+/// Differences between two chapter lists
+#[derive(Clone, Copy, Debug, Default)]
+struct Changes {
+    added: usize,
+    removed: usize,
+    moved: usize,
+}
+
+impl Changes {
+    fn new(old: &[String], new: &[String]) -> Self {
+        let old_set = old.iter().collect::<HashSet<_>>();
+        let new_set = new.iter().collect::<HashSet<_>>();
+
+        // Chapters in both lists that are no longer in the same relative order
+        let kept_old = old.iter().filter(|url| new_set.contains(url));
+        let kept_new = new.iter().filter(|url| old_set.contains(url));
+        Changes {
+            added: new.iter().filter(|url| !old_set.contains(url)).count(),
+            removed: old.iter().filter(|url| !new_set.contains(url)).count(),
+            moved: kept_old.zip(kept_new).filter(|(a, b)| a != b).count(),
+        }
+    }
+
+    fn any(&self) -> bool {
+        self.added > 0 || self.removed > 0 || self.moved > 0
+    }
+}
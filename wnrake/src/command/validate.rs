@@ -0,0 +1,34 @@
+//! validate command
+
+use crate::{book::validate, error::Error, workspace::Workspace};
+use clap::Args;
+use crawler::config::Config;
+use std::{fs, path::Path};
+
+#[derive(Args, Clone, Debug)]
+pub struct Validate {
+    /// EPUB file
+    file: String,
+}
+
+impl Validate {
+    pub fn execute(&self, _config: &Config, workspace: &Workspace) -> Result<(), Error> {
+        check(workspace.path(&self.file))
+    }
+}
+
+/// Validates an EPUB, logging every problem. Fails if there are any.
+pub fn check<P: AsRef<Path>>(path: P) -> Result<(), Error> {
+    let path = path.as_ref();
+    let problems = validate::validate(&fs::read(path)?)?;
+    for problem in problems.iter() {
+        log::error!("{}", problem);
+    }
+    match problems.len() {
+        0 => {
+            log::info!("{:?} is valid", path);
+            Ok(())
+        }
+        count => Err(Error::epub(format!("{:?} has {} problems", path, count))),
+    }
+}
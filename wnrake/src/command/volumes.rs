@@ -0,0 +1,74 @@
+//! volumes command
+
+use crate::{book::VolumeRange, error::Error, utils::ChapterRange, workspace::Workspace};
+use clap::{Args, Subcommand};
+use crawler::config::Config;
+
+#[derive(Args, Clone, Debug)]
+pub struct Volumes {
+    #[command(subcommand)]
+    command: VolumesCommand,
+}
+
+#[derive(Subcommand, Clone, Debug)]
+enum VolumesCommand {
+    /// Lists the volumes and their chapters
+    List,
+
+    /// Groups chapters into a volume (overrides the site's volumes)
+    Set {
+        /// Chapters of the volume (e.g. `1-50`, `51-`)
+        chapters: ChapterRange,
+
+        /// Volume title
+        title: String,
+    },
+
+    /// Removes the user-defined volumes
+    Clear,
+}
+
+impl Volumes {
+    pub fn execute(&self, _config: &Config, workspace: &Workspace) -> Result<(), Error> {
+        let mut manifest = workspace.load()?;
+        match &self.command {
+            VolumesCommand::List => {
+                // Consecutive chapters of the same volume
+                let mut groups: Vec<(Option<&str>, usize, usize)> = Vec::new();
+                for i in 0..manifest.chapters.len() {
+                    let volume = manifest.volume(i);
+                    match groups.last_mut() {
+                        Some((last, _, end)) if *last == volume => *end = i,
+                        _ => groups.push((volume, i, i)),
+                    }
+                }
+                for (volume, start, end) in groups.iter() {
+                    log::info!(
+                        "{:>5}-{:<5} {}",
+                        start + 1,
+                        end + 1,
+                        volume.unwrap_or("(no volume)")
+                    );
+                }
+                if !manifest.volumes.is_empty() {
+                    log::info!("{} user-defined volumes", manifest.volumes.len());
+                }
+                Ok(())
+            }
+            VolumesCommand::Set { chapters, title } => {
+                manifest.volumes.retain(|volume| volume.title != *title);
+                manifest.volumes.push(VolumeRange {
+                    title: title.clone(),
+                    chapters: *chapters,
+                });
+                log::info!("Chapters {} are now in \"{}\"", chapters, title);
+                workspace.save(&manifest)
+            }
+            VolumesCommand::Clear => {
+                log::info!("Removed {} user-defined volumes", manifest.volumes.len());
+                manifest.volumes.clear();
+                workspace.save(&manifest)
+            }
+        }
+    }
+}
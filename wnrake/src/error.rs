@@ -13,6 +13,9 @@ pub enum ErrorType {
     /// Html errors
     Html,
 
+    /// Interrupted by Ctrl-C
+    Interrupted,
+
     /// IO errors
     Io,
 
@@ -32,6 +35,7 @@ impl fmt::Display for ErrorType {
             ErrorType::Crawler => f.write_str("crawler"),
             ErrorType::Epub => f.write_str("epub"),
             ErrorType::Html => f.write_str("html"),
+            ErrorType::Interrupted => f.write_str("interrupted"),
             ErrorType::Io => f.write_str("io"),
             ErrorType::Json => f.write_str("json"),
             ErrorType::Parser => f.write_str("parser"),
@@ -43,6 +47,7 @@ impl fmt::Display for ErrorType {
 #[derive(Debug)]
 pub struct Error {
     pub error_type: ErrorType,
+    #[allow(dead_code)]
     pub fatal: bool,
     pub message: String,
 }
@@ -75,6 +80,12 @@ impl<'a> From<scraper::error::SelectorErrorKind<'a>> for Error {
     }
 }
 
+impl From<zip::result::ZipError> for Error {
+    fn from(error: zip::result::ZipError) -> Self {
+        Error::epub(error)
+    }
+}
+
 impl From<std::io::Error> for Error {
     fn from(error: std::io::Error) -> Error {
         Error::io(error)
@@ -106,6 +117,14 @@ impl Error {
         }
     }
 
+    pub fn interrupted() -> Error {
+        Error {
+            error_type: ErrorType::Interrupted,
+            fatal: true,
+            message: "operation cancelled".into(),
+        }
+    }
+
     pub fn json(msg: impl fmt::Display) -> Error {
         Error {
             error_type: ErrorType::Json,
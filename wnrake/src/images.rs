@@ -0,0 +1,211 @@
+//! Chapter images
+//!
+//! Parsers keep `<img>` tags with the site's `src`. While parsing, every image is downloaded
+//! once, stored under `images/` named after its content hash and the `src` is rewritten to the
+//! stored copy. Images that cannot be fetched are replaced by their alt text.
+
+use crate::{book::cover, client::WnrakeClient, error::Error, utils, workspace::Workspace};
+use crawler::Url;
+use ego_tree::NodeId;
+use image::ImageFormat;
+use scraper::{Html, Node, Selector, StrTendril, node::Text};
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs,
+    path::Path,
+    sync::Arc,
+};
+use tokio::sync::Mutex;
+
+/// Accept header for image downloads
+const ACCEPT: &str = "image/jpeg,image/png,image/gif,image/webp;q=0.9,image/*;q=0.8";
+
+/// Path of the images directory as seen from a parsed chapter
+const CHAPTER_PREFIX: &str = "../";
+
+#[derive(Debug)]
+pub struct Images {
+    /// Downloads images (`None` only reuses images that were already stored)
+    client: Option<WnrakeClient>,
+
+    workspace: Workspace,
+
+    /// Image URL -> workspace-relative path
+    known: Mutex<BTreeMap<String, String>>,
+
+    /// Held while an image URL is downloaded, so it is fetched only once
+    downloading: Mutex<HashMap<String, Arc<Mutex<()>>>>,
+
+    /// Held while an image is written (different URLs can have the same content)
+    storing: Mutex<()>,
+}
+
+impl Images {
+    pub fn new(
+        client: Option<WnrakeClient>,
+        workspace: Workspace,
+        known: BTreeMap<String, String>,
+    ) -> Self {
+        Images {
+            client,
+            workspace,
+            known: Mutex::new(known),
+            downloading: Mutex::new(HashMap::new()),
+            storing: Mutex::new(()),
+        }
+    }
+
+    /// Returns the image URL -> workspace-relative path map
+    pub async fn known(&self) -> BTreeMap<String, String> {
+        self.known.lock().await.clone()
+    }
+
+    /// Stores the chapter's images and points their `src` at the stored copies
+    pub async fn localize(&self, base_url: &str, html: String) -> String {
+        if !html.contains("<img") {
+            return html;
+        }
+
+        // `Html` is not `Send`, so fetch first and rewrite afterwards
+        let mut stored = BTreeMap::new();
+        for src in sources(&html) {
+            let url = match Url::parse(base_url).and_then(|base| base.join(&src)) {
+                Ok(url) => url.to_string(),
+                Err(_) => src.clone(),
+            };
+            // Remote pages must not pull in local files
+            if url.starts_with("file:") && !base_url.starts_with("file:") {
+                log::warn!("ignoring local image {} of {}", url, base_url);
+                continue;
+            }
+            match self.fetch(&url).await {
+                Ok(path) => {
+                    stored.insert(src, format!("{}{}", CHAPTER_PREFIX, path));
+                }
+                Err(e) => log::warn!("unable to fetch image {}: {}", url, e),
+            }
+        }
+        rewrite(&html, &stored)
+    }
+
+    /// Returns the stored image, downloading it first if needed
+    async fn fetch(&self, url: &str) -> Result<String, Error> {
+        let lock = self
+            .downloading
+            .lock()
+            .await
+            .entry(url.into())
+            .or_default()
+            .clone();
+        let _downloading = lock.lock().await;
+        if let Some(path) = self.known.lock().await.get(url)
+            && self.workspace.path(path).is_file()
+        {
+            return Ok(path.clone());
+        }
+        let data = match url.strip_prefix("file:") {
+            Some(path) => fs::read(path.trim_start_matches("//"))?,
+            None => match &self.client {
+                Some(client) => {
+                    log::info!("Downloading image {}", url);
+                    client.download(url, ACCEPT).await?
+                }
+                None => return Err(Error::parser("image downloads are disabled")),
+            },
+        };
+        let path = {
+            let _storing = self.storing.lock().await;
+            store(&self.workspace, &data)?
+        };
+        self.known.lock().await.insert(url.into(), path.clone());
+        Ok(path)
+    }
+}
+
+/// Stores an image under `images/`, named after its content hash. JPEG, PNG and GIF are kept,
+/// other formats are converted. Returns the workspace-relative path.
+pub fn store(workspace: &Workspace, data: &[u8]) -> Result<String, Error> {
+    let format = image::guess_format(data).map_err(Error::parser)?;
+    let (extension, data) = match format {
+        ImageFormat::Jpeg => ("jpg", data.to_vec()),
+        ImageFormat::Png => ("png", data.to_vec()),
+        ImageFormat::Gif => ("gif", data.to_vec()),
+        ImageFormat::Avif => return Err(Error::parser("AVIF images cannot be decoded")),
+        _ => {
+            let image = image::load_from_memory_with_format(data, format).map_err(Error::parser)?;
+            match image.color().has_alpha() {
+                true => ("png", cover::encode_png(&image)?),
+                false => ("jpg", cover::encode_jpeg(&image)?),
+            }
+        }
+    };
+    let hash = utils::hash(&data);
+    let path = format!("images/{}.{}", &hash[..16], extension);
+    let file = workspace.path(&path);
+    if !file.is_file() {
+        utils::ensure_dir(workspace.images_dir())?;
+        utils::write_file(&file, &data)?;
+    }
+    Ok(path)
+}
+
+/// Returns the media type of a stored image
+pub fn media_type(path: &str) -> &'static str {
+    match Path::new(path).extension().and_then(|ext| ext.to_str()) {
+        Some("png") => "image/png",
+        Some("gif") => "image/gif",
+        _ => "image/jpeg",
+    }
+}
+
+/// Returns the `src` of every image
+fn sources(html: &str) -> Vec<String> {
+    let document = Html::parse_document(html);
+    document
+        .select(&Selector::parse("img").expect("img is a valid selector"))
+        .filter_map(|img| img.attr("src").map(String::from))
+        .collect()
+}
+
+/// Points images at their stored copies and replaces the others by their alt text
+fn rewrite(html: &str, stored: &BTreeMap<String, String>) -> String {
+    let mut document = Html::parse_document(html);
+    let images = document
+        .select(&Selector::parse("img").expect("img is a valid selector"))
+        .map(|img| {
+            let src = img.attr("src").unwrap_or("").to_string();
+            let alt = img.attr("alt").unwrap_or("").trim().to_string();
+            (img.id(), src, alt)
+        })
+        .collect::<Vec<_>>();
+    for (id, src, alt) in images {
+        match stored.get(&src) {
+            Some(path) => set_src(&mut document, id, path),
+            None => replace_with_text(&mut document, id, &alt),
+        }
+    }
+    document.html()
+}
+
+fn set_src(document: &mut Html, id: NodeId, src: &str) {
+    if let Some(mut node) = document.tree.get_mut(id)
+        && let Node::Element(img) = node.value()
+    {
+        for (name, value) in img.attrs.iter_mut() {
+            if &*name.local == "src" {
+                *value = StrTendril::from(src);
+            }
+        }
+    }
+}
+
+fn replace_with_text(document: &mut Html, id: NodeId, alt: &str) {
+    if let Some(mut node) = document.tree.get_mut(id) {
+        if !alt.is_empty() {
+            node.insert_before(Node::Text(Text {
+                text: StrTendril::from(format!("[{}]", alt)),
+            }));
+        }
+        node.detach();
+    }
+}
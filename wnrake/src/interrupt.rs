@@ -0,0 +1,54 @@
+//! Ctrl-C handling
+
+use crate::error::Error;
+use std::sync::atomic::{AtomicBool, Ordering};
+use tokio::sync::Notify;
+
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+static NOTIFY: Notify = Notify::const_new();
+
+/// Exit code used when the process is interrupted
+pub const EXIT_CODE: i32 = 130;
+
+/// Installs the Ctrl-C listener. The first signal asks everything to shut down gracefully, the
+/// second one exits immediately.
+pub fn listen() {
+    tokio::spawn(async {
+        if tokio::signal::ctrl_c().await.is_err() {
+            log::warn!("unable to listen for Ctrl-C");
+            return;
+        }
+        log::warn!("interrupted, shutting down (press Ctrl-C again to force)");
+        INTERRUPTED.store(true, Ordering::SeqCst);
+        NOTIFY.notify_waiters();
+
+        if tokio::signal::ctrl_c().await.is_ok() {
+            log::error!("forced exit");
+            std::process::exit(EXIT_CODE);
+        }
+    });
+}
+
+/// Returns true if Ctrl-C was pressed
+pub fn is_interrupted() -> bool {
+    INTERRUPTED.load(Ordering::SeqCst)
+}
+
+/// Returns an error if Ctrl-C was pressed
+pub fn check() -> Result<(), Error> {
+    match is_interrupted() {
+        true => Err(Error::interrupted()),
+        false => Ok(()),
+    }
+}
+
+/// Resolves once Ctrl-C is pressed
+pub async fn interrupted() {
+    loop {
+        let notified = NOTIFY.notified();
+        if is_interrupted() {
+            return;
+        }
+        notified.await;
+    }
+}
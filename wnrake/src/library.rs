@@ -0,0 +1,171 @@
+//! Library of book workspaces
+//!
+//! A library root holds one workspace per book and an index (`library.json`) to keep track of
+//! them without opening every manifest.
+
+use crate::{
+    book::{BookStatus, Manifest},
+    error::Error,
+    utils,
+    workspace::Workspace,
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// Index file name
+pub const INDEX: &str = "library.json";
+
+/// Current index schema version
+pub const SCHEMA_VERSION: u32 = 1;
+
+/// One book of the library
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct LibraryEntry {
+    /// Workspace directory, relative to the library root
+    pub dir: String,
+
+    /// Source URL
+    pub url: String,
+
+    /// Book title
+    #[serde(default)]
+    pub title: String,
+
+    /// Publication status
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub status: Option<BookStatus>,
+
+    /// Time of the last update
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub updated_at: Option<DateTime<Utc>>,
+
+    /// Number of chapters
+    #[serde(default)]
+    pub chapters: usize,
+}
+
+impl LibraryEntry {
+    pub fn new(dir: impl Into<String>, url: impl Into<String>) -> Self {
+        LibraryEntry {
+            dir: dir.into(),
+            url: url.into(),
+            title: String::new(),
+            status: None,
+            updated_at: None,
+            chapters: 0,
+        }
+    }
+
+    /// Copies the book state from its manifest
+    pub fn refresh(&mut self, manifest: &Manifest) {
+        self.title = manifest.book.title.clone();
+        self.status = manifest.book.status;
+        self.chapters = manifest.chapters.len();
+        self.updated_at = Some(Utc::now());
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+struct IndexFile {
+    version: u32,
+
+    #[serde(default)]
+    books: Vec<LibraryEntry>,
+}
+
+#[derive(Clone, Debug)]
+pub struct LibraryIndex {
+    root: PathBuf,
+    books: Vec<LibraryEntry>,
+}
+
+impl LibraryIndex {
+    /// Opens the library at `root`. A directory without an index is an empty library.
+    pub fn open<P: Into<PathBuf>>(root: P) -> Result<Self, Error> {
+        let root = root.into();
+        let path = root.join(INDEX);
+        let books = match path.is_file() {
+            true => {
+                let index: IndexFile =
+                    serde_json::from_str(&fs::read_to_string(&path)?).map_err(Error::json)?;
+                if index.version > SCHEMA_VERSION {
+                    return Err(Error::parser(format!(
+                        "library version {} is newer than supported version {}",
+                        index.version, SCHEMA_VERSION
+                    )));
+                }
+                index.books
+            }
+            false => Vec::new(),
+        };
+        Ok(LibraryIndex { root, books })
+    }
+
+    pub fn save(&self) -> Result<(), Error> {
+        let index = IndexFile {
+            version: SCHEMA_VERSION,
+            books: self.books.clone(),
+        };
+        let json = serde_json::to_string_pretty(&index).map_err(Error::json)?;
+        utils::write_file(self.root.join(INDEX), json.as_bytes())
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    pub fn books(&self) -> &[LibraryEntry] {
+        &self.books
+    }
+
+    pub fn add(&mut self, entry: LibraryEntry) {
+        self.books.push(entry);
+    }
+
+    pub fn remove(&mut self, index: usize) -> LibraryEntry {
+        self.books.remove(index)
+    }
+
+    /// Finds a book by directory or source URL
+    pub fn find(&self, name: &str) -> Option<usize> {
+        let name = name.trim_end_matches('/');
+        self.books
+            .iter()
+            .position(|book| book.dir == name || book.url.trim_end_matches('/') == name)
+    }
+
+    pub fn get_mut(&mut self, index: usize) -> &mut LibraryEntry {
+        &mut self.books[index]
+    }
+
+    pub fn workspace(&self, entry: &LibraryEntry) -> Workspace {
+        Workspace::new(self.root.join(&entry.dir))
+    }
+
+    /// Returns an unused directory name for a book
+    pub fn dir_name(&self, title: &str) -> String {
+        let mut slug = String::new();
+        for c in title.to_lowercase().chars() {
+            match c.is_alphanumeric() {
+                true => slug.push(c),
+                false if !slug.is_empty() && !slug.ends_with('-') => slug.push('-'),
+                false => {}
+            }
+        }
+        let slug = match slug.trim_end_matches('-') {
+            "" => "book",
+            slug => slug,
+        };
+        let mut name = slug.to_string();
+        let mut i = 2;
+        while self.books.iter().any(|book| book.dir == name) || self.root.join(&name).exists() {
+            name = format!("{}-{}", slug, i);
+            i += 1;
+        }
+        name
+    }
+}
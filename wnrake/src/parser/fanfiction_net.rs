@@ -1,10 +1,10 @@
 //! fanfiction.net parser
 
 use crate::{
-    book::{BookInfo, Chapter, UrlCache},
+    book::{BookInfo, BookStatus, Chapter, UrlCache},
     client::WnrakeClient,
     error::Error,
-    parser::{utils, Downloader, Parser},
+    parser::{utils, Downloader, ParseOptions, Parser},
 };
 use async_trait::async_trait;
 use crawler::{Request, WaitFor};
@@ -15,11 +15,12 @@ pub struct FanfictionParser;
 
 #[async_trait]
 impl Downloader for FanfictionParser {
-    async fn get_book_info(&self, client: &mut WnrakeClient, url: &str) -> Result<String, Error> {
+    async fn get_book_info(&self, client: &WnrakeClient, url: &str) -> Result<String, Error> {
         let res = client
             .request(
                 &Request::get(url)
                     .wait_for(WaitFor::id("profile_top"))
+                    .validate(utils::has_element("div#profile_top"))
                     .build(),
             )
             .await?;
@@ -32,7 +33,7 @@ impl Downloader for FanfictionParser {
 
     async fn get_chapterlist(
         &self,
-        _client: &mut WnrakeClient,
+        _client: &WnrakeClient,
         _url: &str,
         html: &str,
     ) -> Result<UrlCache, Error> {
@@ -75,11 +76,12 @@ impl Downloader for FanfictionParser {
         ))
     }
 
-    async fn get_chapter(&self, client: &mut WnrakeClient, url: &str) -> Result<String, Error> {
+    async fn get_chapter(&self, client: &WnrakeClient, url: &str) -> Result<String, Error> {
         let res = client
             .request(
                 &Request::get(url)
                     .wait_for(WaitFor::id("storytext"))
+                    .validate(utils::has_element("div#storytext"))
                     .with_kill()
                     .build(),
             )
@@ -123,15 +125,53 @@ impl Parser for FanfictionParser {
             .trim()
             .to_string();
 
+        // Get the details line: "Rated: Fiction T - English - Romance/Drama - ... - Status: Complete"
+        let details = profile_top
+            .select(&Selector::parse("span.xgray")?)
+            .next()
+            .map(|span| span.text().collect::<Vec<_>>().join(""))
+            .unwrap_or_default();
+        let fields = details.split(" - ").map(str::trim).collect::<Vec<_>>();
+        let genres = fields
+            .get(2)
+            .filter(|field| !field.contains(':'))
+            .map(|field| field.split('/').map(String::from).collect())
+            .unwrap_or_default();
+        let status = match details.contains("Status: Complete") {
+            true => BookStatus::Completed,
+            false => BookStatus::Ongoing,
+        };
+
+        // Dates are listed as updated (optional) then published
+        let mut dates = profile_top
+            .select(&Selector::parse("span[data-xutime]")?)
+            .filter_map(|span| span.attr("data-xutime").and_then(utils::parse_date))
+            .collect::<Vec<_>>();
+        let published = dates.pop();
+        let updated = dates.pop().or(published);
+
         // Return book info
         Ok(BookInfo {
-            title,
-            author,
-            url: url.into(),
+            description: profile_top
+                .select(&Selector::parse("div.xcontrast_txt")?)
+                .next()
+                .map(|div| div.text().collect::<Vec<_>>().join("").trim().to_string()),
+            genres,
+            language: fields.get(1).map(|language| language_code(language)),
+            status: Some(status),
+            cover_url: utils::select_attr(&document, "#profile_top img.cimage", "src")?
+                .map(|src| match src.starts_with("//") {
+                    true => format!("https:{}", src),
+                    false => format!("https://www.fanfiction.net{}", src),
+                }),
+            publisher: Some("FanFiction.Net".into()),
+            published,
+            updated,
+            ..BookInfo::new(title, author, url)
         })
     }
 
-    fn parse_chapter(&self, html: &str) -> Result<Chapter, Error> {
+    fn parse_chapter(&self, html: &str, options: &ParseOptions) -> Result<Chapter, Error> {
         let document = Html::parse_document(&html);
 
         // Parse title
@@ -153,10 +193,10 @@ impl Parser for FanfictionParser {
             .ok_or(Error::html("storytext not in html", true))?;
 
         // Build HTML
-        let html = utils::parse_content(&title, chapter)?;
+        let chapter = utils::parse_chapter(&title, chapter, Vec::new(), Vec::new(), options)?;
 
         // Return chapter
-        Ok(Chapter { title, html })
+        Ok(chapter)
     }
 
     fn next_page(&self, html: &str) -> Result<Option<String>, Error> {
@@ -187,3 +227,23 @@ impl Parser for FanfictionParser {
         )))
     }
 }
+
+/// Maps the language names FanFiction.Net prints to language codes
+fn language_code(language: &str) -> String {
+    match language {
+        "English" => "en",
+        "Spanish" => "es",
+        "French" => "fr",
+        "German" => "de",
+        "Portuguese" => "pt",
+        "Italian" => "it",
+        "Russian" => "ru",
+        "Indonesian" => "id",
+        "Polish" => "pl",
+        "Dutch" => "nl",
+        "Japanese" => "ja",
+        "Chinese" => "zh",
+        _ => language,
+    }
+    .into()
+}
@@ -4,7 +4,7 @@ use crate::{
     book::{BookInfo, Chapter, UrlCache},
     client::WnrakeClient,
     error::Error,
-    parser::{Downloader, Parser},
+    parser::{Downloader, ParseOptions, Parser},
 };
 use async_trait::async_trait;
 use std::fs;
@@ -14,20 +14,15 @@ pub struct FileParser;
 
 #[async_trait]
 impl Downloader for FileParser {
-    async fn get_book_info(&self, _: &mut WnrakeClient, _: &str) -> Result<String, Error> {
+    async fn get_book_info(&self, _: &WnrakeClient, _: &str) -> Result<String, Error> {
         Err(Error::parser("not implemented for file"))
     }
 
-    async fn get_chapterlist(
-        &self,
-        _: &mut WnrakeClient,
-        _: &str,
-        _: &str,
-    ) -> Result<UrlCache, Error> {
+    async fn get_chapterlist(&self, _: &WnrakeClient, _: &str, _: &str) -> Result<UrlCache, Error> {
         Err(Error::parser("not implemented for file"))
     }
 
-    async fn get_chapter(&self, _: &mut WnrakeClient, url: &str) -> Result<String, Error> {
+    async fn get_chapter(&self, _: &WnrakeClient, url: &str) -> Result<String, Error> {
         let path = url
             .trim()
             .trim_start_matches("file:")
@@ -42,7 +37,7 @@ impl Parser for FileParser {
         Err(Error::parser("not implemented for file"))
     }
 
-    fn parse_chapter(&self, _html: &str) -> Result<Chapter, Error> {
+    fn parse_chapter(&self, _html: &str, _options: &ParseOptions) -> Result<Chapter, Error> {
         Err(Error::parser("not implemented for file"))
     }
 
@@ -0,0 +1,352 @@
+//! Copyright notice filter
+//!
+//! Some sites hide notices such as "This story was stolen from Royal Road" in the chapter text.
+//! Short texts are scored against weighted trigger words, and known notices can be matched
+//! exactly or with regular expressions. The built-in rules can be changed with a rules file
+//! (`filter-rules.json` in the workspace, or `parse --filter-rules`):
+//!
+//! ```json
+//! {
+//!   "max_length": 200,
+//!   "threshold": 8,
+//!   "count": 4,
+//!   "triggers": { "stolen": 2, "report": 0 },
+//!   "exact": ["Find this novel on the official site."],
+//!   "patterns": ["(?i)^read (it|this) (at|on) \\S+$"],
+//!   "allow": ["I'll report this story to the official site."],
+//!   "sites": {
+//!     "royalroad.com": { "threshold": 10 }
+//!   }
+//! }
+//! ```
+//!
+//! Every field is optional. Triggers change the built-in weights (0 removes one), lists are
+//! added to each other and the rules of the book's site apply last. Exact and allowed texts are
+//! compared case-insensitively with whitespace collapsed.
+
+use crate::{
+    book::Filtered,
+    error::Error,
+    parser::phrases::{
+        COPYRIGHT_TRIGGER_COUNT, COPYRIGHT_TRIGGER_LENGTH, COPYRIGHT_TRIGGER_THRESHOLD,
+        COPYRIGHT_TRIGGERS,
+    },
+    utils,
+};
+use crawler::Url;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    fs,
+    path::Path,
+};
+
+/// A rules file, or the rules of one site in it
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct RuleSet {
+    /// Longer texts are not scored
+    max_length: Option<usize>,
+
+    /// Total trigger weight that drops a text
+    threshold: Option<usize>,
+
+    /// Number of triggers that drops a text
+    count: Option<usize>,
+
+    triggers: BTreeMap<String, usize>,
+
+    /// Known notices
+    exact: Vec<String>,
+
+    /// Regular expressions matching known notices
+    patterns: Vec<String>,
+
+    /// Texts that are never dropped
+    allow: Vec<String>,
+
+    /// Rules by site host (without `www.`)
+    sites: HashMap<String, RuleSet>,
+}
+
+#[derive(Clone, Debug)]
+pub struct FilterRules {
+    max_length: usize,
+    threshold: usize,
+    count: usize,
+    triggers: Vec<(String, usize)>,
+    exact: HashSet<String>,
+    patterns: Vec<Regex>,
+    allow: HashSet<String>,
+}
+
+impl Default for FilterRules {
+    fn default() -> Self {
+        FilterRules {
+            max_length: COPYRIGHT_TRIGGER_LENGTH,
+            threshold: COPYRIGHT_TRIGGER_THRESHOLD,
+            count: COPYRIGHT_TRIGGER_COUNT,
+            triggers: COPYRIGHT_TRIGGERS
+                .iter()
+                .map(|(trigger, weight)| (trigger.to_string(), *weight))
+                .collect(),
+            exact: HashSet::new(),
+            patterns: Vec::new(),
+            allow: HashSet::new(),
+        }
+    }
+}
+
+impl FilterRules {
+    /// Loads a rules file on top of the built-in rules, with the overrides of the book's site
+    pub fn load(path: &Path, url: &str) -> Result<Self, Error> {
+        log::debug!("loading filter rules {:?}", path);
+        let set: RuleSet = serde_json::from_str(&fs::read_to_string(path)?)
+            .map_err(|e| Error::json(format!("{:?}: {}", path, e)))?;
+        let mut rules = FilterRules::default();
+        rules.apply(&set)?;
+        let host = Url::parse(&utils::normalize_url(url))
+            .ok()
+            .and_then(|url| url.host_str().map(String::from))
+            .unwrap_or_default();
+        if let Some(site) = set.sites.get(&host) {
+            log::debug!("using filter rules of {}", host);
+            rules.apply(site)?;
+        }
+        Ok(rules)
+    }
+
+    fn apply(&mut self, set: &RuleSet) -> Result<(), Error> {
+        self.max_length = set.max_length.unwrap_or(self.max_length);
+        self.threshold = set.threshold.unwrap_or(self.threshold);
+        self.count = set.count.unwrap_or(self.count);
+        for (trigger, weight) in set.triggers.iter() {
+            let trigger = trigger.to_lowercase();
+            self.triggers.retain(|(t, _)| *t != trigger);
+            if *weight > 0 {
+                self.triggers.push((trigger, *weight));
+            }
+        }
+        self.exact.extend(set.exact.iter().map(|text| key(text)));
+        for pattern in set.patterns.iter() {
+            let regex = Regex::new(pattern)
+                .map_err(|e| Error::parser(format!("invalid filter pattern: {}", e)))?;
+            self.patterns.push(regex);
+        }
+        self.allow.extend(set.allow.iter().map(|text| key(text)));
+        Ok(())
+    }
+
+    /// Returns why the text should be dropped, `None` to keep it
+    pub fn check(&self, text: &str) -> Option<String> {
+        let key = key(text);
+        if self.allow.contains(&key) {
+            return None;
+        }
+        if self.exact.contains(&key) {
+            return Some("exact".into());
+        }
+        if let Some(pattern) = self.patterns.iter().find(|p| p.is_match(text.trim())) {
+            return Some(format!("pattern {}", pattern.as_str()));
+        }
+        if text.len() > self.max_length {
+            return None;
+        }
+        let text = text.to_lowercase();
+        let mut total_weight = 0;
+        let mut count = 0;
+        for (trigger, weight) in self.triggers.iter() {
+            if text.contains(trigger.as_str()) {
+                total_weight += weight;
+                count += 1;
+            }
+        }
+        (total_weight >= self.threshold || count >= self.count)
+            .then(|| format!("score {} ({} triggers)", total_weight, count))
+    }
+}
+
+/// Lowercase with whitespace collapsed
+fn key(text: &str) -> String {
+    text.split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .to_lowercase()
+}
+
+/// Text dropped from a chapter, as written to the audit log
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Dropped {
+    /// Chapter number (1-based)
+    pub chapter: usize,
+    pub url: String,
+
+    #[serde(flatten)]
+    pub filtered: Filtered,
+}
+
+/// Updates the audit log: entries of the chapters in `parsed` are replaced by `dropped`
+pub fn write_audit(
+    path: &Path,
+    parsed: &HashSet<String>,
+    dropped: Vec<Dropped>,
+) -> Result<(), Error> {
+    let mut entries = match path.is_file() {
+        true => {
+            serde_json::from_str::<Vec<Dropped>>(&fs::read_to_string(path)?).map_err(Error::json)?
+        }
+        false => Vec::new(),
+    };
+    entries.retain(|entry| !parsed.contains(&entry.url));
+    entries.extend(dropped);
+    entries.sort_by_key(|entry| (entry.chapter, entry.filtered.position));
+    let json = serde_json::to_string_pretty(&entries).map_err(Error::json)?;
+    utils::write_file(path, json.as_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    /// A file in the temporary directory, removed when dropped
+    struct TempFile(PathBuf);
+
+    impl TempFile {
+        fn new(name: &str, contents: &str) -> Self {
+            let path = std::env::temp_dir().join(format!("wnrake-{}-{}", std::process::id(), name));
+            fs::write(&path, contents).unwrap();
+            TempFile(path)
+        }
+    }
+
+    impl Drop for TempFile {
+        fn drop(&mut self) {
+            let _ = fs::remove_file(&self.0);
+        }
+    }
+
+    const RULES: &str = r#"{
+        "triggers": { "stolen": 0, "pirated": 10 },
+        "exact": ["Find this   novel on the OFFICIAL site."],
+        "patterns": ["(?i)^read (it|this) (at|on) \\S+$"],
+        "allow": ["Please read this on example.com"],
+        "sites": {
+            "royalroad.com": { "threshold": 100, "count": 100, "exact": ["Support me on Patreon."] }
+        }
+    }"#;
+
+    #[test]
+    fn default_rules() {
+        let rules = FilterRules::default();
+        assert!(rules.check("Hello there.").is_none());
+        assert!(rules.check(&"word ".repeat(100)).is_none());
+    }
+
+    #[test]
+    fn load_rules() {
+        let file = TempFile::new("load.json", RULES);
+        let rules = FilterRules::load(&file.0, "https://www.scribblehub.com/series/1/").unwrap();
+        assert!(
+            rules
+                .triggers
+                .iter()
+                .all(|(trigger, _)| trigger != "stolen")
+        );
+        assert!(rules.triggers.contains(&("pirated".to_string(), 10)));
+        assert_eq!(
+            rules
+                .check("find this novel on the official site.")
+                .as_deref(),
+            Some("exact")
+        );
+        assert!(
+            rules
+                .check("Read it on example.com")
+                .unwrap()
+                .starts_with("pattern")
+        );
+        assert!(rules.check("Please read this on example.com").is_none());
+        assert!(
+            rules
+                .check("This was pirated.")
+                .unwrap()
+                .starts_with("score")
+        );
+        assert!(rules.check("Support me on Patreon.").is_none());
+    }
+
+    #[test]
+    fn site_rules() {
+        let file = TempFile::new("sites.json", RULES);
+        let rules = FilterRules::load(&file.0, "https://www.royalroad.com/fiction/1/").unwrap();
+        assert_eq!(rules.threshold, 100);
+        assert_eq!(rules.count, 100);
+        assert!(rules.check("This was pirated.").is_none());
+        assert_eq!(
+            rules.check("Support me on Patreon.").as_deref(),
+            Some("exact")
+        );
+        assert_eq!(
+            rules
+                .check("Find this novel on the official site.")
+                .as_deref(),
+            Some("exact")
+        );
+    }
+
+    #[test]
+    fn invalid_rules() {
+        let file = TempFile::new("unknown.json", r#"{ "treshold": 1 }"#);
+        assert!(FilterRules::load(&file.0, "https://example.com/").is_err());
+        let file = TempFile::new("pattern.json", r#"{ "patterns": ["("] }"#);
+        assert!(FilterRules::load(&file.0, "https://example.com/").is_err());
+    }
+
+    fn dropped(chapter: usize, position: usize, text: &str) -> Dropped {
+        Dropped {
+            chapter,
+            url: format!("https://example.com/{}", chapter),
+            filtered: Filtered {
+                position,
+                reason: "exact".into(),
+                text: text.into(),
+            },
+        }
+    }
+
+    #[test]
+    fn audit_merge() {
+        let file = TempFile::new("audit.json", "");
+        fs::remove_file(&file.0).unwrap();
+        let parsed = (1..=3)
+            .map(|chapter| format!("https://example.com/{}", chapter))
+            .collect::<HashSet<_>>();
+        write_audit(
+            &file.0,
+            &parsed,
+            vec![dropped(3, 1, "c"), dropped(1, 2, "a2"), dropped(1, 1, "a1")],
+        )
+        .unwrap();
+
+        // Chapter 1 is parsed again without dropped text, chapter 2 drops some now
+        let parsed = HashSet::from([
+            "https://example.com/1".into(),
+            "https://example.com/2".into(),
+        ]);
+        write_audit(&file.0, &parsed, vec![dropped(2, 4, "b")]).unwrap();
+        let entries: Vec<Dropped> =
+            serde_json::from_str(&fs::read_to_string(&file.0).unwrap()).unwrap();
+        let texts = entries
+            .iter()
+            .map(|entry| (entry.chapter, entry.filtered.text.as_str()))
+            .collect::<Vec<_>>();
+        assert_eq!(texts, [(2, "b"), (3, "c")]);
+        assert!(
+            fs::read_to_string(&file.0)
+                .unwrap()
+                .contains("\"position\": 4")
+        );
+    }
+}
@@ -1,10 +1,10 @@
 //! lightnovelpub.org parser
 
 use crate::{
-    book::{BookInfo, Chapter, UrlCache},
+    book::{BookInfo, BookStatus, Chapter, UrlCache},
     client::WnrakeClient,
     error::Error,
-    parser::{utils, Downloader, Parser},
+    parser::{Downloader, ParseOptions, Parser, utils},
 };
 use async_trait::async_trait;
 use crawler::{Request, WaitFor};
@@ -15,11 +15,12 @@ pub struct LightNovelPubParser;
 
 #[async_trait]
 impl Downloader for LightNovelPubParser {
-    async fn get_book_info(&self, client: &mut WnrakeClient, url: &str) -> Result<String, Error> {
+    async fn get_book_info(&self, client: &WnrakeClient, url: &str) -> Result<String, Error> {
         let res = client
             .request(
                 &Request::get(url)
                     .wait_for(WaitFor::class("novel-info"))
+                    .validate(utils::has_element("div.novel-info"))
                     .build(),
             )
             .await?;
@@ -32,7 +33,7 @@ impl Downloader for LightNovelPubParser {
 
     async fn get_chapterlist(
         &self,
-        client: &mut WnrakeClient,
+        client: &WnrakeClient,
         url: &str,
         _html: &str,
     ) -> Result<UrlCache, Error> {
@@ -92,11 +93,12 @@ impl Downloader for LightNovelPubParser {
         Ok(chapterlist)
     }
 
-    async fn get_chapter(&self, client: &mut WnrakeClient, url: &str) -> Result<String, Error> {
+    async fn get_chapter(&self, client: &WnrakeClient, url: &str) -> Result<String, Error> {
         let res = client
             .request(
                 &Request::get(url)
                     .wait_for(WaitFor::class("chapter-container"))
+                    .validate(utils::has_element("div.chapter-container"))
                     .with_kill()
                     .build(),
             )
@@ -145,14 +147,23 @@ impl Parser for LightNovelPubParser {
             .join("");
 
         // Return book info
+        let author = author.trim().trim_start_matches("Author:").trim();
         Ok(BookInfo {
-            title: title.trim().into(),
-            author: author.trim().trim_start_matches("Author:").trim().into(),
-            url: url.into(),
+            description: utils::select_text(&document, "div.summary div.content")?,
+            genres: utils::select_texts(&document, "div.categories a")?,
+            tags: utils::select_texts(&document, "div.tags a")?,
+            language: Some("en".into()),
+            status: utils::select_texts(&document, "div.header-stats span")?
+                .iter()
+                .find_map(|stat| BookStatus::from_label(stat)),
+            cover_url: utils::select_attr(&document, "figure.cover img", "data-src")?
+                .or(utils::select_attr(&document, "figure.cover img", "src")?),
+            publisher: Some("LightNovelPub".into()),
+            ..BookInfo::new(title.trim(), author, url)
         })
     }
 
-    fn parse_chapter(&self, html: &str) -> Result<Chapter, Error> {
+    fn parse_chapter(&self, html: &str, options: &ParseOptions) -> Result<Chapter, Error> {
         let document = Html::parse_document(&html);
 
         // Parse title
@@ -171,13 +182,8 @@ impl Parser for LightNovelPubParser {
             .ok_or(Error::html("chapterText not in html", true))?;
 
         // Build HTML
-        let html = utils::parse_content(&title, chapter)?;
-
         // Return chapter
-        Ok(Chapter {
-            title: title.trim().into(),
-            html,
-        })
+        utils::parse_chapter(title.trim(), chapter, Vec::new(), Vec::new(), options)
     }
 
     fn next_page(&self, html: &str) -> Result<Option<String>, Error> {
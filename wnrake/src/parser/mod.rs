@@ -4,12 +4,14 @@ use crate::{
     book::{BookInfo, Chapter, UrlCache},
     client::WnrakeClient,
     error::Error,
+    utils as crate_utils,
 };
 use async_trait::async_trait;
 use crawler::Url;
 
 mod fanfiction_net;
 mod file;
+mod filter;
 mod lightnovelpub_org;
 mod novelfull_net;
 mod phrases;
@@ -22,30 +24,39 @@ mod wanderinginn_com;
 
 pub use fanfiction_net::FanfictionParser;
 pub use file::FileParser;
+pub use filter::{Dropped, FilterRules, write_audit};
 pub use lightnovelpub_org::LightNovelPubParser;
 pub use novelfull_net::NovelFullNetParser;
 pub use ranobes_net::RanobesParser as RanobesParserNet;
 pub use ranobes_top::RanobesParser;
 pub use royalroad_com::RoyalRoadParser;
 pub use scribblehub_com::ScribbleHubParser;
+pub use utils::{DEFAULT_TYPOGRAPHY, Typography, TypographyRule};
 pub use wanderinginn_com::WanderingInnParser;
 
 /// Trait for webnovel parsers
 #[async_trait]
 pub trait Downloader {
     /// Returns the novel's landing page HTML
-    async fn get_book_info(&self, client: &mut WnrakeClient, url: &str) -> Result<String, Error>;
+    async fn get_book_info(&self, client: &WnrakeClient, url: &str) -> Result<String, Error>;
 
     /// Returns a list of URLs for each chapter (in order)
     async fn get_chapterlist(
         &self,
-        client: &mut WnrakeClient,
+        client: &WnrakeClient,
         url: &str,
         html: &str,
     ) -> Result<UrlCache, Error>;
 
     /// Returns the chapter's HTML
-    async fn get_chapter(&self, client: &mut WnrakeClient, url: &str) -> Result<String, Error>;
+    async fn get_chapter(&self, client: &WnrakeClient, url: &str) -> Result<String, Error>;
+}
+
+/// How chapter text is cleaned up, usually the book's settings
+#[derive(Clone, Debug, Default)]
+pub struct ParseOptions {
+    pub typography: Typography,
+    pub filter: FilterRules,
 }
 
 pub trait Parser {
@@ -53,10 +64,29 @@ pub trait Parser {
     fn parse_book_info(&self, url: &str, html: &str) -> Result<BookInfo, Error>;
 
     /// Parses the HTML of the novel's chapter
-    fn parse_chapter(&self, html: &str) -> Result<Chapter, Error>;
+    fn parse_chapter(&self, html: &str, options: &ParseOptions) -> Result<Chapter, Error>;
 
     /// Parses the HTML of the current page and returns the URL of the next chapter's page
     fn next_page(&self, html: &str) -> Result<Option<String>, Error>;
+
+    /// Returns the site's ID of the chapter, if its URL has one
+    fn chapter_id(&self, _url: &str) -> Option<String> {
+        None
+    }
+
+    /// Parses the HTML of the novel's landing page and returns (chapter URL, volume) pairs
+    fn parse_volumes(&self, _html: &str) -> Result<Vec<(String, String)>, Error> {
+        Ok(Vec::new())
+    }
+}
+
+/// Returns a stable identifier for a chapter: the site's chapter ID when the parser knows one,
+/// a hash of the normalized URL otherwise
+pub fn chapter_id(url: &str) -> String {
+    match WnParser::try_from(url).ok().and_then(|parser| parser.chapter_id(url)) {
+        Some(id) => id,
+        None => crate_utils::hash(crate_utils::normalize_url(url).as_bytes())[..16].into(),
+    }
 }
 
 #[derive(Clone, Debug)]
@@ -104,7 +134,7 @@ impl TryFrom<&str> for WnParser {
 
 #[async_trait]
 impl Downloader for WnParser {
-    async fn get_book_info(&self, client: &mut WnrakeClient, url: &str) -> Result<String, Error> {
+    async fn get_book_info(&self, client: &WnrakeClient, url: &str) -> Result<String, Error> {
         match self {
             WnParser::Fanfiction(parser) => parser.get_book_info(client, url).await,
             WnParser::File(parser) => parser.get_book_info(client, url).await,
@@ -120,7 +150,7 @@ impl Downloader for WnParser {
 
     async fn get_chapterlist(
         &self,
-        client: &mut WnrakeClient,
+        client: &WnrakeClient,
         url: &str,
         html: &str,
     ) -> Result<UrlCache, Error> {
@@ -137,7 +167,7 @@ impl Downloader for WnParser {
         }
     }
 
-    async fn get_chapter(&self, client: &mut WnrakeClient, url: &str) -> Result<String, Error> {
+    async fn get_chapter(&self, client: &WnrakeClient, url: &str) -> Result<String, Error> {
         match self {
             WnParser::Fanfiction(parser) => parser.get_chapter(client, url).await,
             WnParser::File(parser) => parser.get_chapter(client, url).await,
@@ -167,17 +197,17 @@ impl Parser for WnParser {
         }
     }
 
-    fn parse_chapter(&self, html: &str) -> Result<Chapter, Error> {
+    fn parse_chapter(&self, html: &str, options: &ParseOptions) -> Result<Chapter, Error> {
         match self {
-            WnParser::Fanfiction(parser) => parser.parse_chapter(html),
-            WnParser::File(parser) => parser.parse_chapter(html),
-            WnParser::LightNovelPub(parser) => parser.parse_chapter(html),
-            WnParser::NovelFullNet(parser) => parser.parse_chapter(html),
-            WnParser::Ranobes(parser) => parser.parse_chapter(html),
-            WnParser::RanobesNet(parser) => parser.parse_chapter(html),
-            WnParser::RoyalRoad(parser) => parser.parse_chapter(html),
-            WnParser::ScribbleHub(parser) => parser.parse_chapter(html),
-            WnParser::WanderingInn(parser) => parser.parse_chapter(html),
+            WnParser::Fanfiction(parser) => parser.parse_chapter(html, options),
+            WnParser::File(parser) => parser.parse_chapter(html, options),
+            WnParser::LightNovelPub(parser) => parser.parse_chapter(html, options),
+            WnParser::NovelFullNet(parser) => parser.parse_chapter(html, options),
+            WnParser::Ranobes(parser) => parser.parse_chapter(html, options),
+            WnParser::RanobesNet(parser) => parser.parse_chapter(html, options),
+            WnParser::RoyalRoad(parser) => parser.parse_chapter(html, options),
+            WnParser::ScribbleHub(parser) => parser.parse_chapter(html, options),
+            WnParser::WanderingInn(parser) => parser.parse_chapter(html, options),
         }
     }
 
@@ -194,4 +224,32 @@ impl Parser for WnParser {
             WnParser::WanderingInn(parser) => parser.next_page(html),
         }
     }
+
+    fn chapter_id(&self, url: &str) -> Option<String> {
+        match self {
+            WnParser::Fanfiction(parser) => parser.chapter_id(url),
+            WnParser::File(parser) => parser.chapter_id(url),
+            WnParser::LightNovelPub(parser) => parser.chapter_id(url),
+            WnParser::NovelFullNet(parser) => parser.chapter_id(url),
+            WnParser::Ranobes(parser) => parser.chapter_id(url),
+            WnParser::RanobesNet(parser) => parser.chapter_id(url),
+            WnParser::RoyalRoad(parser) => parser.chapter_id(url),
+            WnParser::ScribbleHub(parser) => parser.chapter_id(url),
+            WnParser::WanderingInn(parser) => parser.chapter_id(url),
+        }
+    }
+
+    fn parse_volumes(&self, html: &str) -> Result<Vec<(String, String)>, Error> {
+        match self {
+            WnParser::Fanfiction(parser) => parser.parse_volumes(html),
+            WnParser::File(parser) => parser.parse_volumes(html),
+            WnParser::LightNovelPub(parser) => parser.parse_volumes(html),
+            WnParser::NovelFullNet(parser) => parser.parse_volumes(html),
+            WnParser::Ranobes(parser) => parser.parse_volumes(html),
+            WnParser::RanobesNet(parser) => parser.parse_volumes(html),
+            WnParser::RoyalRoad(parser) => parser.parse_volumes(html),
+            WnParser::ScribbleHub(parser) => parser.parse_volumes(html),
+            WnParser::WanderingInn(parser) => parser.parse_volumes(html),
+        }
+    }
 }
@@ -1,10 +1,10 @@
 //! novelfull.net parser
 
 use crate::{
-    book::{BookInfo, Chapter, UrlCache},
+    book::{BookInfo, BookStatus, Chapter, UrlCache},
     client::WnrakeClient,
     error::Error,
-    parser::{utils, Downloader, Parser},
+    parser::{Downloader, ParseOptions, Parser, utils},
 };
 use async_trait::async_trait;
 use crawler::{Request, WaitFor};
@@ -16,9 +16,14 @@ pub struct NovelFullNetParser;
 
 #[async_trait]
 impl Downloader for NovelFullNetParser {
-    async fn get_book_info(&self, client: &mut WnrakeClient, url: &str) -> Result<String, Error> {
+    async fn get_book_info(&self, client: &WnrakeClient, url: &str) -> Result<String, Error> {
         let res = client
-            .request(&Request::get(url).wait_for(WaitFor::class("info")).build())
+            .request(
+                &Request::get(url)
+                    .wait_for(WaitFor::class("info"))
+                    .validate(utils::has_element("div.info"))
+                    .build(),
+            )
             .await?;
         let document = Html::parse_document(&res);
         match document.select(&Selector::parse("div.info")?).next() {
@@ -29,7 +34,7 @@ impl Downloader for NovelFullNetParser {
 
     async fn get_chapterlist(
         &self,
-        client: &mut WnrakeClient,
+        client: &WnrakeClient,
         _url: &str,
         html: &str,
     ) -> Result<UrlCache, Error> {
@@ -76,11 +81,12 @@ impl Downloader for NovelFullNetParser {
         Ok(chapterlist)
     }
 
-    async fn get_chapter(&self, client: &mut WnrakeClient, url: &str) -> Result<String, Error> {
+    async fn get_chapter(&self, client: &WnrakeClient, url: &str) -> Result<String, Error> {
         let res = client
             .request(
                 &Request::get(url)
                     .wait_for(WaitFor::id("chapter-content"))
+                    .validate(utils::has_element("div#chapter-content"))
                     .with_kill()
                     .build(),
             )
@@ -116,19 +122,39 @@ impl Parser for NovelFullNetParser {
             .collect::<Vec<_>>()
             .join("");
 
+        // The info block lists "Author:", "Genre:", "Source:" and "Status:" rows
+        let info = utils::select_texts(&document, "div.info > div")?;
+        let row = |label: &str| {
+            info.iter()
+                .find(|row| row.starts_with(label))
+                .map(|row| row.trim_start_matches(label).trim().to_string())
+        };
+        let genres = row("Genre:")
+            .map(|genres| genres.split(',').map(|g| g.trim().to_string()).collect())
+            .unwrap_or_default();
+
         // Return book info
         Ok(BookInfo {
-            title: title_h3.trim().into(),
-            author: author_div
-                .trim()
-                .trim_start_matches("Author:")
-                .trim()
-                .into(),
-            url: url.into(),
+            description: utils::select_text(&document, "div.desc-text")?,
+            genres,
+            language: Some("en".into()),
+            status: row("Status:").and_then(|status| BookStatus::from_label(&status)),
+            cover_url: utils::select_attr(&document, "div.book img", "src")?.map(|src| {
+                match src.starts_with("http") {
+                    true => src,
+                    false => format!("https://novelfull.net{}", src),
+                }
+            }),
+            publisher: Some("NovelFull".into()),
+            ..BookInfo::new(
+                title_h3.trim(),
+                author_div.trim().trim_start_matches("Author:").trim(),
+                url,
+            )
         })
     }
 
-    fn parse_chapter(&self, html: &str) -> Result<Chapter, Error> {
+    fn parse_chapter(&self, html: &str, options: &ParseOptions) -> Result<Chapter, Error> {
         let document = Html::parse_document(&html);
 
         // Kill some stuff first
@@ -158,13 +184,8 @@ impl Parser for NovelFullNetParser {
             .ok_or(Error::html("chapter-content not in html", true))?;
 
         // Build HTML
-        let html = utils::parse_content(&title, chapter)?;
-
         // Return chapter
-        Ok(Chapter {
-            title: title.trim().into(),
-            html,
-        })
+        utils::parse_chapter(title.trim(), chapter, Vec::new(), Vec::new(), options)
     }
 
     fn next_page(&self, html: &str) -> Result<Option<String>, Error> {
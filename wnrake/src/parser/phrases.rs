@@ -56,3 +56,19 @@ pub static COPYRIGHT_TRIGGERS: &[(&str, usize)] = &[
     ("platform", 2),
     ("website", 3),
 ];
+
+/// Lowercase prefixes of translator notes
+pub static TL_NOTE_MARKERS: &[&str] = &[
+    "tl note",
+    "tl's note",
+    "tl:",
+    "t/n",
+    "tn:",
+    "translator note",
+    "translator's note",
+    "translators note",
+    "[tl",
+    "(tl",
+    "[t/n",
+    "(t/n",
+];
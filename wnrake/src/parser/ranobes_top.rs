@@ -4,7 +4,7 @@ use crate::{
     book::{BookInfo, Chapter, UrlCache},
     client::WnrakeClient,
     error::Error,
-    parser::{utils, Downloader, Parser},
+    parser::{Downloader, ParseOptions, Parser, utils},
 };
 use async_trait::async_trait;
 use crawler::{Request, WaitFor};
@@ -17,11 +17,12 @@ pub struct RanobesParser;
 
 #[async_trait]
 impl Downloader for RanobesParser {
-    async fn get_book_info(&self, client: &mut WnrakeClient, url: &str) -> Result<String, Error> {
+    async fn get_book_info(&self, client: &WnrakeClient, url: &str) -> Result<String, Error> {
         let res = client
             .request(
                 &Request::get(url)
                     .wait_for(WaitFor::id("dle-content"))
+                    .validate(utils::has_element("div.r-fullstory-s1"))
                     .build(),
             )
             .await?;
@@ -37,7 +38,7 @@ impl Downloader for RanobesParser {
 
     async fn get_chapterlist(
         &self,
-        client: &mut WnrakeClient,
+        client: &WnrakeClient,
         _url: &str,
         html: &str,
     ) -> Result<UrlCache, Error> {
@@ -103,11 +104,12 @@ impl Downloader for RanobesParser {
         Ok(chapterlist)
     }
 
-    async fn get_chapter(&self, client: &mut WnrakeClient, url: &str) -> Result<String, Error> {
+    async fn get_chapter(&self, client: &WnrakeClient, url: &str) -> Result<String, Error> {
         let res = client
             .request(
                 &Request::get(url)
                     .wait_for(WaitFor::id("arrticle"))
+                    .validate(utils::has_element("div#arrticle"))
                     .with_kill()
                     .build(),
             )
@@ -144,13 +146,28 @@ impl Parser for RanobesParser {
 
         // Return book info
         Ok(BookInfo {
-            title: title.into(),
-            author: author.into(),
-            url: url.into(),
+            description: json["description"].as_str().map(|d| d.trim().to_string()),
+            genres: match &json["genre"] {
+                Value::Array(genres) => genres
+                    .iter()
+                    .filter_map(|g| g.as_str().map(String::from))
+                    .collect(),
+                Value::String(genres) => genres.split(',').map(|g| g.trim().to_string()).collect(),
+                _ => Vec::new(),
+            },
+            language: json["inLanguage"].as_str().map(String::from),
+            cover_url: match &json["image"] {
+                Value::String(image) => Some(image.clone()),
+                image => image["url"].as_str().map(String::from),
+            },
+            publisher: Some("Ranobes".into()),
+            published: json["datePublished"].as_str().and_then(utils::parse_date),
+            updated: json["dateModified"].as_str().and_then(utils::parse_date),
+            ..BookInfo::new(title, author, url)
         })
     }
 
-    fn parse_chapter(&self, html: &str) -> Result<Chapter, Error> {
+    fn parse_chapter(&self, html: &str, options: &ParseOptions) -> Result<Chapter, Error> {
         let document = Html::parse_document(&html);
 
         // Kill some stuff first
@@ -204,7 +221,7 @@ impl Parser for RanobesParser {
             .ok_or(Error::html("arrticle not in html", true))?;
 
         // Build HTML
-        let html = utils::parse_content(&title, chapter)?;
+        let chapter = utils::parse_chapter(&title, chapter, Vec::new(), Vec::new(), options)?;
 
         /*
         // Build HTML
@@ -216,7 +233,7 @@ impl Parser for RanobesParser {
         */
 
         // Return chapter
-        Ok(Chapter { title, html })
+        Ok(chapter)
     }
 
     fn next_page(&self, html: &str) -> Result<Option<String>, Error> {
@@ -1,10 +1,10 @@
 //! royalroad.com parser
 
 use crate::{
-    book::{BookInfo, Chapter, UrlCache},
+    book::{BookInfo, BookStatus, Chapter, NoteKind, UrlCache},
     client::WnrakeClient,
     error::Error,
-    parser::{utils, Downloader, Parser},
+    parser::{utils, Downloader, ParseOptions, Parser},
 };
 use async_trait::async_trait;
 use crawler::{Request, WaitFor};
@@ -16,9 +16,14 @@ pub struct RoyalRoadParser;
 
 #[async_trait]
 impl Downloader for RoyalRoadParser {
-    async fn get_book_info(&self, client: &mut WnrakeClient, url: &str) -> Result<String, Error> {
+    async fn get_book_info(&self, client: &WnrakeClient, url: &str) -> Result<String, Error> {
         let res = client
-            .request(&Request::get(url).wait_for(WaitFor::id("chapters")).build())
+            .request(
+                &Request::get(url)
+                    .wait_for(WaitFor::id("chapters"))
+                    .validate(utils::has_element("#chapters"))
+                    .build(),
+            )
             .await?;
         let document = Html::parse_document(&res);
         match document.select(&Selector::parse("#chapters")?).next() {
@@ -29,7 +34,7 @@ impl Downloader for RoyalRoadParser {
 
     async fn get_chapterlist(
         &self,
-        _client: &mut WnrakeClient,
+        _client: &WnrakeClient,
         _url: &str,
         html: &str,
     ) -> Result<UrlCache, Error> {
@@ -101,11 +106,12 @@ impl Downloader for RoyalRoadParser {
         Ok(chapterlist)
     }
 
-    async fn get_chapter(&self, client: &mut WnrakeClient, url: &str) -> Result<String, Error> {
+    async fn get_chapter(&self, client: &WnrakeClient, url: &str) -> Result<String, Error> {
         let res = client
             .request(
                 &Request::get(url)
                     .wait_for(WaitFor::selector("div.chapter-content"))
+                    .validate(utils::has_element("div.chapter-content"))
                     .with_kill()
                     .build(),
             )
@@ -143,16 +149,36 @@ impl Parser for RoyalRoadParser {
         let title = title_h1.text().collect::<Vec<_>>().join("");
         let author = author_span.text().collect::<Vec<_>>().join("");
 
+        // Get dates from the chapter table
+        let dates = document
+            .select(&Selector::parse("#chapters time")?)
+            .filter_map(|time| time.attr("datetime").and_then(utils::parse_date))
+            .collect::<Vec<_>>();
+
         // Return book info
         Ok(BookInfo {
-            title: title.trim().into(),
-            author: author.trim().into(),
-            url: url.into(),
+            description: utils::select_text(&document, "div.description")?,
+            tags: utils::select_texts(&document, "span.tags a")?,
+            language: Some("en".into()),
+            status: utils::select_texts(&document, "div.fiction-info span.label")?
+                .iter()
+                .find_map(|label| BookStatus::from_label(label)),
+            cover_url: utils::select_attr(&document, "meta[property=\"og:image\"]", "content")?,
+            publisher: Some("Royal Road".into()),
+            published: dates.iter().min().cloned(),
+            updated: dates.iter().max().cloned(),
+            ..BookInfo::new(title.trim(), author.trim(), url)
         })
     }
 
-    fn parse_chapter(&self, html: &str) -> Result<Chapter, Error> {
-        let document = Html::parse_document(&html);
+    fn parse_chapter(&self, html: &str, options: &ParseOptions) -> Result<Chapter, Error> {
+        let mut document = Html::parse_document(&html);
+
+        // Anti-theft paragraphs are hidden by the page's stylesheet
+        let hidden = utils::remove_hidden(&mut document)?;
+        if hidden > 0 {
+            log::debug!("removed {} hidden elements", hidden);
+        }
 
         // Get title
         let title = document
@@ -171,8 +197,17 @@ impl Parser for RoyalRoadParser {
             .next()
             .ok_or(Error::html("expected div.chapter-content", true))?;
 
+        // Author notes come before or after the chapter
+        let notes = document
+            .select(&Selector::parse("div.author-note")?)
+            .map(|note| {
+                let position = utils::position(&document, chapter, note);
+                (NoteKind::Author, position, note)
+            })
+            .collect();
+
         // Build HTML
-        let html = utils::parse_content(&title, chapter)?;
+        let chapter = utils::parse_chapter(&title, chapter, notes, Vec::new(), options)?;
 
         /*
         // Build HTML
@@ -184,7 +219,7 @@ impl Parser for RoyalRoadParser {
         */
 
         // Return chapter
-        Ok(Chapter { title, html })
+        Ok(chapter)
     }
 
     fn next_page(&self, html: &str) -> Result<Option<String>, Error> {
@@ -213,4 +248,52 @@ impl Parser for RoyalRoadParser {
             },
         )
     }
+
+    fn chapter_id(&self, url: &str) -> Option<String> {
+        let ids = utils::path_ids(url, "chapter", 1)?;
+        Some(format!("rr-{}", ids[0]))
+    }
+
+    fn parse_volumes(&self, html: &str) -> Result<Vec<(String, String)>, Error> {
+        let document = Html::parse_document(html);
+        let mut volumes = None;
+        let mut chapters = None;
+        for row in document.select(&Selector::parse("script")?) {
+            let script = row.text().collect::<Vec<_>>().join("");
+            for line in script.split('\n').map(|line| line.trim()) {
+                if let Some(json) = line.strip_prefix("window.volumes = ") {
+                    volumes = Some(
+                        serde_json::from_str::<Value>(json.trim_end_matches(";"))
+                            .map_err(Error::json)?,
+                    );
+                } else if let Some(json) = line.strip_prefix("window.chapters = ") {
+                    chapters = Some(
+                        serde_json::from_str::<Value>(json.trim_end_matches(";"))
+                            .map_err(Error::json)?,
+                    );
+                }
+            }
+        }
+
+        // Volume ID -> title
+        let (Some(Value::Array(volumes)), Some(Value::Array(chapters))) = (volumes, chapters)
+        else {
+            return Ok(Vec::new());
+        };
+        let titles = volumes
+            .iter()
+            .filter_map(|volume| Some((volume["id"].as_u64()?, volume["title"].as_str()?)))
+            .collect::<Vec<_>>();
+        Ok(chapters
+            .iter()
+            .filter_map(|chapter| {
+                let volume_id = chapter["volumeId"].as_u64()?;
+                let (_, title) = titles.iter().find(|(id, _)| *id == volume_id)?;
+                Some((
+                    format!("https://www.royalroad.com{}", chapter["url"].as_str()?),
+                    title.trim().to_string(),
+                ))
+            })
+            .collect())
+    }
 }
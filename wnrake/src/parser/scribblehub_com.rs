@@ -1,10 +1,10 @@
 //! scribblehub.com parser
 
 use crate::{
-    book::{BookInfo, Chapter, UrlCache},
+    book::{BookInfo, BookStatus, Chapter, NoteKind, NotePosition, UrlCache},
     client::WnrakeClient,
     error::Error,
-    parser::{utils, Downloader, Parser},
+    parser::{utils, Downloader, ParseOptions, Parser},
 };
 use async_trait::async_trait;
 use crawler::{Cookie, Request, WaitFor};
@@ -15,11 +15,12 @@ pub struct ScribbleHubParser;
 
 #[async_trait]
 impl Downloader for ScribbleHubParser {
-    async fn get_book_info(&self, client: &mut WnrakeClient, url: &str) -> Result<String, Error> {
+    async fn get_book_info(&self, client: &WnrakeClient, url: &str) -> Result<String, Error> {
         let res = client
             .request(
                 &Request::get(url)
                     .wait_for(WaitFor::class("fic_title"))
+                    .validate(utils::has_element("div.fic_title"))
                     .build(),
             )
             .await?;
@@ -32,7 +33,7 @@ impl Downloader for ScribbleHubParser {
 
     async fn get_chapterlist(
         &self,
-        client: &mut WnrakeClient,
+        client: &WnrakeClient,
         url: &str,
         _: &str,
     ) -> Result<UrlCache, Error> {
@@ -101,11 +102,12 @@ impl Downloader for ScribbleHubParser {
         Ok(chapterlist)
     }
 
-    async fn get_chapter(&self, client: &mut WnrakeClient, url: &str) -> Result<String, Error> {
+    async fn get_chapter(&self, client: &WnrakeClient, url: &str) -> Result<String, Error> {
         let res = client
             .request(
                 &Request::get(url)
                     .wait_for(WaitFor::id("main read chapter"))
+                    .validate(utils::has_element("main[id='main read chapter']"))
                     .with_kill()
                     .build(),
             )
@@ -149,13 +151,20 @@ impl Parser for ScribbleHubParser {
 
         // Return book info
         Ok(BookInfo {
-            title,
-            author,
-            url: url.into(),
+            description: utils::select_text(&document, "div.wi_fic_desc")?,
+            genres: utils::select_texts(&document, "a.fic_genre")?,
+            tags: utils::select_texts(&document, "a.stag")?,
+            language: Some("en".into()),
+            status: utils::select_texts(&document, "ul.widget_fic_similar li")?
+                .iter()
+                .find_map(|item| BookStatus::from_label(item)),
+            cover_url: utils::select_attr(&document, "div.fic_image img", "src")?,
+            publisher: Some("Scribble Hub".into()),
+            ..BookInfo::new(title, author, url)
         })
     }
 
-    fn parse_chapter(&self, html: &str) -> Result<Chapter, Error> {
+    fn parse_chapter(&self, html: &str, options: &ParseOptions) -> Result<Chapter, Error> {
         let document = Html::parse_document(&html);
 
         // Get title
@@ -193,8 +202,17 @@ impl Parser for ScribbleHubParser {
             .next()
             .ok_or(Error::html("expected id=chp_raw", true))?;
 
+        // Author notes sit at the end of the chapter
+        let notes = chapter
+            .select(&Selector::parse("div.wi_authornotes_body")?)
+            .map(|note| (NoteKind::Author, NotePosition::After, note))
+            .collect();
+
+        // Footnotes use the Modern Footnotes markup
+        let footnotes = utils::modern_footnotes(chapter)?;
+
         // Build HTML
-        let html = utils::parse_content(&title, chapter)?;
+        let chapter = utils::parse_chapter(&title, chapter, notes, footnotes, options)?;
 
         /*
         // Build HTML
@@ -206,7 +224,7 @@ impl Parser for ScribbleHubParser {
         */
 
         // Return chapter
-        Ok(Chapter { title, html })
+        Ok(chapter)
     }
 
     fn next_page(&self, html: &str) -> Result<Option<String>, Error> {
@@ -232,6 +250,11 @@ impl Parser for ScribbleHubParser {
             },
         )
     }
+
+    fn chapter_id(&self, url: &str) -> Option<String> {
+        let ids = utils::path_ids(url, "chapter", 1)?;
+        Some(format!("sh-{}", ids[0]))
+    }
 }
 
 impl ScribbleHubParser {
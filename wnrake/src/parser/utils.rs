@@ -1,46 +1,597 @@
 //! parser utilities
 
 use crate::{
+    book::{Chapter, Filtered, Note, NoteKind, NotePosition},
     error::Error,
-    parser::phrases::*,
+    parser::{ParseOptions, phrases::*},
     xhtml::{Element, XhtmlBuilder, XhtmlNode},
 };
-use ego_tree::NodeRef;
-use scraper::{ElementRef, Node};
+use chrono::{DateTime, NaiveDate, NaiveDateTime, Utc};
+use crawler::Url;
+use ego_tree::{NodeId, NodeRef};
+use scraper::{ElementRef, Html, Node, Selector};
+use serde::{Deserialize, Serialize};
+use std::{
+    cell::{Cell, RefCell},
+    collections::{HashMap, HashSet},
+};
+use unicode_normalization::UnicodeNormalization;
+
+/// Typographic rules applied to chapter text
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum TypographyRule {
+    /// Curly quotes and apostrophes
+    SmartQuotes,
+
+    /// `...` becomes an ellipsis character
+    Ellipsis,
+
+    /// `--` becomes an em dash
+    Dashes,
+
+    /// Collapses runs of spaces, tabs, line breaks and non-breaking spaces
+    Whitespace,
+
+    /// Removes zero-width characters, soft hyphens and control characters
+    Invisible,
+
+    /// Unicode NFC normalization
+    Nfc,
+}
+
+/// Rules used when the book has none
+pub static DEFAULT_TYPOGRAPHY: &[TypographyRule] = &[
+    TypographyRule::Whitespace,
+    TypographyRule::Invisible,
+    TypographyRule::Nfc,
+];
+
+/// Words starting with an apostrophe rather than an opening quote
+static ELISIONS: &[&str] = &["bout", "cause", "em", "n", "round", "til", "tis", "twas"];
+
+/// Text normalization run on every text node before it is added to a chapter
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Typography {
+    smart_quotes: bool,
+    ellipsis: bool,
+    dashes: bool,
+    whitespace: bool,
+    invisible: bool,
+    nfc: bool,
+}
+
+impl Default for Typography {
+    fn default() -> Self {
+        Typography::new(DEFAULT_TYPOGRAPHY)
+    }
+}
+
+impl Typography {
+    pub fn new(rules: &[TypographyRule]) -> Self {
+        Typography {
+            smart_quotes: rules.contains(&TypographyRule::SmartQuotes),
+            ellipsis: rules.contains(&TypographyRule::Ellipsis),
+            dashes: rules.contains(&TypographyRule::Dashes),
+            whitespace: rules.contains(&TypographyRule::Whitespace),
+            invisible: rules.contains(&TypographyRule::Invisible),
+            nfc: rules.contains(&TypographyRule::Nfc),
+        }
+    }
+
+    /// Normalizes a text node. `last` is the last character of the text before it in the same
+    /// block, used to decide which way a quote at the start of the node goes.
+    pub fn normalize(&self, text: &str, last: Option<char>) -> String {
+        let mut text = text.to_string();
+        if self.invisible {
+            text.retain(|c| {
+                !matches!(
+                    c,
+                    '\u{ad}' | '\u{200b}' | '\u{200e}' | '\u{200f}' | '\u{2060}' | '\u{feff}'
+                ) && (!c.is_control() || c.is_whitespace())
+            });
+        }
+        if self.nfc {
+            text = text.nfc().collect();
+        }
+        if self.whitespace {
+            text = collapse_whitespace(&text);
+        }
+        if self.ellipsis {
+            text = text.replace(". . .", "...");
+            while text.contains("....") {
+                text = text.replace("....", "...");
+            }
+            text = text.replace("...", "\u{2026}");
+        }
+        if self.dashes {
+            text = text.replace("---", "\u{2014}").replace("--", "\u{2014}");
+        }
+        if self.smart_quotes {
+            text = smart_quotes(&text, last);
+        }
+        text
+    }
+}
 
-/// Look for copyright indicators to decide whether to filter this text.
-pub fn filter(text: &str) -> bool {
-    if text.len() > COPYRIGHT_TRIGGER_LENGTH {
-        return false;
+/// Runs of whitespace become one space; a single non-breaking space is kept
+fn collapse_whitespace(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        if !c.is_whitespace() {
+            out.push(c);
+            continue;
+        }
+        let mut run = 1;
+        while chars.next_if(|c| c.is_whitespace()).is_some() {
+            run += 1;
+        }
+        out.push(match (run, c) {
+            (1, '\u{a0}') => '\u{a0}',
+            _ => ' ',
+        });
+    }
+    out
+}
+
+/// Curls straight quotes. A quote opens after a space, an opening bracket or a dash. At the
+/// start of a block it opens unless followed by a space or punctuation, so dialogue carried on
+/// in a new paragraph (`"...` with no closing quote before it) opens again. `last` continues the
+/// text of the block across inline elements (`<em>John</em>'s`).
+fn smart_quotes(text: &str, last: Option<char>) -> String {
+    let chars = text.chars().collect::<Vec<_>>();
+    let mut out = String::with_capacity(text.len());
+    for (i, c) in chars.iter().enumerate() {
+        if *c != '"' && *c != '\'' {
+            out.push(*c);
+            continue;
+        }
+        let rest = &chars[i + 1..];
+        // Quotes before this one are already curled
+        let prev = out.chars().last().or(last);
+        let opens = match (prev, rest.first()) {
+            (Some(prev), next) => opens_after(prev) && next.is_none_or(|c| !c.is_whitespace()),
+            (None, Some(next)) => !next.is_whitespace() && !closes_before(rest),
+            // A lone quote opening a block, before an inline element
+            (None, None) => true,
+        };
+        out.push(match (*c, opens) {
+            ('"', true) => '\u{201c}',
+            ('"', false) => '\u{201d}',
+            (_, true) if is_elision(rest) => '\u{2019}',
+            (_, true) => '\u{2018}',
+            (_, false) => '\u{2019}',
+        });
+    }
+    out
+}
+
+fn opens_after(c: char) -> bool {
+    c.is_whitespace()
+        || matches!(
+            c,
+            '(' | '[' | '{' | '-' | '\u{2013}' | '\u{2014}' | '\u{201c}' | '\u{2018}'
+        )
+}
+
+/// Punctuation after a closing quote (`".`, `",` but not `"...`)
+fn closes_before(rest: &[char]) -> bool {
+    match rest {
+        ['.', '.', ..] => false,
+        [c, ..] => matches!(c, '.' | ',' | ';' | ':' | '!' | '?' | ')' | ']' | '}'),
+        [] => true,
+    }
+}
+
+/// `'90s`, `'em`, `'tis`, ...
+fn is_elision(rest: &[char]) -> bool {
+    if rest.first().is_some_and(|c| c.is_ascii_digit()) {
+        return true;
     }
-    let text = text.to_lowercase();
-    let mut total_weight = 0;
-    let mut count = 0;
-    for (trigger, weight) in COPYRIGHT_TRIGGERS {
-        if text.contains(trigger) {
-            total_weight = total_weight + weight;
-            count = count + 1;
+    let word = rest
+        .iter()
+        .take_while(|c| c.is_alphabetic())
+        .collect::<String>()
+        .to_lowercase();
+    ELISIONS.contains(&word.as_str())
+}
+
+/// A footnote in the chapter content: the marker in the text and the note it points to
+#[derive(Clone, Copy, Debug)]
+pub struct Footnote<'a> {
+    pub reference: ElementRef<'a>,
+    pub body: ElementRef<'a>,
+}
+
+/// Nodes of the content that are not parsed as text, and the state of the text so far
+#[derive(Debug)]
+struct Marks<'o> {
+    options: &'o ParseOptions,
+
+    /// Left out
+    skip: HashSet<NodeId>,
+
+    /// Footnote references and their numbers
+    noterefs: HashMap<NodeId, usize>,
+
+    /// Last character of the text in the current block, for [`Typography::normalize`]
+    last: Cell<Option<char>>,
+
+    /// Number of text nodes so far
+    position: Cell<usize>,
+
+    /// Text dropped by the copyright filter
+    filtered: RefCell<Vec<Filtered>>,
+}
+
+impl<'o> Marks<'o> {
+    fn new(options: &'o ParseOptions) -> Self {
+        Marks {
+            options,
+            skip: HashSet::new(),
+            noterefs: HashMap::new(),
+            last: Cell::new(None),
+            position: Cell::new(0),
+            filtered: RefCell::new(Vec::new()),
         }
     }
-    total_weight >= COPYRIGHT_TRIGGER_THRESHOLD || count >= COPYRIGHT_TRIGGER_COUNT
 }
 
-/// Generic parse_content function. Should be good enough for most scenarios.
-pub fn parse_content<'a>(title: &str, content: ElementRef<'a>) -> Result<String, Error> {
+/// Generic parse_content function. Should be good enough for most scenarios. Footnotes are
+/// numbered in the order of their references and placed at the end of the article.
+fn parse_content<'a>(
+    title: &str,
+    content: ElementRef<'a>,
+    marks: &mut Marks,
+    footnotes: &[Footnote<'a>],
+) -> Result<String, Error> {
+    for (i, footnote) in footnotes.iter().enumerate() {
+        marks.noterefs.insert(footnote.reference.id(), i + 1);
+        marks.skip.insert(footnote.body.id());
+    }
     let xhtml = XhtmlBuilder::with_header(title);
     let article = xhtml.article();
-    parse_inner_content(&xhtml, article, content)?;
+    parse_inner_content(&xhtml, article, content, marks)?;
+    for (i, footnote) in footnotes.iter().enumerate() {
+        let n = i + 1;
+        let aside = xhtml.append_element_with_attrs(
+            article,
+            Element::Aside,
+            &[
+                ("epub:type", "footnote"),
+                ("id", &format!("fn-{}", n)),
+                ("class", "footnote"),
+            ],
+        )?;
+
+        // Links back to the reference are replaced by our own
+        marks.skip.remove(&footnote.body.id());
+        for link in footnote.body.select(&Selector::parse("a[href^='#']")?) {
+            marks.skip.insert(link.id());
+        }
+        parse_inner_content(&xhtml, aside, footnote.body, marks)?;
+        let back =
+            xhtml.append_element_with_attrs(aside, Element::P, &[("class", "footnote-back")])?;
+        let link = xhtml.append_element_with_attrs(
+            back,
+            Element::A,
+            &[("href", &format!("#fnref-{}", n))],
+        )?;
+        xhtml.append_text(link, "\u{21a9}".to_string());
+    }
     Ok(xhtml.build())
 }
 
+/// Generic parse_chapter function: parses the content, the site's author notes and footnotes.
+/// Translator notes at the top level of the content are split off. Notes inside the content are
+/// left out of the text. Footnotes the site does not mark are found with [`find_footnotes`].
+pub fn parse_chapter<'a>(
+    title: &str,
+    content: ElementRef<'a>,
+    notes: Vec<(NoteKind, NotePosition, ElementRef<'a>)>,
+    footnotes: Vec<Footnote<'a>>,
+    options: &ParseOptions,
+) -> Result<Chapter, Error> {
+    let notes = notes
+        .into_iter()
+        .chain(translator_notes(content))
+        .collect::<Vec<_>>();
+    let mut marks = Marks::new(options);
+    marks.skip = notes
+        .iter()
+        .map(|(_, _, note)| top_level(content, *note))
+        .collect();
+    let footnotes = footnotes
+        .into_iter()
+        .chain(find_footnotes(content))
+        .filter(|footnote| !marks.skip.contains(&top_level(content, footnote.reference)))
+        .collect::<Vec<_>>();
+    let html = parse_content(
+        title,
+        content,
+        &mut marks,
+        &sort_footnotes(content, footnotes),
+    )?;
+    let mut chapter = Chapter {
+        title: title.to_string(),
+        html,
+        notes: Vec::new(),
+        filtered: Vec::new(),
+    };
+    for (kind, position, note) in notes {
+        let xhtml = XhtmlBuilder::new(title);
+        let note_marks = Marks::new(options);
+        note_marks.position.set(marks.position.get());
+        parse_inner_content(&xhtml, xhtml.article(), note, &note_marks)?;
+        marks.position.set(note_marks.position.get());
+        marks.filtered.get_mut().extend(note_marks.filtered.take());
+        let html = xhtml.build();
+        let html = match (html.find("<article>"), html.rfind("</article>")) {
+            (Some(start), Some(end)) if start + 9 <= end => html[start + 9..end].trim().to_string(),
+            _ => continue,
+        };
+        if !html.is_empty() {
+            log::debug!("found {:?} note {:?}", kind, position);
+            chapter.notes.push(Note {
+                kind,
+                position,
+                html,
+            });
+        }
+    }
+    chapter.filtered = marks.filtered.take();
+    Ok(chapter)
+}
+
+/// Returns the child of `content` holding `node`, or `node` if it is outside the content
+fn top_level(content: ElementRef, node: ElementRef) -> NodeId {
+    let mut top = *node;
+    while let Some(parent) = top.parent() {
+        if parent.id() == content.id() {
+            return top.id();
+        }
+        top = parent;
+    }
+    node.id()
+}
+
+/// Returns whether `note` comes before or after `content` in the document
+pub fn position(document: &Html, content: ElementRef, note: ElementRef) -> NotePosition {
+    for node in document.root_element().descendants() {
+        if node.id() == note.id() {
+            return NotePosition::Before;
+        }
+        if node.id() == content.id() {
+            return NotePosition::After;
+        }
+    }
+    NotePosition::After
+}
+
+/// Generic footnote heuristic:
+///
+/// - links to an element of the content whose text is a short marker (`1`, `[2]`, `*`, ...)
+/// - `<sup>[N]</sup>` (or `<sup>N</sup>`) with a later top-level block starting with `[N]`
+pub fn find_footnotes<'a>(content: ElementRef<'a>) -> Vec<Footnote<'a>> {
+    let mut footnotes = Vec::new();
+    let elements = content
+        .descendants()
+        .filter_map(ElementRef::wrap)
+        .enumerate()
+        .collect::<Vec<_>>();
+    let mut targets = HashMap::new();
+    for (i, el) in elements.iter() {
+        for id in [el.attr("id"), el.attr("name")].into_iter().flatten() {
+            targets.entry(id).or_insert((*i, *el));
+        }
+    }
+    let mut linked = HashSet::new();
+    for (i, link) in elements.iter() {
+        let Some(id) = link.attr("href").and_then(|href| href.strip_prefix('#')) else {
+            continue;
+        };
+        let (Some((j, target)), Some(_)) = (targets.get(id), footnote_marker(*link)) else {
+            continue;
+        };
+        // Notes follow their reference; links back to the text point the other way
+        if j <= i {
+            continue;
+        }
+        if let Some(body) = footnote_block(content, *target)
+            && !body.descendants().any(|node| node.id() == link.id())
+        {
+            linked.insert(body.id());
+            footnotes.push(Footnote {
+                reference: *link,
+                body,
+            });
+        }
+    }
+
+    // Unlinked `[N]` markers
+    let blocks = content
+        .child_elements()
+        .filter(|block| !linked.contains(&block.id()))
+        .collect::<Vec<_>>();
+    for sup in content.descendants().filter_map(ElementRef::wrap) {
+        if sup.value().name() != "sup"
+            || sup.children().any(|node| linked_ref(&footnotes, node.id()))
+        {
+            continue;
+        }
+        let Some(marker) = footnote_marker(sup).filter(|marker| marker.parse::<u32>().is_ok())
+        else {
+            continue;
+        };
+        let marker = format!("[{}]", marker);
+        let top = top_level(content, sup);
+        let body = blocks
+            .iter()
+            .skip_while(|block| block.id() != top)
+            .skip(1)
+            .find(|block| {
+                block
+                    .text()
+                    .collect::<String>()
+                    .trim_start()
+                    .starts_with(&marker)
+            });
+        if let Some(body) = body
+            && !footnotes
+                .iter()
+                .any(|footnote| footnote.body.id() == body.id())
+        {
+            footnotes.push(Footnote {
+                reference: sup,
+                body: *body,
+            });
+        }
+    }
+    footnotes
+}
+
+/// The WordPress "Modern Footnotes" plugin: `<sup class="modern-footnotes-footnote">` followed by
+/// a `<span class="modern-footnotes-footnote__note">` holding the note
+pub fn modern_footnotes<'a>(content: ElementRef<'a>) -> Result<Vec<Footnote<'a>>, Error> {
+    let mut footnotes = Vec::new();
+    for reference in content.select(&Selector::parse("sup.modern-footnotes-footnote")?) {
+        let body = reference
+            .next_siblings()
+            .filter_map(ElementRef::wrap)
+            .next()
+            .filter(|el| {
+                el.value()
+                    .classes()
+                    .any(|class| class == "modern-footnotes-footnote__note")
+            });
+        if let Some(body) = body {
+            footnotes.push(Footnote { reference, body });
+        }
+    }
+    Ok(footnotes)
+}
+
+fn linked_ref(footnotes: &[Footnote], id: NodeId) -> bool {
+    footnotes
+        .iter()
+        .any(|footnote| footnote.reference.id() == id)
+}
+
+/// Returns the marker if the element's text is one (`1`, `[1]`, `(1)`, `*`, `a`)
+fn footnote_marker(el: ElementRef) -> Option<String> {
+    let text = el.text().collect::<String>();
+    let marker = text
+        .trim()
+        .trim_start_matches(['[', '('])
+        .trim_end_matches([']', ')'])
+        .trim();
+    (!marker.is_empty()
+        && marker.chars().count() <= 3
+        && marker.chars().all(|c| c.is_alphanumeric() || c == '*'))
+    .then(|| marker.to_string())
+}
+
+/// The block holding a footnote target (the target itself unless it is inline)
+fn footnote_block<'a>(content: ElementRef<'a>, target: ElementRef<'a>) -> Option<ElementRef<'a>> {
+    let mut el = target;
+    while matches!(
+        el.value().name(),
+        "a" | "sup" | "span" | "b" | "strong" | "i" | "em" | "small"
+    ) {
+        el = el.parent().and_then(ElementRef::wrap)?;
+    }
+    (el.id() != content.id()).then_some(el)
+}
+
+/// Orders footnotes by their reference and drops duplicates
+fn sort_footnotes<'a>(content: ElementRef<'a>, footnotes: Vec<Footnote<'a>>) -> Vec<Footnote<'a>> {
+    let order = content
+        .descendants()
+        .enumerate()
+        .map(|(i, node)| (node.id(), i))
+        .collect::<HashMap<_, _>>();
+    let mut footnotes = footnotes
+        .into_iter()
+        .filter(|footnote| order.contains_key(&footnote.reference.id()))
+        .collect::<Vec<_>>();
+    footnotes.sort_by_key(|footnote| order[&footnote.reference.id()]);
+    let mut seen = HashSet::new();
+    footnotes
+        .retain(|footnote| seen.insert(footnote.reference.id()) && seen.insert(footnote.body.id()));
+    footnotes
+}
+
+/// Top-level blocks of the content starting with a translator note marker ("TL note:", "T/N:",
+/// ...). Blocks before the first text block are placed before it.
+fn translator_notes<'a>(content: ElementRef<'a>) -> Vec<(NoteKind, NotePosition, ElementRef<'a>)> {
+    let mut notes = Vec::new();
+    let mut position = NotePosition::Before;
+    for child in content.child_elements() {
+        let text = child.text().collect::<String>();
+        let text = text.trim_start().to_lowercase();
+        if text.is_empty() {
+            continue;
+        }
+        match TL_NOTE_MARKERS
+            .iter()
+            .any(|marker| text.starts_with(marker))
+        {
+            true => notes.push((NoteKind::Translator, position, child)),
+            false => position = NotePosition::After,
+        }
+    }
+    notes
+}
+
+/// Source elements that start a new block of text
+const BLOCK_TAGS: &[&str] = &[
+    "article",
+    "aside",
+    "blockquote",
+    "br",
+    "div",
+    "footer",
+    "h1",
+    "h2",
+    "h3",
+    "h4",
+    "h5",
+    "h6",
+    "header",
+    "hr",
+    "li",
+    "ol",
+    "p",
+    "section",
+    "table",
+    "td",
+    "th",
+    "tr",
+    "ul",
+];
+
 fn parse_inner_content<'a>(
     xhtml: &XhtmlBuilder,
     parent: XhtmlNode,
     content: ElementRef<'a>,
+    marks: &Marks,
 ) -> Result<(), Error> {
+    if marks.skip.contains(&content.id()) {
+        log::debug!("skipping: {:?}", content);
+        return Ok(());
+    }
+    if let Some(n) = marks.noterefs.get(&content.id()) {
+        log::debug!("footnote reference {}: {:?}", n, content);
+        return append_noteref(xhtml, parent, *n);
+    }
     log::debug!("found: {:?}", content);
     let el = content.value();
     let tag = el.name();
+    let block = BLOCK_TAGS.contains(&tag);
+    if block {
+        marks.last.set(None);
+    }
     let node = match tag {
         "br" => {
             let linebreak = append_element(xhtml, parent, Element::P)?;
@@ -58,6 +609,17 @@ fn parse_inner_content<'a>(
             let attrs = el.attrs().collect::<Vec<_>>();
             append_element_with_attrs(xhtml, parent, Element::Hr, attrs.as_ref())?
         }
+        "img" => {
+            // Lazy-loaded images keep the real source in data-src
+            match el.attr("data-src").or(el.attr("src")) {
+                Some(src) if !src.trim().is_empty() => {
+                    let alt = el.attr("alt").unwrap_or("");
+                    append_image(xhtml, parent, src.trim(), alt)?;
+                }
+                _ => log::debug!("skipping image without source"),
+            }
+            parent
+        }
         "span" => match el.attr("style") {
             Some(style) => {
                 // Filter out font-family and font-size. Too unpredictable.
@@ -84,7 +646,10 @@ fn parse_inner_content<'a>(
         _ => parent,
     };
     for child in content.children() {
-        parse_node(xhtml, node, child)?;
+        parse_node(xhtml, node, child, marks)?;
+    }
+    if block {
+        marks.last.set(None);
     }
     Ok(())
 }
@@ -93,15 +658,24 @@ fn parse_node<'a>(
     xhtml: &XhtmlBuilder,
     parent: XhtmlNode,
     content: NodeRef<'a, Node>,
+    marks: &Marks,
 ) -> Result<(), Error> {
     match content.value() {
         Node::Text(text) => {
             let text = text.to_string();
             if !text.trim().is_empty() {
-                if filter(&text) {
-                    log::debug!("filtering: {}", text);
+                marks.position.set(marks.position.get() + 1);
+                if let Some(reason) = marks.options.filter.check(&text) {
+                    log::debug!("filtering ({}): {}", reason, text);
+                    marks.filtered.borrow_mut().push(Filtered {
+                        position: marks.position.get(),
+                        reason,
+                        text: text.trim().to_string(),
+                    });
                 } else {
-                    let len = if text.len() > 100 { 100 } else { text.len() };
+                    let text = marks.options.typography.normalize(&text, marks.last.get());
+                    marks.last.set(text.chars().last());
+                    let len = text.floor_char_boundary(100);
                     log::debug!("appending {:?} to {:?}", &text[0..len], parent);
                     append_text(xhtml, parent, text)?;
                 }
@@ -112,6 +686,7 @@ fn parse_node<'a>(
             xhtml,
             parent,
             ElementRef::wrap(content).ok_or(Error::html("should be element", true))?,
+            marks,
         ),
         _ => Ok(()),
     }
@@ -140,7 +715,7 @@ where
                 xhtml.append_element_with_attrs(p, element, attrs)?
             }
         },
-        Element::Span => match parent.element() {
+        Element::Span | Element::Sup => match parent.element() {
             Element::P | Element::Span | Element::Em | Element::Strong => {
                 xhtml.append_element_with_attrs(parent, element, attrs)?
             }
@@ -164,6 +739,42 @@ fn append_element(
     append_element_with_attrs::<&str, &str>(xhtml, parent, element, &[])
 }
 
+/// Images sit in their own paragraph unless they are inline with text
+fn append_image(
+    xhtml: &XhtmlBuilder,
+    parent: XhtmlNode,
+    src: &str,
+    alt: &str,
+) -> Result<(), Error> {
+    match parent.element() {
+        Element::P | Element::Span | Element::Em | Element::Strong | Element::Th | Element::Td => {
+            xhtml.append_image_with_alt(parent, src, alt)
+        }
+        Element::Table | Element::Tr => return Err(Error::html("no image in table or tr", true)),
+        _ => {
+            let p = xhtml.append_element(parent, Element::P)?;
+            xhtml.append_image_with_alt(p, src, alt)
+        }
+    }
+    Ok(())
+}
+
+/// Footnote reference `n`, linking to the footnote at the end of the article
+fn append_noteref(xhtml: &XhtmlBuilder, parent: XhtmlNode, n: usize) -> Result<(), Error> {
+    let sup = append_element(xhtml, parent, Element::Sup)?;
+    let link = xhtml.append_element_with_attrs(
+        sup,
+        Element::A,
+        &[
+            ("epub:type", "noteref"),
+            ("id", &format!("fnref-{}", n)),
+            ("href", &format!("#fn-{}", n)),
+        ],
+    )?;
+    xhtml.append_text(link, n.to_string());
+    Ok(())
+}
+
 /// Similar to paragraph styles, only allow text under certain tags
 fn append_text(xhtml: &XhtmlBuilder, parent: XhtmlNode, text: String) -> Result<(), Error> {
     match parent.element() {
@@ -212,3 +823,386 @@ fn break_paragraph(xhtml: &XhtmlBuilder, node: XhtmlNode) -> Result<XhtmlNode, E
     log::debug!("[br] new parent: {:?}", node);
     Ok(node)
 }
+
+/// Removes the elements hidden by the page's inline stylesheets (`display: none` on a class),
+/// such as anti-theft notices. Returns the number of elements removed.
+pub fn remove_hidden(document: &mut Html) -> Result<usize, Error> {
+    let classes = hidden_classes(document)?;
+    if classes.is_empty() {
+        return Ok(0);
+    }
+    log::debug!("hidden classes: {:?}", classes);
+    let ids = document
+        .root_element()
+        .descendants()
+        .filter_map(ElementRef::wrap)
+        .filter(|el| el.value().classes().any(|class| classes.contains(class)))
+        .map(|el| el.id())
+        .collect::<Vec<_>>();
+    for id in ids.iter() {
+        if let Some(mut node) = document.tree.get_mut(*id) {
+            node.detach();
+        }
+    }
+    Ok(ids.len())
+}
+
+/// Classes that `<style>` rules hide with `display: none`. Only plain class selectors (`.x`,
+/// `p.x`) count; rules inside at-rules such as `@media` are ignored.
+pub fn hidden_classes(document: &Html) -> Result<HashSet<String>, Error> {
+    let mut classes = HashSet::new();
+    for style in document.select(&Selector::parse("style")?) {
+        let css = strip_css_comments(&style.text().collect::<String>());
+        let mut rest = css.as_str();
+        while let Some(open) = rest.find('{') {
+            let prelude = rest[..open].trim();
+            let Some(close) = block_end(&rest[open..]).map(|close| open + close) else {
+                break;
+            };
+            let block = &rest[open + 1..close];
+            rest = &rest[close + 1..];
+            if prelude.starts_with('@') || !hides(block) {
+                continue;
+            }
+            for selector in prelude.split(',') {
+                let selector = selector.trim();
+                let Some((tag, class)) = selector.split_once('.') else {
+                    continue;
+                };
+                let is_ident = |s: &str| {
+                    s.chars()
+                        .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+                };
+                if is_ident(tag) && !class.is_empty() && is_ident(class) {
+                    classes.insert(class.to_string());
+                }
+            }
+        }
+    }
+    Ok(classes)
+}
+
+fn strip_css_comments(css: &str) -> String {
+    let mut out = String::with_capacity(css.len());
+    let mut rest = css;
+    while let Some(start) = rest.find("/*") {
+        out.push_str(&rest[..start]);
+        rest = match rest[start + 2..].find("*/") {
+            Some(end) => &rest[start + 2 + end + 2..],
+            None => "",
+        };
+    }
+    out.push_str(rest);
+    out
+}
+
+/// Index of the `}` closing the block starting at `css[0]`
+fn block_end(css: &str) -> Option<usize> {
+    let mut depth = 0;
+    for (i, c) in css.char_indices() {
+        match c {
+            '{' => depth += 1,
+            '}' => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(i);
+                }
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Returns true if the declarations contain `display: none`
+fn hides(block: &str) -> bool {
+    block.split(';').any(|declaration| {
+        declaration
+            .split_once(':')
+            .is_some_and(|(property, value)| {
+                property.trim().eq_ignore_ascii_case("display")
+                    && value
+                        .trim()
+                        .trim_end_matches("!important")
+                        .trim()
+                        .eq_ignore_ascii_case("none")
+            })
+    })
+}
+
+/// Parses the date formats the sites use (RFC 3339, `YYYY-MM-DD`, unix seconds)
+pub fn parse_date(text: &str) -> Option<DateTime<Utc>> {
+    let text = text.trim();
+    if let Ok(date) = DateTime::parse_from_rfc3339(text) {
+        return Some(date.with_timezone(&Utc));
+    }
+    if let Ok(date) = NaiveDateTime::parse_from_str(text, "%Y-%m-%dT%H:%M:%S") {
+        return Some(date.and_utc());
+    }
+    if let Ok(date) = NaiveDate::parse_from_str(text, "%Y-%m-%d") {
+        return date.and_hms_opt(0, 0, 0).map(|date| date.and_utc());
+    }
+    text.parse::<i64>()
+        .ok()
+        .and_then(|secs| DateTime::from_timestamp(secs, 0))
+}
+
+/// Returns the trimmed text of every matching element
+pub fn select_texts(document: &Html, selector: &str) -> Result<Vec<String>, Error> {
+    Ok(document
+        .select(&Selector::parse(selector)?)
+        .map(|e| e.text().collect::<Vec<_>>().join("").trim().to_string())
+        .filter(|text| !text.is_empty())
+        .collect())
+}
+
+/// Returns a request validator accepting pages with an element matching `selector`
+pub fn has_element(selector: &'static str) -> impl Fn(&str) -> bool + Send + Sync + 'static {
+    move |html| {
+        Selector::parse(selector).is_ok_and(|selector| {
+            Html::parse_document(html)
+                .select(&selector)
+                .next()
+                .is_some()
+        })
+    }
+}
+
+/// Returns the trimmed text of the first matching element
+pub fn select_text(document: &Html, selector: &str) -> Result<Option<String>, Error> {
+    Ok(select_texts(document, selector)?.into_iter().next())
+}
+
+/// Returns an attribute of the first matching element
+pub fn select_attr(document: &Html, selector: &str, attr: &str) -> Result<Option<String>, Error> {
+    Ok(document
+        .select(&Selector::parse(selector)?)
+        .find_map(|e| e.attr(attr))
+        .map(|value| value.trim().to_string()))
+}
+
+/// Returns the numeric path segments following `marker`, e.g. `["123"]` for `chapter` in
+/// `/fiction/1/x/chapter/123/slug`
+pub fn path_ids(url: &str, marker: &str, count: usize) -> Option<Vec<String>> {
+    let url = Url::parse(url).ok()?;
+    let mut segments = url
+        .path_segments()?
+        .skip_while(|segment| *segment != marker);
+    segments.next()?;
+    let ids = segments
+        .take(count)
+        .map(|segment| segment.split('-').next().unwrap_or(segment))
+        .filter(|segment| !segment.is_empty() && segment.chars().all(|c| c.is_ascii_digit()))
+        .map(String::from)
+        .collect::<Vec<_>>();
+    (ids.len() == count).then_some(ids)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use scraper::Html;
+
+    fn typography(rule: TypographyRule) -> Typography {
+        Typography::new(&[rule])
+    }
+
+    /// Parses `<div id="content">` of the page with the smart quotes rule
+    fn parse_quotes(html: &str) -> String {
+        let document = Html::parse_document(html);
+        let content = document
+            .select(&Selector::parse("#content").unwrap())
+            .next()
+            .unwrap();
+        let options = ParseOptions {
+            typography: typography(TypographyRule::SmartQuotes),
+            ..Default::default()
+        };
+        parse_chapter("Title", content, Vec::new(), Vec::new(), &options)
+            .unwrap()
+            .html
+    }
+
+    #[test]
+    fn smart_quotes() {
+        let typography = typography(TypographyRule::SmartQuotes);
+        assert_eq!(
+            typography.normalize("\"Hello,\" she said. \"Bye...\"", None),
+            "\u{201c}Hello,\u{201d} she said. \u{201c}Bye...\u{201d}"
+        );
+        assert_eq!(
+            typography.normalize("(\"quoted\") and --\"this\"", None),
+            "(\u{201c}quoted\u{201d}) and --\u{201c}this\u{201d}"
+        );
+    }
+
+    #[test]
+    fn nested_quotes() {
+        let typography = typography(TypographyRule::SmartQuotes);
+        assert_eq!(
+            typography.normalize("\"She said 'hi' to me.\"", None),
+            "\u{201c}She said \u{2018}hi\u{2019} to me.\u{201d}"
+        );
+        assert_eq!(
+            typography.normalize("'\"Run,\" he said.'", None),
+            "\u{2018}\u{201c}Run,\u{201d} he said.\u{2019}"
+        );
+    }
+
+    #[test]
+    fn apostrophes() {
+        let typography = typography(TypographyRule::SmartQuotes);
+        assert_eq!(
+            typography.normalize("don't, the '90s, get 'em, rock 'n' roll", None),
+            "don\u{2019}t, the \u{2019}90s, get \u{2019}em, rock \u{2019}n\u{2019} roll"
+        );
+        assert_eq!(
+            typography.normalize("the boys' room", None),
+            "the boys\u{2019} room"
+        );
+    }
+
+    #[test]
+    fn quotes_across_inline_elements() {
+        let html = parse_quotes(
+            "<div id=\"content\"><p><em>John</em>'s \"<em>book</em>\" and \
+             \"<strong>run</strong>,\" he said.</p></div>",
+        );
+        assert!(html.contains("John</em>\u{2019}s"), "{}", html);
+        assert!(html.contains("\u{201c}<em>book</em>\u{201d}"), "{}", html);
+        assert!(
+            html.contains("\u{201c}<strong>run</strong>,\u{201d}"),
+            "{}",
+            html
+        );
+    }
+
+    #[test]
+    fn dialogue_across_paragraphs() {
+        let html = parse_quotes(
+            "<div id=\"content\"><p>\"This goes on,</p><p>\"and on.\"</p>\
+             <p>'Tis <em>done</em>.</p></div>",
+        );
+        assert!(html.contains("<p>\u{201c}This goes on,</p>"), "{}", html);
+        assert!(html.contains("<p>\u{201c}and on.\u{201d}</p>"), "{}", html);
+        assert!(html.contains("<p>\u{2019}Tis "), "{}", html);
+    }
+
+    #[test]
+    fn ellipsis() {
+        let typography = typography(TypographyRule::Ellipsis);
+        assert_eq!(
+            typography.normalize("Wait... what", None),
+            "Wait\u{2026} what"
+        );
+        assert_eq!(
+            typography.normalize("Well. . . no....", None),
+            "Well\u{2026} no\u{2026}"
+        );
+    }
+
+    #[test]
+    fn dashes() {
+        let typography = typography(TypographyRule::Dashes);
+        assert_eq!(
+            typography.normalize("one--two---three - four", None),
+            "one\u{2014}two\u{2014}three - four"
+        );
+    }
+
+    #[test]
+    fn whitespace() {
+        let typography = typography(TypographyRule::Whitespace);
+        assert_eq!(
+            typography.normalize("a \t\n b\u{a0}c\u{a0}\u{a0}d", None),
+            "a b\u{a0}c d"
+        );
+    }
+
+    #[test]
+    fn invisible() {
+        let typography = typography(TypographyRule::Invisible);
+        assert_eq!(
+            typography.normalize("soft\u{ad}ware\u{200b} \u{feff}x\u{7}\ty", None),
+            "software x\ty"
+        );
+    }
+
+    #[test]
+    fn nfc() {
+        let typography = typography(TypographyRule::Nfc);
+        assert_eq!(typography.normalize("cafe\u{301}", None), "caf\u{e9}");
+    }
+
+    #[test]
+    fn no_rules() {
+        let text = "\"a\"  b... c--d\u{200b} e\u{301}";
+        assert_eq!(Typography::new(&[]).normalize(text, None), text);
+    }
+
+    /// Hidden classes of a page with the stylesheet, sorted
+    fn hidden(css: &str) -> Vec<String> {
+        let document = Html::parse_document(&format!(
+            "<html><head><style>{}</style></head><body></body></html>",
+            css
+        ));
+        let mut classes = hidden_classes(&document)
+            .unwrap()
+            .into_iter()
+            .collect::<Vec<_>>();
+        classes.sort();
+        classes
+    }
+
+    #[test]
+    fn hidden_class_rules() {
+        assert_eq!(hidden(".x{display:none}"), ["x"]);
+        assert_eq!(hidden("p.x{color: red; display: none !important}"), ["x"]);
+        assert_eq!(hidden(".a, span.b ,.c { DISPLAY: NONE; }"), ["a", "b", "c"]);
+        assert_eq!(hidden(".a, div .b, #c, .d:hover {display: none}"), ["a"]);
+        assert_eq!(
+            hidden(".x{display: block} .y{visibility: hidden}"),
+            Vec::<String>::new()
+        );
+    }
+
+    #[test]
+    fn hidden_classes_skip_at_rules() {
+        assert_eq!(
+            hidden("@media print { .x { display: none } } .y { display: none }"),
+            ["y"]
+        );
+        assert_eq!(
+            hidden("@supports (display: grid) { .x { display: none } }"),
+            Vec::<String>::new()
+        );
+    }
+
+    #[test]
+    fn hidden_classes_skip_comments() {
+        assert_eq!(
+            hidden("/* .x { display: none } */ .y { /* display: none */ color: red }"),
+            Vec::<String>::new()
+        );
+        assert_eq!(hidden(".y /* } */ { display: none }"), ["y"]);
+    }
+
+    #[test]
+    fn remove_hidden_elements() {
+        let mut document = Html::parse_document(
+            "<html><head><style>.stolen { display: none }</style></head><body>\
+             <p class=\"text\">Kept</p>\
+             <p class=\"text stolen\">Removed</p>\
+             <span class=\"visible\">Also kept</span>\
+             </body></html>",
+        );
+        assert_eq!(remove_hidden(&mut document).unwrap(), 1);
+        let body = document
+            .select(&Selector::parse("body").unwrap())
+            .next()
+            .unwrap()
+            .text()
+            .collect::<String>();
+        assert_eq!(body, "KeptAlso kept");
+    }
+}
@@ -1,10 +1,10 @@
 //! wanderinginn.com parser
 
 use crate::{
-    book::{BookInfo, Chapter, UrlCache},
+    book::{BookInfo, BookStatus, Chapter, UrlCache},
     client::WnrakeClient,
     error::Error,
-    parser::{utils, Downloader, Parser},
+    parser::{Downloader, ParseOptions, Parser, utils},
 };
 use async_trait::async_trait;
 use crawler::{Request, WaitFor};
@@ -15,11 +15,12 @@ pub struct WanderingInnParser;
 
 #[async_trait]
 impl Downloader for WanderingInnParser {
-    async fn get_book_info(&self, client: &mut WnrakeClient, _url: &str) -> Result<String, Error> {
+    async fn get_book_info(&self, client: &WnrakeClient, _url: &str) -> Result<String, Error> {
         let res = client
             .request(
                 &Request::get("https://wanderinginn.com/table-of-contents/")
                     .wait_for(WaitFor::id("table-of-contents"))
+                    .validate(utils::has_element("#table-of-contents"))
                     .build(),
             )
             .await?;
@@ -35,7 +36,7 @@ impl Downloader for WanderingInnParser {
 
     async fn get_chapterlist(
         &self,
-        _client: &mut WnrakeClient,
+        _client: &WnrakeClient,
         _url: &str,
         html: &str,
     ) -> Result<UrlCache, Error> {
@@ -59,7 +60,7 @@ impl Downloader for WanderingInnParser {
         Ok(chapterlist)
     }
 
-    async fn get_chapter(&self, client: &mut WnrakeClient, url: &str) -> Result<String, Error> {
+    async fn get_chapter(&self, client: &WnrakeClient, url: &str) -> Result<String, Error> {
         let res = client
             .request(
                 &Request::get(url)
@@ -86,13 +87,18 @@ impl Downloader for WanderingInnParser {
 impl Parser for WanderingInnParser {
     fn parse_book_info(&self, _url: &str, _html: &str) -> Result<BookInfo, Error> {
         Ok(BookInfo {
-            title: "The Wandering Inn".into(),
-            author: "Pirateaba".into(),
-            url: "https://wanderinginn.com/table-of-contents/".into(),
+            language: Some("en".into()),
+            status: Some(BookStatus::Ongoing),
+            publisher: Some("wanderinginn.com".into()),
+            ..BookInfo::new(
+                "The Wandering Inn",
+                "Pirateaba",
+                "https://wanderinginn.com/table-of-contents/",
+            )
         })
     }
 
-    fn parse_chapter(&self, html: &str) -> Result<Chapter, Error> {
+    fn parse_chapter(&self, html: &str, options: &ParseOptions) -> Result<Chapter, Error> {
         let document = Html::parse_document(&html);
 
         // Parse title
@@ -115,16 +121,40 @@ impl Parser for WanderingInnParser {
             .ok_or(Error::html("article not in html", true))?;
 
         // Build HTML
-        let html = utils::parse_content(&title, chapter)?;
-
         // Return chapter
-        Ok(Chapter {
-            title: title.trim().into(),
-            html,
-        })
+        utils::parse_chapter(title.trim(), chapter, Vec::new(), Vec::new(), options)
     }
 
     fn next_page(&self, _html: &str) -> Result<Option<String>, Error> {
         Err(Error::parser("Not implemented--do wnrake info"))
     }
+
+    /// Volume headings (`h2`) precede their chapters in the table of contents
+    fn parse_volumes(&self, html: &str) -> Result<Vec<(String, String)>, Error> {
+        let document = Html::parse_document(html);
+        let toc = document
+            .select(&Selector::parse("#table-of-contents")?)
+            .next()
+            .ok_or(Error::html("expected #table-of-contents", true))?;
+        let link = Selector::parse("a")?;
+        let mut volume = None;
+        let mut volumes = Vec::new();
+        for element in toc.select(&Selector::parse("h2, div.chapter-entry")?) {
+            match element.value().name() {
+                "h2" => {
+                    let title = element.text().collect::<Vec<_>>().join(" ");
+                    let title = title.split_whitespace().collect::<Vec<_>>().join(" ");
+                    volume = (!title.is_empty()).then_some(title);
+                }
+                _ => {
+                    if let Some(volume) = &volume
+                        && let Some(url) = element.select(&link).next().and_then(|a| a.attr("href"))
+                    {
+                        volumes.push((url.to_string(), volume.clone()));
+                    }
+                }
+            }
+        }
+        Ok(volumes)
+    }
 }
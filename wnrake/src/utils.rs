@@ -1,30 +1,137 @@
 //! utils
 
 use crate::error::Error;
-use std::{fs, path::Path};
+use crawler::Url;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::{
+    fmt,
+    fs::{self, File},
+    io::Write,
+    path::Path,
+    str::FromStr,
+};
 
-pub fn ensure_dir(dir: &str) -> Result<(), Error> {
-    let dir = Path::new(dir);
+pub fn ensure_dir<P: AsRef<Path>>(dir: P) -> Result<(), Error> {
+    let dir = dir.as_ref();
     if !dir.is_dir() {
         log::debug!("creating {:?} directory", dir.as_os_str().to_str());
-        fs::create_dir_all(&dir)?;
+        fs::create_dir_all(dir)?;
     }
     Ok(())
 }
 
-pub fn url_to_filename(index: usize, url: &str) -> String {
-    let filename = url
-        .trim_end_matches("/")
-        .rsplit("/")
-        .next()
-        .unwrap_or("chapter")
-        .split("?")
-        .next()
-        .unwrap_or("chapter")
-        .replace(" ", "");
-    format!("{:04}-{}", index, filename)
+/// Writes the file through a temporary file so an interruption never leaves a partial file behind
+pub fn write_file<P: AsRef<Path>>(path: P, data: &[u8]) -> Result<(), Error> {
+    let path = path.as_ref();
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(".part");
+    {
+        let mut file = File::create(&tmp)?;
+        file.write_all(data)?;
+        file.sync_all()?;
+    }
+    fs::rename(&tmp, path)?;
+    Ok(())
+}
+
+/// Normalizes a URL so the same page always gives the same string: https, no `www.`, no
+/// fragment and no trailing slash
+pub fn normalize_url(url: &str) -> String {
+    let mut url = match Url::parse(url.trim()) {
+        Ok(url) => url,
+        Err(_) => return url.trim().trim_end_matches('/').into(),
+    };
+    url.set_fragment(None);
+    if url.scheme() == "http" {
+        let _ = url.set_scheme("https");
+    }
+    if let Some(host) = url.host_str().map(|host| host.to_lowercase())
+        && let Some(host) = host.strip_prefix("www.")
+    {
+        let _ = url.set_host(Some(host));
+    }
+    url.to_string().trim_end_matches('/').into()
+}
+
+/// File name of a chapter, staged or parsed
+pub fn chapter_filename(id: &str) -> String {
+    format!("{}.html", id)
+}
+
+/// Returns the hex SHA-256 of the data
+pub fn hash(data: &[u8]) -> String {
+    Sha256::digest(data)
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
 }
 
-pub fn index_to_filename(index: usize) -> String {
-    format!("{:04}.html", index + 1)
+/// Chapter range on the command line: `N`, `N-M`, `N-` or `-M` (1-based, inclusive)
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct ChapterRange {
+    start: usize,
+    end: Option<usize>,
+}
+
+impl ChapterRange {
+    /// Returns true if the chapter at `index` (0-based) is in the range
+    pub fn contains(&self, index: usize) -> bool {
+        index + 1 >= self.start && self.end.is_none_or(|end| index < end)
+    }
+}
+
+impl fmt::Display for ChapterRange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.end {
+            Some(end) if end == self.start => write!(f, "{}", end),
+            Some(end) => write!(f, "{}-{}", self.start, end),
+            None => write!(f, "{}-", self.start),
+        }
+    }
+}
+
+impl TryFrom<String> for ChapterRange {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl From<ChapterRange> for String {
+    fn from(range: ChapterRange) -> Self {
+        range.to_string()
+    }
+}
+
+impl FromStr for ChapterRange {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let number = |s: &str| -> Result<Option<usize>, String> {
+            match s.trim() {
+                "" => Ok(None),
+                s => match s.parse::<usize>() {
+                    Ok(0) | Err(_) => Err(format!("invalid chapter number `{}`", s)),
+                    Ok(n) => Ok(Some(n)),
+                },
+            }
+        };
+        let (start, end) = match s.split_once('-') {
+            Some((start, end)) => (number(start)?, number(end)?),
+            None => (number(s)?, number(s)?),
+        };
+        match (start, end) {
+            (None, None) => Err(format!("invalid chapter range `{}`", s)),
+            (Some(start), Some(end)) if start > end => {
+                Err(format!("invalid chapter range `{}`", s))
+            }
+            (start, end) => Ok(ChapterRange {
+                start: start.unwrap_or(1),
+                end,
+            }),
+        }
+    }
 }
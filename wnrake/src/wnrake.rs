@@ -8,11 +8,16 @@ mod book;
 mod client;
 mod command;
 mod error;
+mod images;
+mod interrupt;
+mod library;
 mod parser;
 mod utils;
+mod workspace;
 mod xhtml;
 
 use error::{Error, ErrorType};
+use workspace::Workspace;
 
 #[derive(Parser, Clone, Debug)]
 #[command(version, about, long_about = None)]
@@ -37,6 +42,10 @@ struct Cli {
     #[arg(short = 'f')]
     config: Option<String>,
 
+    /// Book workspace
+    #[arg(short = 'C', long, value_name = "DIR", default_value = ".")]
+    dir: String,
+
     /// Solver URL [default: http://localhost:8191/v1]
     #[arg(long, value_name = "URL")]
     solver: Option<String>,
@@ -57,6 +66,30 @@ struct Cli {
     #[arg(long, value_name = "NAME")]
     proxy: Option<String>,
 
+    /// Disable the IP leak guard (allows proxies without an `api` to check their exit IP)
+    #[arg(long)]
+    disable_leak_guard: bool,
+
+    /// Direct public IP lookup endpoint [default: https://api.ipify.org]
+    #[arg(long, value_name = "URL")]
+    ip_lookup: Option<String>,
+
+    /// Remember which exit IPs each site blocked [default: disabled]
+    #[arg(long, value_name = "FILE")]
+    ip_history: Option<String>,
+
+    /// Concurrent requests per proxy [default: 1]
+    #[arg(short = 'j', long, value_name = "N")]
+    concurrency: Option<usize>,
+
+    /// Minimum milliseconds between requests [default: 0]
+    #[arg(long, value_name = "MS")]
+    rate_limit: Option<u64>,
+
+    /// Seconds to wait for a proxy to recover between attempts [default: 60]
+    #[arg(long, value_name = "SECONDS")]
+    recover_timeout: Option<u64>,
+
     /// Command
     #[command(subcommand)]
     command: Command,
@@ -80,20 +113,31 @@ enum Command {
     /// Builds epub book
     Build(command::Build),
 
+    /// Fetches new chapters and rebuilds the book
+    Update(command::Update),
+
+    /// Re-fetches chapters and reports the ones that were revised
+    Recheck(command::Recheck),
+
+    /// Checks the structure of an EPUB
+    Validate(command::Validate),
+
+    /// Lists or sets the volumes chapters are grouped into
+    Volumes(command::Volumes),
+
+    /// Manages a library of books (the workspace is the library root)
+    Library(command::Library),
+
     /// Helpful for debugging FlareSolverr
     Debug(command::Debug),
+
+    /// Shows exit IP history
+    Ips(command::Ips),
 }
 
-fn load_configuration(
-    config: Option<String>,
-    solver: Option<String>,
-    disable_cache: bool,
-    cache: Option<String>,
-    disable_proxy: bool,
-    proxy_name: Option<String>,
-) -> Result<Config, Error> {
-    let config_file = if config.is_some() {
-        Some(config.unwrap())
+fn load_configuration(cli: &Cli) -> Result<Config, Error> {
+    let config_file = if cli.config.is_some() {
+        cli.config.clone()
     } else if cfg!(windows) {
         match std::env::var("LOCALAPPDATA") {
             Ok(home) => Some(format!("{}/wnrake.toml", home)),
@@ -111,11 +155,17 @@ fn load_configuration(
         None => ConfigBuilder::default(),
     };
     Ok(builder
-        .solver(solver)
-        .cache(cache)
-        .disable_cache(disable_cache)
-        .proxy(proxy_name)
-        .disable_proxy(disable_proxy)
+        .solver(cli.solver.clone())
+        .cache(cli.cache.clone())
+        .disable_cache(cli.disable_cache)
+        .proxy(cli.proxy.clone())
+        .disable_proxy(cli.disable_proxy)
+        .disable_leak_guard(cli.disable_leak_guard)
+        .ip_lookup(cli.ip_lookup.clone())
+        .ip_history(cli.ip_history.clone())
+        .concurrency(cli.concurrency)
+        .rate_limit(cli.rate_limit)
+        .recover_timeout(cli.recover_timeout)
         .build())
 }
 
@@ -142,26 +192,30 @@ async fn dispatcher() -> Result<(), Error> {
     }
     builder.init();
 
+    // Shut down gracefully on Ctrl-C
+    interrupt::listen();
+
     // Load configuration
+    let config = load_configuration(&cli)?;
+    let workspace = Workspace::new(&cli.dir);
     let command = cli.command;
-    let config = load_configuration(
-        cli.config,
-        cli.solver,
-        cli.disable_cache,
-        cli.cache,
-        cli.disable_proxy,
-        cli.proxy,
-    )?;
     log::debug!("{:?}", config);
+    log::debug!("{:?}", workspace);
 
     // Dispatch
     match &command {
-        Command::Info(cmd) => cmd.execute(&config).await,
-        Command::Download(cmd) => cmd.execute(&config).await,
-        Command::Crawl(cmd) => cmd.execute(&config).await,
-        Command::Parse(cmd) => cmd.execute(&config).await,
-        Command::Build(cmd) => cmd.execute(&config),
+        Command::Info(cmd) => cmd.execute(&config, &workspace).await,
+        Command::Download(cmd) => cmd.execute(&config, &workspace).await,
+        Command::Crawl(cmd) => cmd.execute(&config, &workspace).await,
+        Command::Parse(cmd) => cmd.execute(&config, &workspace).await,
+        Command::Build(cmd) => cmd.execute(&config, &workspace),
+        Command::Update(cmd) => cmd.execute(&config, &workspace).await,
+        Command::Recheck(cmd) => cmd.execute(&config, &workspace).await,
+        Command::Validate(cmd) => cmd.execute(&config, &workspace),
+        Command::Volumes(cmd) => cmd.execute(&config, &workspace),
+        Command::Library(cmd) => cmd.execute(&config, &workspace).await,
         Command::Debug(cmd) => cmd.execute(&config).await,
+        Command::Ips(cmd) => cmd.execute(&config),
     }
 }
 
@@ -173,6 +227,7 @@ fn main() {
                 ErrorType::Crawler => 1,
                 ErrorType::Epub => 2,
                 ErrorType::Html => 3,
+                ErrorType::Interrupted => interrupt::EXIT_CODE,
                 ErrorType::Io => 4,
                 ErrorType::Json => 5,
                 ErrorType::Parser => 6,
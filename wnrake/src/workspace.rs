@@ -0,0 +1,329 @@
+//! Book workspace
+
+use crate::{
+    book::{BookInfo, ChapterList, ChapterStatus, CoverFormat, Manifest, UrlCache},
+    error::Error,
+    parser,
+};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// Manifest file name
+pub const MANIFEST: &str = "wnrake.json";
+
+/// Legacy state files, replaced by the manifest
+const LEGACY_BOOKINFO: &str = "bookinfo.txt";
+const LEGACY_URLCACHE: &str = "urlcache.txt";
+const LEGACY_CHAPTERLIST: &str = "chapterlist.txt";
+
+/// Directory holding the manifest, the staging and book directories and the cover
+#[derive(Clone, Debug)]
+pub struct Workspace {
+    root: PathBuf,
+}
+
+impl Workspace {
+    pub fn new<P: Into<PathBuf>>(root: P) -> Self {
+        Workspace { root: root.into() }
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// Resolves a path relative to the workspace
+    pub fn path<P: AsRef<Path>>(&self, path: P) -> PathBuf {
+        self.root.join(path)
+    }
+
+    pub fn manifest_path(&self) -> PathBuf {
+        self.path(MANIFEST)
+    }
+
+    pub fn staging_dir(&self) -> PathBuf {
+        self.path("staging")
+    }
+
+    pub fn book_dir(&self) -> PathBuf {
+        self.path("book")
+    }
+
+    pub fn revisions_dir(&self) -> PathBuf {
+        self.path("revisions")
+    }
+
+    /// Directory of the page templates (`title.txt`, `about.txt`, `closing.txt`)
+    pub fn templates_dir(&self) -> PathBuf {
+        self.path("templates")
+    }
+
+    /// Copyright filter rules of the book
+    pub fn filter_rules_path(&self) -> PathBuf {
+        self.path("filter-rules.json")
+    }
+
+    /// Audit log of the text dropped by the copyright filter
+    pub fn filter_log_path(&self) -> PathBuf {
+        self.path("filtered.json")
+    }
+
+    /// Directory of the stored chapter images
+    pub fn images_dir(&self) -> PathBuf {
+        self.path("images")
+    }
+
+    /// Returns the cover image (`cover.jpg` or `cover.png`) if there is one
+    pub fn cover_path(&self) -> Option<PathBuf> {
+        [CoverFormat::Jpeg, CoverFormat::Png]
+            .into_iter()
+            .map(|format| self.cover_file(format))
+            .find(|path| path.is_file())
+    }
+
+    pub fn cover_file(&self, format: CoverFormat) -> PathBuf {
+        self.path(format!("cover.{}", format.extension()))
+    }
+
+    /// Workspace-relative path of a downloaded page
+    pub fn staging_file(filename: &str) -> String {
+        format!("staging/{}", filename)
+    }
+
+    /// Workspace-relative path of a chapter revision
+    pub fn revision_file(filename: &str) -> String {
+        format!("revisions/{}", filename)
+    }
+
+    /// Workspace-relative path of a parsed chapter
+    pub fn book_file(filename: &str) -> String {
+        format!("book/{}", filename)
+    }
+
+    /// Loads the manifest, migrating legacy files first. Returns `None` for a new workspace.
+    pub fn try_load(&self) -> Result<Option<Manifest>, Error> {
+        let path = self.manifest_path();
+        let mut manifest = if path.is_file() {
+            log::debug!("loading {:?}", path);
+            Manifest::from_file(path)?
+        } else if self.path(LEGACY_BOOKINFO).is_file() {
+            self.migrate()?
+        } else {
+            return Ok(None);
+        };
+        if self.migrate_ids(&mut manifest)? {
+            self.save(&manifest)?;
+        }
+        Ok(Some(manifest))
+    }
+
+    /// Loads the manifest
+    pub fn load(&self) -> Result<Manifest, Error> {
+        match self.try_load()? {
+            Some(manifest) => Ok(manifest),
+            None => Err(Error::io(format!(
+                "{:?} does not exist (run `wnrake info <url>` first)",
+                self.manifest_path()
+            ))),
+        }
+    }
+
+    pub fn save(&self, manifest: &Manifest) -> Result<(), Error> {
+        log::debug!("writing {:?}", self.manifest_path());
+        manifest.to_file(self.manifest_path())
+    }
+
+    /// Builds the manifest from `bookinfo.txt`, `urlcache.txt` and `chapterlist.txt` and moves
+    /// the legacy files out of the way
+    fn migrate(&self) -> Result<Manifest, Error> {
+        log::info!("migrating legacy files to {}", MANIFEST);
+        let mut manifest = Manifest::new(BookInfo::from_file(self.path(LEGACY_BOOKINFO))?);
+
+        let urlcache = self.path(LEGACY_URLCACHE);
+        if urlcache.is_file() {
+            manifest.set_urls(UrlCache::from_file(urlcache)?);
+        }
+
+        let chapterlist = self.path(LEGACY_CHAPTERLIST);
+        let chapterlist = match chapterlist.is_file() {
+            true => ChapterList::from_file(chapterlist)?,
+            false => ChapterList::new(),
+        };
+
+        for (i, chapter) in manifest.chapters.iter_mut().enumerate() {
+            let staging = Self::staging_file(&legacy_staging_filename(i, &chapter.url));
+            if let Ok(data) = fs::read(self.path(&staging)) {
+                chapter.downloaded(staging, &data);
+            }
+            let parsed = Self::book_file(&legacy_parsed_filename(i));
+            if let Some(info) = chapterlist.as_ref().get(i)
+                && self.path(&parsed).is_file()
+            {
+                chapter.parsed(parsed, info.title.clone());
+            }
+        }
+
+        self.save(&manifest)?;
+        for legacy in [LEGACY_BOOKINFO, LEGACY_URLCACHE, LEGACY_CHAPTERLIST] {
+            let path = self.path(legacy);
+            if path.is_file() {
+                fs::rename(&path, self.path(format!("{}.bak", legacy)))?;
+            }
+        }
+        Ok(manifest)
+    }
+    /// Gives chapters from older manifests an ID and renames their files after it (they used
+    /// to be named after their index). Returns true if the manifest changed.
+    fn migrate_ids(&self, manifest: &mut Manifest) -> Result<bool, Error> {
+        let mut changed = false;
+        for chapter in manifest.chapters.iter_mut() {
+            if chapter.id.is_empty() {
+                chapter.id = parser::chapter_id(&chapter.url);
+                changed = true;
+            }
+            let staging = chapter.staging_file();
+            changed |= self.relocate(&mut chapter.staging, staging)?;
+            let parsed = chapter.parsed_file();
+            changed |= self.relocate(&mut chapter.parsed, parsed)?;
+
+            // Chapters whose files are gone have to be parsed or downloaded again
+            let status = match chapter.status {
+                ChapterStatus::Parsed if chapter.parsed.is_none() => ChapterStatus::Downloaded,
+                status => status,
+            };
+            let status = match status {
+                ChapterStatus::Downloaded if chapter.staging.is_none() => ChapterStatus::Pending,
+                status => status,
+            };
+            if status != chapter.status {
+                chapter.status = status;
+                changed = true;
+            }
+        }
+        if changed {
+            log::info!("migrated chapter files to stable names");
+        }
+        Ok(changed)
+    }
+
+    /// Moves a chapter file to `to`, clearing `path` if the file is missing. Returns true if
+    /// `path` changed.
+    fn relocate(&self, path: &mut Option<String>, to: String) -> Result<bool, Error> {
+        let from = match path {
+            Some(from) if *from != to => self.path(&*from),
+            _ => return Ok(false),
+        };
+        let target = self.path(&to);
+        if from.is_file() && !target.exists() {
+            log::debug!("renaming {:?} to {:?}", from, target);
+            fs::rename(&from, &target)?;
+        }
+        *path = target.is_file().then_some(to);
+        Ok(true)
+    }
+}
+
+/// Staging file name used before chapters had IDs
+fn legacy_staging_filename(index: usize, url: &str) -> String {
+    let filename = url
+        .trim_end_matches("/")
+        .rsplit("/")
+        .next()
+        .unwrap_or("chapter")
+        .split("?")
+        .next()
+        .unwrap_or("chapter")
+        .replace(" ", "");
+    format!("{:04}-{}", index, filename)
+}
+
+/// Parsed file name used before chapters had IDs
+fn legacy_parsed_filename(index: usize) -> String {
+    format!("{:04}.html", index + 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn migrate_urlcache() {
+        let root = std::env::temp_dir().join(format!("wnrake-migrate-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(root.join("staging")).unwrap();
+        fs::write(root.join(LEGACY_BOOKINFO), "Title\nAuthor\nhttps://a/\n").unwrap();
+        fs::write(
+            root.join(LEGACY_URLCACHE),
+            "https://a/chapter/1\n\nhttps://a/chapter/2?p=1\nhttps://a/chapter/1\n",
+        )
+        .unwrap();
+        fs::write(root.join("staging/0001-2"), "<p>two</p>").unwrap();
+
+        let workspace = Workspace::new(&root);
+        let manifest = workspace.load().unwrap();
+        let urls = manifest.urls().0;
+        assert_eq!(
+            urls,
+            [
+                "https://a/chapter/1",
+                "https://a/chapter/2?p=1",
+                "https://a/chapter/1"
+            ]
+        );
+        let states = manifest
+            .chapters
+            .iter()
+            .map(|c| c.status)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            states,
+            [
+                ChapterStatus::Pending,
+                ChapterStatus::Downloaded,
+                ChapterStatus::Pending
+            ]
+        );
+        let staging = manifest.chapters[1].staging.as_deref().unwrap();
+        assert_eq!(
+            fs::read_to_string(root.join(staging)).unwrap(),
+            "<p>two</p>"
+        );
+        assert!(root.join(format!("{}.bak", LEGACY_URLCACHE)).is_file());
+        assert!(workspace.manifest_path().is_file());
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn migrate_missing_files() {
+        let root = std::env::temp_dir().join(format!("wnrake-missing-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(root.join("staging")).unwrap();
+        fs::write(root.join("staging/0000-1"), "<p>one</p>").unwrap();
+
+        let workspace = Workspace::new(&root);
+        let mut manifest = Manifest::new(BookInfo::new("Title", "Author", "https://a/"));
+        manifest.set_urls(UrlCache(vec!["https://a/1".into(), "https://a/2".into()]));
+        for (i, chapter) in manifest.chapters.iter_mut().enumerate() {
+            chapter.id = String::new();
+            chapter.downloaded(format!("staging/000{}-{}", i, i + 1), b"");
+            chapter.parsed(format!("book/000{}.html", i + 1), "Chapter".into());
+        }
+        workspace.save(&manifest).unwrap();
+
+        let manifest = workspace.load().unwrap();
+        let states = manifest
+            .chapters
+            .iter()
+            .map(|c| (c.status, c.staging.is_some(), c.parsed.is_some()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            states,
+            [
+                (ChapterStatus::Downloaded, true, false),
+                (ChapterStatus::Pending, false, false)
+            ]
+        );
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -26,7 +26,9 @@ static XHTML_BASE: &str = r#"
 
 #[derive(Clone, Debug, Copy, PartialOrd, PartialEq, Ord, Eq)]
 pub enum Element {
+    A,
     Article,
+    Aside,
     Body,
     Br,
     Em,
@@ -41,6 +43,7 @@ pub enum Element {
     Section,
     Span,
     Strong,
+    Sup,
     Table,
     Td,
     Th,
@@ -51,7 +54,9 @@ pub enum Element {
 impl Element {
     fn from_tag(tag: &str) -> Option<Self> {
         match tag {
+            "a" => Some(Element::A),
             "article" => Some(Element::Article),
+            "aside" => Some(Element::Aside),
             "body" => Some(Element::Body),
             "br" => Some(Element::Br),
             "i" | "em" => Some(Element::Em),
@@ -66,6 +71,7 @@ impl Element {
             "section" => Some(Element::Section),
             "span" => Some(Element::Span),
             "b" | "strong" => Some(Element::Strong),
+            "sup" => Some(Element::Sup),
             "table" => Some(Element::Table),
             "td" => Some(Element::Td),
             "th" => Some(Element::Th),
@@ -80,7 +86,9 @@ impl Element {
             None,
             Namespace::from(XHTML_NAMESPACE),
             match self {
+                Element::A => LocalName::from("a"),
                 Element::Article => LocalName::from("article"),
+                Element::Aside => LocalName::from("aside"),
                 Element::Body => LocalName::from("body"),
                 Element::Br => LocalName::from("br"),
                 Element::Em => LocalName::from("em"),
@@ -95,6 +103,7 @@ impl Element {
                 Element::Section => LocalName::from("section"),
                 Element::Span => LocalName::from("span"),
                 Element::Strong => LocalName::from("strong"),
+                Element::Sup => LocalName::from("sup"),
                 Element::Table => LocalName::from("table"),
                 Element::Td => LocalName::from("td"),
                 Element::Th => LocalName::from("th"),
@@ -263,6 +272,10 @@ impl XhtmlBuilder {
         Element::Img.append_to_with_attrs(&self.sink, node, &[("src", source)]);
     }
 
+    pub fn append_image_with_alt(&self, node: XhtmlNode, source: &str, alt: &str) {
+        Element::Img.append_to_with_attrs(&self.sink, node, &[("src", source), ("alt", alt)]);
+    }
+
     pub fn append_text(&self, node: XhtmlNode, text: String) {
         self.sink
             .append(&node.id, NodeOrText::AppendText(text.into()))
@@ -273,13 +286,14 @@ impl XhtmlBuilder {
     }
 
     pub fn build(self) -> String {
-        // purge empty paragraphs
+        // purge empty paragraphs (image-only paragraphs are kept)
         let mut ids_to_purge = Vec::new();
         {
             let html = self.sink.0.borrow();
+            let img = Selector::parse("img").unwrap();
             for p in html.select(&Selector::parse("p").unwrap()) {
                 let text = p.text().collect::<Vec<_>>().join("").trim().to_string();
-                if text.is_empty() {
+                if text.is_empty() && p.select(&img).next().is_none() {
                     ids_to_purge.push(p.id());
                 }
             }